@@ -251,7 +251,7 @@ fn create_bit_field(name: Ident, segment_size: Lit, bit_fields: &IORegister) ->
                 };
             }
 
-            pub fn register(&mut self, mem: &Rc<RefCell<Vec<u8>>>) {
+            pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>) {
                 self.memory = Some(mem.clone());
             }
 
@@ -325,7 +325,7 @@ fn create_multiple_bit_field(name: Ident, segment_size: Lit, bit_fields: &Multip
                 };
             }
 
-            pub fn register(&mut self, mem: &Rc<RefCell<Vec<u8>>>) {
+            pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>) {
                 self.memory = Some(mem.clone());
             }
 
@@ -0,0 +1,391 @@
+use crate::memory::{sound_registers::*, GbaMem, memory_map::MemoryMap};
+use crate::dma::DMAController;
+use super::{
+    square_channel::SquareChannel,
+    wave_channel::WaveChannel,
+    noise_channel::NoiseChannel,
+    direct_sound::FIFO_REFILL_LEVEL
+};
+use std::cell::RefCell;
+use std::rc::Rc;
+use serde::{Serialize, Deserialize};
+
+pub const CPU_FREQUENCY: u64 = 16_777_216;
+pub const DEFAULT_SAMPLE_RATE: u32 = 32_768;
+// the frame sequencer runs at 512 Hz
+pub const FRAME_SEQUENCER_CYCLES: i32 = 32_768;
+
+#[derive(Serialize, Deserialize)]
+pub struct APU {
+    pub square_channels: [SquareChannel; 2],
+    pub wave_channel: WaveChannel,
+    pub noise_channel: NoiseChannel,
+
+    pub sound_control_low: SoundControlLow,
+    pub sound_control_high: SoundControlHigh,
+    pub sound_control_x: SoundControlX,
+    pub sound_bias: SoundBias,
+
+    pub direct_sound_samples: [i8; 2],
+
+    pub frame_sequencer_step: u8,
+    pub frame_sequencer_cycles: i32,
+
+    pub sample_rate: u32,
+    pub sample_accumulator: u64,
+    #[serde(skip)]
+    pub samples: Vec<(i16, i16)>
+}
+
+impl APU {
+    pub fn new() -> APU {
+        APU {
+            square_channels: [SquareChannel::new(0), SquareChannel::new(1)],
+            wave_channel: WaveChannel::new(),
+            noise_channel: NoiseChannel::new(),
+
+            sound_control_low: SoundControlLow::new(),
+            sound_control_high: SoundControlHigh::new(),
+            sound_control_x: SoundControlX::new(),
+            sound_bias: SoundBias::new(),
+
+            direct_sound_samples: [0; 2],
+
+            frame_sequencer_step: 0,
+            frame_sequencer_cycles: FRAME_SEQUENCER_CYCLES,
+
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_accumulator: 0,
+            samples: Vec::new()
+        }
+    }
+
+    pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>) {
+        for i in 0..2 {
+            self.square_channels[i].register(mem);
+        }
+        self.wave_channel.register(mem);
+        self.noise_channel.register(mem);
+
+        self.sound_control_low.register(mem);
+        self.sound_control_high.register(mem);
+        self.sound_control_x.register(mem);
+        self.sound_bias.register(mem);
+    }
+
+    // a rate of 0 would never produce a sample, it's treated as 1
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate.max(1);
+        self.sample_accumulator = 0;
+    }
+
+    pub fn drain_samples(&mut self) -> Vec<(i16, i16)> {
+        std::mem::take(&mut self.samples)
+    }
+
+    fn enabled(&self) -> bool {
+        self.sound_control_x.get_psg_fifo_master_enable() != 0
+    }
+
+    pub fn step(&mut self, cycles: usize, mem_map: &mut MemoryMap, timer_overflows: &[usize; 4], dma_ctl: &mut DMAController) {
+        self.step_direct_sound(mem_map, timer_overflows, dma_ctl);
+
+        let mut remaining = cycles as u64;
+        let rate = self.sample_rate as u64;
+
+        while remaining > 0 {
+            // run up to the next output sample
+            let until_sample = (CPU_FREQUENCY - self.sample_accumulator).div_ceil(rate);
+            let chunk = remaining.min(until_sample.max(1));
+
+            self.step_channels(chunk as i32);
+
+            self.sample_accumulator += chunk * rate;
+            if self.sample_accumulator >= CPU_FREQUENCY {
+                self.sample_accumulator -= CPU_FREQUENCY;
+                let sample = self.mix();
+                self.samples.push(sample);
+            }
+            remaining -= chunk;
+        }
+    }
+
+    // each fifo plays one sample per overflow of the timer selected in SOUNDCNT_H
+    fn step_direct_sound(&mut self, mem_map: &mut MemoryMap, timer_overflows: &[usize; 4], dma_ctl: &mut DMAController) {
+        if self.sound_control_high.get_dma_sound_a_reset_fifo() != 0 {
+            self.sound_control_high.set_dma_sound_a_reset_fifo(0);
+            mem_map.sound_fifos[0].clear();
+        }
+
+        if self.sound_control_high.get_dma_sound_b_reset_fifo() != 0 {
+            self.sound_control_high.set_dma_sound_b_reset_fifo(0);
+            mem_map.sound_fifos[1].clear();
+        }
+
+        if !self.enabled() {
+            return;
+        }
+
+        let timer_selects = [
+            self.sound_control_high.get_dma_sound_a_timer_select() as usize,
+            self.sound_control_high.get_dma_sound_b_timer_select() as usize
+        ];
+
+        for fifo in 0..2 {
+            let overflows = timer_overflows[timer_selects[fifo]];
+            if overflows == 0 {
+                continue;
+            }
+
+            for _ in 0..overflows {
+                if let Some(sample) = mem_map.sound_fifos[fifo].pop() {
+                    self.direct_sound_samples[fifo] = sample;
+                }
+            }
+
+            if mem_map.sound_fifos[fifo].len() <= FIFO_REFILL_LEVEL {
+                dma_ctl.fifo_requests[fifo] = true;
+            }
+        }
+    }
+
+    fn step_channels(&mut self, cycles: i32) {
+        self.wave_channel.sync_wave_ram();
+
+        if !self.enabled() {
+            self.update_status();
+            return;
+        }
+
+        for i in 0..2 {
+            self.square_channels[i].check_trigger();
+        }
+        self.wave_channel.check_trigger();
+        self.noise_channel.check_trigger();
+
+        for i in 0..2 {
+            self.square_channels[i].step(cycles);
+        }
+        self.wave_channel.step(cycles);
+        self.noise_channel.step(cycles);
+
+        self.frame_sequencer_cycles -= cycles;
+        while self.frame_sequencer_cycles <= 0 {
+            self.frame_sequencer_cycles += FRAME_SEQUENCER_CYCLES;
+            self.clock_frame_sequencer();
+        }
+
+        self.update_status();
+    }
+
+    fn clock_frame_sequencer(&mut self) {
+        let step = self.frame_sequencer_step;
+
+        if step.is_multiple_of(2) {
+            for i in 0..2 {
+                self.square_channels[i].clock_length();
+            }
+            self.wave_channel.clock_length();
+            self.noise_channel.clock_length();
+        }
+
+        if step == 2 || step == 6 {
+            self.square_channels[0].clock_sweep();
+        }
+
+        if step == 7 {
+            for i in 0..2 {
+                self.square_channels[i].clock_envelope();
+            }
+            self.noise_channel.clock_envelope();
+        }
+
+        self.frame_sequencer_step = (step + 1) & 0x7;
+    }
+
+    fn update_status(&mut self) {
+        let enabled = self.enabled();
+        self.sound_control_x.set_sound_1_on_flag((enabled && self.square_channels[0].enabled) as u8);
+        self.sound_control_x.set_sound_2_on_flag((enabled && self.square_channels[1].enabled) as u8);
+        self.sound_control_x.set_sound_3_on_flag((enabled && self.wave_channel.enabled) as u8);
+        self.sound_control_x.set_sound_4_on_flag((enabled && self.noise_channel.enabled) as u8);
+    }
+
+    // returns the (left, right) psg output before the master sound volume is applied
+    fn mix_psg(&self) -> (i32, i32) {
+        let channel_samples = [
+            self.square_channels[0].sample() as i32,
+            self.square_channels[1].sample() as i32,
+            self.wave_channel.sample() as i32,
+            self.noise_channel.sample() as i32
+        ];
+
+        let enable_right = self.sound_control_low.get_sound_enable_flags_right();
+        let enable_left = self.sound_control_low.get_sound_enable_flags_left();
+
+        let mut left = 0;
+        let mut right = 0;
+        for (i, sample) in channel_samples.iter().enumerate() {
+            if (enable_left >> i) & 1 != 0 {
+                left += sample;
+            }
+            if (enable_right >> i) & 1 != 0 {
+                right += sample;
+            }
+        }
+
+        left *= self.sound_control_low.get_sound_master_volume_left() as i32 + 1;
+        right *= self.sound_control_low.get_sound_master_volume_right() as i32 + 1;
+
+        let shift = match self.sound_control_high.get_sound_volume() {
+            0 => 4,
+            1 => 3,
+            _ => 2
+        };
+
+        (left >> shift, right >> shift)
+    }
+
+    fn mix_direct_sound(&self) -> (i32, i32) {
+        let channels = [
+            (
+                self.sound_control_high.get_dma_sound_a_volume(),
+                self.sound_control_high.get_dma_sound_a_enable_left(),
+                self.sound_control_high.get_dma_sound_a_enable_right()
+            ),
+            (
+                self.sound_control_high.get_dma_sound_b_volume(),
+                self.sound_control_high.get_dma_sound_b_enable_left(),
+                self.sound_control_high.get_dma_sound_b_enable_right()
+            )
+        ];
+
+        let mut left = 0;
+        let mut right = 0;
+        for (fifo, (full_volume, enable_left, enable_right)) in channels.iter().enumerate() {
+            // 100% volume is sample * 4, 50% is sample * 2
+            let sample = (self.direct_sound_samples[fifo] as i32) << (1 + *full_volume as i32);
+            if *enable_left != 0 {
+                left += sample;
+            }
+            if *enable_right != 0 {
+                right += sample;
+            }
+        }
+
+        (left, right)
+    }
+
+    // mixes into the 10 bit range of the sound dac, then converts that to a signed 16 bit host sample
+    fn mix(&self) -> (i16, i16) {
+        if !self.enabled() {
+            return (0, 0);
+        }
+
+        let (psg_left, psg_right) = self.mix_psg();
+        let (fifo_left, fifo_right) = self.mix_direct_sound();
+        let (left, right) = (psg_left + fifo_left, psg_right + fifo_right);
+        let bias = (self.sound_bias.get_bias_level() as i32) << 1;
+
+        (APU::to_host_sample(left, bias), APU::to_host_sample(right, bias))
+    }
+
+    fn to_host_sample(sample: i32, bias: i32) -> i16 {
+        let dac = (sample + bias).clamp(0, 0x3FF);
+        ((dac - bias) << 6).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    }
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        APU::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gba::GBA;
+
+    fn step_apu(gba: &mut GBA, cycles: usize) {
+        gba.apu.step(cycles, &mut gba.memory_bus.mem_map, &[0; 4], &mut gba.dma_control);
+    }
+
+    #[test]
+    fn sample_rate_controls_sample_count() {
+        let mut gba: GBA = GBA::default();
+        gba.set_sample_rate(32_768);
+        step_apu(&mut gba, 16_777_216 / 64);
+        assert_eq!(gba.drain_samples().len(), 512);
+        assert!(gba.drain_samples().is_empty());
+
+        gba.set_sample_rate(44_100);
+        step_apu(&mut gba, 16_777_216);
+        assert_eq!(gba.drain_samples().len(), 44_100);
+
+        gba.set_sample_rate(0);
+        step_apu(&mut gba, 16_777_216);
+        assert_eq!(gba.drain_samples().len(), 1);
+    }
+
+    #[test]
+    fn disabled_apu_outputs_silence() {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.write_u16(0x4000062, 0xF080);
+        gba.memory_bus.write_u16(0x4000064, 0x8700);
+        step_apu(&mut gba, 4096);
+
+        assert!(gba.drain_samples().iter().all(|sample| *sample == (0, 0)));
+    }
+
+    #[test]
+    fn square_channel_is_mixed_to_enabled_sides() {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.write_u16(0x4000084, 0x0080);
+        gba.memory_bus.write_u16(0x4000088, 0x0200);
+        // channel 1 on the left only at full master volume, 100% psg volume
+        gba.memory_bus.write_u16(0x4000080, 0x1077);
+        gba.memory_bus.write_u16(0x4000082, 0x0002);
+        gba.memory_bus.write_u16(0x4000062, 0xF080);
+        gba.memory_bus.write_u16(0x4000064, 0x8700);
+        step_apu(&mut gba, 16_777_216 / 256);
+
+        let samples = gba.drain_samples();
+        assert!(samples.iter().any(|(left, _)| *left != 0));
+        assert!(samples.iter().all(|(_, right)| *right == 0));
+        assert_eq!(gba.memory_bus.read_u16(0x4000084) & 0x1, 1);
+    }
+
+    #[test]
+    fn timer_overflow_plays_fifo_and_requests_dma() {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.write_u16(0x4000084, 0x0080);
+        // fifo a at 100% on both sides driven by timer 0
+        gba.memory_bus.write_u16(0x4000082, 0x0304);
+        for _ in 0..5 {
+            gba.memory_bus.write_u32(0x40000A0, 0x7F7F_7F40);
+        }
+
+        gba.apu.step(1, &mut gba.memory_bus.mem_map, &[1, 0, 0, 0], &mut gba.dma_control);
+        assert_eq!(gba.apu.direct_sound_samples[0], 0x40);
+        assert!(!gba.dma_control.fifo_requests[0]);
+
+        gba.apu.step(1, &mut gba.memory_bus.mem_map, &[0, 3, 0, 0], &mut gba.dma_control);
+        assert_eq!(gba.apu.direct_sound_samples[0], 0x40);
+
+        gba.apu.step(1, &mut gba.memory_bus.mem_map, &[3, 0, 0, 0], &mut gba.dma_control);
+        assert_eq!(gba.apu.direct_sound_samples[0], 0x7F);
+        assert_eq!(gba.memory_bus.mem_map.sound_fifos[0].len(), 16);
+        assert!(gba.dma_control.fifo_requests[0]);
+    }
+
+    #[test]
+    fn reset_bit_clears_fifo() {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.write_u32(0x40000A4, 0x0102_0304);
+        gba.memory_bus.write_u16(0x4000082, 0x8000);
+        step_apu(&mut gba, 1);
+
+        assert!(gba.memory_bus.mem_map.sound_fifos[1].is_empty());
+        assert_eq!(gba.apu.sound_control_high.get_dma_sound_b_reset_fifo(), 0);
+    }
+}
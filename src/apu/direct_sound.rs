@@ -0,0 +1,140 @@
+use crate::memory::memory_map::MemoryMap;
+use serde::{Serialize, Deserialize};
+
+pub const FIFO_A_ADDRESS: u32 = 0x40000A0;
+pub const FIFO_B_ADDRESS: u32 = 0x40000A4;
+pub const FIFO_SIZE: usize = 32;
+// a dma refill is requested once the fifo is half empty
+pub const FIFO_REFILL_LEVEL: usize = 16;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SoundFifo {
+    buffer: [i8; FIFO_SIZE],
+    read_index: usize,
+    length: usize
+}
+
+impl SoundFifo {
+    pub fn new() -> SoundFifo {
+        SoundFifo {
+            buffer: [0; FIFO_SIZE],
+            read_index: 0,
+            length: 0
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn push(&mut self, sample: i8) {
+        if self.length == FIFO_SIZE {
+            return;
+        }
+
+        self.buffer[(self.read_index + self.length) % FIFO_SIZE] = sample;
+        self.length += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<i8> {
+        if self.length == 0 {
+            return None;
+        }
+
+        let sample = self.buffer[self.read_index];
+        self.read_index = (self.read_index + 1) % FIFO_SIZE;
+        self.length -= 1;
+        Some(sample)
+    }
+
+    pub fn clear(&mut self) {
+        self.read_index = 0;
+        self.length = 0;
+    }
+}
+
+impl Default for SoundFifo {
+    fn default() -> Self {
+        SoundFifo::new()
+    }
+}
+
+impl MemoryMap {
+    pub fn is_fifo_address(address: u32) -> bool {
+        (FIFO_A_ADDRESS..FIFO_B_ADDRESS + 4).contains(&address)
+    }
+
+    // bytes are pushed from the lowest address up, whatever the width of the write was
+    pub fn write_fifo(&mut self, address: u32, bytes: &[u8]) {
+        let fifo = if address < FIFO_B_ADDRESS { 0 } else { 1 };
+        for byte in bytes {
+            self.sound_fifos[fifo].push(*byte as i8);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::memory_bus::MemoryBus;
+    use crate::gba::GBA;
+
+    #[test]
+    fn fifo_drops_samples_when_full() {
+        let mut fifo = SoundFifo::new();
+        for i in 0..40 {
+            fifo.push(i as i8);
+        }
+        assert_eq!(fifo.len(), FIFO_SIZE);
+        assert_eq!(fifo.pop(), Some(0));
+        assert_eq!(fifo.len(), FIFO_SIZE - 1);
+    }
+
+    #[test]
+    fn word_writes_push_in_byte_order() {
+        let mut bus = MemoryBus::new_stub();
+        bus.write_u32(FIFO_A_ADDRESS, 0x0403_0201);
+        bus.write_u16(FIFO_B_ADDRESS + 2, 0x0605);
+
+        let fifo_a = &mut bus.mem_map.sound_fifos[0];
+        assert_eq!(fifo_a.len(), 4);
+        assert_eq!(fifo_a.pop(), Some(1));
+        assert_eq!(fifo_a.pop(), Some(2));
+
+        let fifo_b = &mut bus.mem_map.sound_fifos[1];
+        assert_eq!(fifo_b.pop(), Some(5));
+        assert_eq!(fifo_b.pop(), Some(6));
+        assert!(fifo_b.is_empty());
+    }
+
+    #[test]
+    fn dma_special_mode_refills_fifo() {
+        let mut gba: GBA = GBA::default();
+        for i in 0..8 {
+            gba.memory_bus.write_u32(0x0200_0000 + i * 4, 0x0101_0101 * (i + 1));
+        }
+
+        gba.memory_bus.write_u32(0x40000BC, 0x0200_0000);
+        gba.memory_bus.write_u32(0x40000C0, FIFO_A_ADDRESS);
+        // repeat, 32 bit, special timing, enable
+        gba.memory_bus.write_u16(0x40000C6, 0xB600);
+
+        gba.dma_control.update(&mut gba.memory_bus, &mut gba.interrupt_handler).unwrap();
+        assert!(gba.memory_bus.mem_map.sound_fifos[0].is_empty());
+
+        gba.dma_control.fifo_requests[0] = true;
+        gba.dma_control.update(&mut gba.memory_bus, &mut gba.interrupt_handler).unwrap();
+        assert_eq!(gba.memory_bus.mem_map.sound_fifos[0].len(), 16);
+        assert!(!gba.dma_control.fifo_requests[0]);
+        assert_eq!(gba.dma_control.dma_channels[1].internal_destination_address, FIFO_A_ADDRESS);
+
+        gba.dma_control.fifo_requests[0] = true;
+        gba.dma_control.update(&mut gba.memory_bus, &mut gba.interrupt_handler).unwrap();
+        assert_eq!(gba.memory_bus.mem_map.sound_fifos[0].len(), 32);
+        assert_eq!(gba.memory_bus.mem_map.sound_fifos[0].pop(), Some(1));
+    }
+}
@@ -0,0 +1,112 @@
+use serde::{Serialize, Deserialize};
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Envelope {
+    pub volume: u8,
+    pub step_time: u8,
+    pub increase: bool,
+    pub timer: u8
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            volume: 0,
+            step_time: 0,
+            increase: false,
+            timer: 0
+        }
+    }
+
+    pub fn trigger(&mut self, initial_volume: u8, step_time: u8, increase: bool) {
+        self.volume = initial_volume & 0xF;
+        self.step_time = step_time & 0x7;
+        self.increase = increase;
+        self.timer = self.step_time;
+    }
+
+    // clocked at 64 Hz by the frame sequencer
+    pub fn clock(&mut self) {
+        if self.step_time == 0 {
+            return;
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.step_time;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct LengthCounter {
+    pub counter: u16,
+    pub enabled: bool
+}
+
+impl LengthCounter {
+    pub fn new() -> LengthCounter {
+        LengthCounter {
+            counter: 0,
+            enabled: false
+        }
+    }
+
+    pub fn trigger(&mut self, max_length: u16, length: u16, enabled: bool) {
+        self.counter = max_length - (length % max_length);
+        self.enabled = enabled;
+    }
+
+    // clocked at 256 Hz by the frame sequencer, returns true when the channel should turn off
+    pub fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope_decreases_to_zero() {
+        let mut envelope = Envelope::new();
+        envelope.trigger(2, 1, false);
+        envelope.clock();
+        assert_eq!(envelope.volume, 1);
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(envelope.volume, 0);
+    }
+
+    #[test]
+    fn envelope_step_time_zero_holds_volume() {
+        let mut envelope = Envelope::new();
+        envelope.trigger(7, 0, true);
+        for _ in 0..10 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.volume, 7);
+    }
+
+    #[test]
+    fn length_counter_expires() {
+        let mut length = LengthCounter::new();
+        length.trigger(64, 62, true);
+        assert!(!length.clock());
+        assert!(length.clock());
+        assert!(!length.clock());
+    }
+}
@@ -0,0 +1,6 @@
+pub mod apu;
+pub mod envelope;
+pub mod square_channel;
+pub mod wave_channel;
+pub mod noise_channel;
+pub mod direct_sound;
@@ -0,0 +1,134 @@
+use crate::memory::{sound_registers::*, GbaMem};
+use super::envelope::{Envelope, LengthCounter};
+use std::cell::RefCell;
+use std::rc::Rc;
+use serde::{Serialize, Deserialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct NoiseChannel {
+    pub control_low: SoundChannelControlNoiseLow,
+    pub control_high: SoundChannelControlNoiseHigh,
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+    pub enabled: bool,
+    pub lfsr: u16,
+    pub timer: i32
+}
+
+impl NoiseChannel {
+    pub fn new() -> NoiseChannel {
+        NoiseChannel {
+            control_low: SoundChannelControlNoiseLow::new(),
+            control_high: SoundChannelControlNoiseHigh::new(),
+            envelope: Envelope::new(),
+            length: LengthCounter::new(),
+            enabled: false,
+            lfsr: 0x7FFF,
+            timer: 0
+        }
+    }
+
+    pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>) {
+        self.control_low.register(mem);
+        self.control_high.register(mem);
+    }
+
+    fn period(&self) -> i32 {
+        let ratio = self.control_high.get_dividing_ratio_of_frequencies() as i32;
+        let divisor = if ratio == 0 { 8 } else { 16 * ratio };
+        (divisor << self.control_high.get_shift_clock_frequency()) * 4
+    }
+
+    fn short_mode(&self) -> bool {
+        self.control_high.get_counter_step_width() != 0
+    }
+
+    pub fn check_trigger(&mut self) {
+        if self.control_high.get_initial() == 0 {
+            return;
+        }
+        self.control_high.set_initial(0);
+
+        self.enabled = true;
+        self.timer = self.period();
+        self.lfsr = if self.short_mode() { 0x7F } else { 0x7FFF };
+        self.length.trigger(64, self.control_low.get_sound_length() as u16, self.control_high.get_length_flag() != 0);
+        self.envelope.trigger(
+            self.control_low.get_initial_volume_of_envelope(),
+            self.control_low.get_envelope_step_time(),
+            self.control_low.get_envelope_direction() != 0
+        );
+
+        if self.control_low.get_initial_volume_of_envelope() == 0 && self.control_low.get_envelope_direction() == 0 {
+            self.enabled = false;
+        }
+    }
+
+    pub fn step(&mut self, cycles: i32) {
+        if !self.enabled {
+            return;
+        }
+
+        self.timer -= cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr >>= 1;
+            if self.short_mode() {
+                self.lfsr = (self.lfsr & !0x40) | (feedback << 6);
+            } else {
+                self.lfsr |= feedback << 14;
+            }
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn sample(&self) -> i16 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let volume = self.envelope.volume as i16;
+        if self.lfsr & 1 == 0 {
+            volume
+        } else {
+            -volume
+        }
+    }
+}
+
+impl Default for NoiseChannel {
+    fn default() -> Self {
+        NoiseChannel::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gba::GBA;
+
+    #[test]
+    fn short_mode_lfsr_repeats_every_127_steps() {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.write_u16(0x4000078, 0xF000);
+        gba.memory_bus.write_u16(0x400007C, 0x8008);
+
+        let channel = &mut gba.apu.noise_channel;
+        channel.check_trigger();
+        let start = channel.lfsr;
+        let period = channel.timer;
+        channel.step(period * 127);
+        assert_eq!(channel.lfsr, start);
+        channel.step(period);
+        assert_ne!(channel.lfsr, start);
+    }
+}
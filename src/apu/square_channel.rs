@@ -0,0 +1,227 @@
+use crate::memory::{sound_registers::*, GbaMem};
+use super::envelope::{Envelope, LengthCounter};
+use std::cell::RefCell;
+use std::rc::Rc;
+use serde::{Serialize, Deserialize};
+
+pub const DUTY_PATTERNS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],   // 12.5%
+    [1, 0, 0, 0, 0, 0, 0, 1],   // 25%
+    [1, 0, 0, 0, 0, 1, 1, 1],   // 50%
+    [0, 1, 1, 1, 1, 1, 1, 0],   // 75%
+];
+
+#[derive(Serialize, Deserialize)]
+pub struct SquareChannel {
+    pub sweep_control: SoundChannelControlSweep,
+    pub duty_length_envelope: SoundChannelControlDLE,
+    pub frequency_control: SoundChannelControlFC,
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+    pub enabled: bool,
+    pub duty_step: u8,
+    pub timer: i32,
+    pub sweep_enabled: bool,
+    pub sweep_timer: u8,
+    pub shadow_frequency: u16,
+    pub id: usize
+}
+
+impl SquareChannel {
+    pub fn new(channel: usize) -> SquareChannel {
+        assert!(channel < 2);
+        SquareChannel {
+            sweep_control: SoundChannelControlSweep::new(),
+            duty_length_envelope: SoundChannelControlDLE::new(channel),
+            frequency_control: SoundChannelControlFC::new(channel),
+            envelope: Envelope::new(),
+            length: LengthCounter::new(),
+            enabled: false,
+            duty_step: 0,
+            timer: 0,
+            sweep_enabled: false,
+            sweep_timer: 0,
+            shadow_frequency: 0,
+            id: channel
+        }
+    }
+
+    pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>) {
+        self.sweep_control.register(mem);
+        self.duty_length_envelope.register(mem);
+        self.frequency_control.register(mem);
+    }
+
+    fn has_sweep(&self) -> bool {
+        self.id == 0
+    }
+
+    fn period(&self) -> i32 {
+        (2048 - (self.frequency_control.get_frequency() as i32)) * 16
+    }
+
+    // the initial bit is write only, so it gets cleared once it has been consumed
+    pub fn check_trigger(&mut self) {
+        if self.frequency_control.get_initial() == 0 {
+            return;
+        }
+        self.frequency_control.set_initial(0);
+
+        self.enabled = true;
+        self.timer = self.period();
+        self.length.trigger(64, self.duty_length_envelope.get_sound_length() as u16, self.frequency_control.get_length_flag() != 0);
+        self.envelope.trigger(
+            self.duty_length_envelope.get_initial_volume_of_envelope(),
+            self.duty_length_envelope.get_envelope_step_time(),
+            self.duty_length_envelope.get_envelope_direction() != 0
+        );
+
+        if self.has_sweep() {
+            self.shadow_frequency = self.frequency_control.get_frequency();
+            let sweep_time = self.sweep_control.get_sweep_time();
+            let sweep_shift = self.sweep_control.get_number_of_sweep_shift();
+            self.sweep_timer = if sweep_time == 0 { 8 } else { sweep_time };
+            self.sweep_enabled = sweep_time != 0 || sweep_shift != 0;
+            if sweep_shift != 0 && self.sweep_frequency() > 2047 {
+                self.enabled = false;
+            }
+        }
+
+        // the dac is off when the envelope starts at zero and is decreasing
+        if self.dac_off() {
+            self.enabled = false;
+        }
+    }
+
+    fn dac_off(&self) -> bool {
+        self.duty_length_envelope.get_initial_volume_of_envelope() == 0 && self.duty_length_envelope.get_envelope_direction() == 0
+    }
+
+    fn sweep_frequency(&self) -> u16 {
+        let delta = self.shadow_frequency >> self.sweep_control.get_number_of_sweep_shift();
+        if self.sweep_control.get_sweep_frequency_direction() != 0 {
+            self.shadow_frequency.wrapping_sub(delta)
+        } else {
+            self.shadow_frequency + delta
+        }
+    }
+
+    pub fn step(&mut self, cycles: i32) {
+        if !self.enabled {
+            return;
+        }
+
+        self.timer -= cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            self.duty_step = (self.duty_step + 1) & 0x7;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    // clocked at 128 Hz by the frame sequencer, only channel 1 has a sweep unit
+    pub fn clock_sweep(&mut self) {
+        if !self.has_sweep() || !self.sweep_enabled {
+            return;
+        }
+
+        if self.sweep_timer > 0 {
+            self.sweep_timer -= 1;
+        }
+
+        if self.sweep_timer != 0 {
+            return;
+        }
+
+        let sweep_time = self.sweep_control.get_sweep_time();
+        self.sweep_timer = if sweep_time == 0 { 8 } else { sweep_time };
+        if sweep_time == 0 {
+            return;
+        }
+
+        let new_frequency = self.sweep_frequency();
+        if new_frequency > 2047 {
+            self.enabled = false;
+        } else if self.sweep_control.get_number_of_sweep_shift() != 0 {
+            self.shadow_frequency = new_frequency;
+            self.frequency_control.set_frequency(new_frequency);
+            if self.sweep_frequency() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    pub fn sample(&self) -> i16 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let duty = self.duty_length_envelope.get_wave_pattern_duty() as usize;
+        let volume = self.envelope.volume as i16;
+        if DUTY_PATTERNS[duty][self.duty_step as usize] != 0 {
+            volume
+        } else {
+            -volume
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gba::GBA;
+
+    #[test]
+    fn trigger_enables_channel_and_clears_initial() {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.write_u16(0x4000062, 0xF080);
+        gba.memory_bus.write_u16(0x4000064, 0x8700);
+
+        let channel = &mut gba.apu.square_channels[0];
+        channel.check_trigger();
+
+        assert!(channel.enabled);
+        assert_eq!(channel.envelope.volume, 0xF);
+        assert_eq!(channel.frequency_control.get_initial(), 0);
+        assert_eq!(channel.frequency_control.get_frequency(), 0x700);
+    }
+
+    #[test]
+    fn sweep_overflow_disables_channel() {
+        let mut gba: GBA = GBA::default();
+        // sweep time 1, increase, shift 1
+        gba.memory_bus.write_u16(0x4000060, 0x0011);
+        gba.memory_bus.write_u16(0x4000062, 0xF000);
+        gba.memory_bus.write_u16(0x4000064, 0x8000 | 0x500);
+
+        let channel = &mut gba.apu.square_channels[0];
+        channel.check_trigger();
+        assert!(channel.enabled);
+
+        channel.clock_sweep();
+        assert_eq!(channel.frequency_control.get_frequency(), 0x500 + (0x500 >> 1));
+        channel.clock_sweep();
+        assert!(!channel.enabled);
+    }
+
+    #[test]
+    fn length_counter_turns_off_channel() {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.write_u16(0x4000068, 0xF03F);
+        gba.memory_bus.write_u16(0x400006C, 0xC000);
+
+        let channel = &mut gba.apu.square_channels[1];
+        channel.check_trigger();
+        assert!(channel.enabled);
+        channel.clock_length();
+        assert!(!channel.enabled);
+    }
+}
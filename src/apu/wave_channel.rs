@@ -0,0 +1,180 @@
+use crate::memory::{sound_registers::*, GbaMem};
+use super::envelope::LengthCounter;
+use std::cell::RefCell;
+use std::rc::Rc;
+use serde::{Serialize, Deserialize};
+
+pub const WAVE_BANK_SIZE: usize = 16;
+
+#[derive(Serialize, Deserialize)]
+pub struct WaveChannel {
+    pub control_low: SoundChannelControlWaveLow,
+    pub control_high: SoundChannelControlWaveHigh,
+    pub control_x: SoundChannelControlWaveX,
+    pub wave_ram: [WaveRam; 4],
+    pub banks: [[u8; WAVE_BANK_SIZE]; 2],
+    pub selected_bank: u8,
+    pub length: LengthCounter,
+    pub enabled: bool,
+    pub position: u8,
+    pub timer: i32
+}
+
+impl WaveChannel {
+    pub fn new() -> WaveChannel {
+        WaveChannel {
+            control_low: SoundChannelControlWaveLow::new(),
+            control_high: SoundChannelControlWaveHigh::new(),
+            control_x: SoundChannelControlWaveX::new(),
+            wave_ram: [WaveRam::new(0), WaveRam::new(1), WaveRam::new(2), WaveRam::new(3)],
+            banks: [[0; WAVE_BANK_SIZE]; 2],
+            selected_bank: 0,
+            length: LengthCounter::new(),
+            enabled: false,
+            position: 0,
+            timer: 0
+        }
+    }
+
+    pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>) {
+        self.control_low.register(mem);
+        self.control_high.register(mem);
+        self.control_x.register(mem);
+        for i in 0..4 {
+            self.wave_ram[i].register(mem);
+        }
+    }
+
+    fn period(&self) -> i32 {
+        (2048 - (self.control_x.get_sample_rate() as i32)) * 8
+    }
+
+    fn read_window(&self) -> [u8; WAVE_BANK_SIZE] {
+        let mut bank = [0u8; WAVE_BANK_SIZE];
+        for (i, register) in self.wave_ram.iter().enumerate() {
+            let value = register.get_register();
+            for byte in 0..4 {
+                bank[i * 4 + byte] = (value >> (byte * 8)) as u8;
+            }
+        }
+        bank
+    }
+
+    fn write_window(&self, bank: &[u8; WAVE_BANK_SIZE]) {
+        for (i, register) in self.wave_ram.iter().enumerate() {
+            let value = (0..4).fold(0u32, |acc, byte| acc | ((bank[i * 4 + byte] as u32) << (byte * 8)));
+            register.set_register(value);
+        }
+    }
+
+    // The wave ram io window always maps to the bank that is not being played,
+    // so keep the internal banks in sync with it and swap when the game flips banks
+    pub fn sync_wave_ram(&mut self) {
+        let requested_bank = self.control_low.get_wave_ram_bank_number();
+        self.banks[(1 - self.selected_bank) as usize] = self.read_window();
+
+        if requested_bank != self.selected_bank {
+            self.selected_bank = requested_bank;
+            self.write_window(&self.banks[(1 - self.selected_bank) as usize]);
+        }
+    }
+
+    pub fn check_trigger(&mut self) {
+        if self.control_x.get_initial() == 0 {
+            return;
+        }
+        self.control_x.set_initial(0);
+
+        self.enabled = self.control_low.get_sound_channel_3_off() != 0;
+        self.position = 0;
+        self.timer = self.period();
+        self.length.trigger(256, self.control_high.get_sound_length() as u16, self.control_x.get_length_flag() != 0);
+    }
+
+    pub fn step(&mut self, cycles: i32) {
+        if self.control_low.get_sound_channel_3_off() == 0 {
+            self.enabled = false;
+        }
+
+        if !self.enabled {
+            return;
+        }
+
+        let samples = if self.control_low.get_wave_ram_dimension() != 0 { 64 } else { 32 };
+        self.timer -= cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            self.position = (self.position + 1) % samples;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn sample(&self) -> i16 {
+        if !self.enabled {
+            return 0;
+        }
+
+        // in 64 sample mode playback starts in the selected bank and continues into the other one
+        let bank = (self.selected_bank as usize + (self.position as usize / 32)) & 1;
+        let index = (self.position as usize % 32) / 2;
+        let byte = self.banks[bank][index];
+        let nibble = if self.position & 1 == 0 { byte >> 4 } else { byte & 0xF };
+        let centered = (nibble as i16) * 2 - 15;
+
+        if self.control_high.get_force_volume() != 0 {
+            return centered * 3 / 4;
+        }
+
+        match self.control_high.get_sound_volume() {
+            0 => 0,
+            1 => centered,
+            2 => centered / 2,
+            _ => centered / 4
+        }
+    }
+}
+
+impl Default for WaveChannel {
+    fn default() -> Self {
+        WaveChannel::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gba::GBA;
+
+    #[test]
+    fn wave_ram_window_swaps_with_bank() {
+        let mut gba: GBA = GBA::default();
+        // write bank 1 while bank 0 is selected for playback
+        gba.memory_bus.write_u32(0x4000090, 0xDEADBEEF);
+        gba.apu.wave_channel.sync_wave_ram();
+        assert_eq!(gba.apu.wave_channel.banks[1][0], 0xEF);
+
+        // select bank 1, the window should now show bank 0
+        gba.memory_bus.write_u16(0x4000070, 0x0040);
+        gba.apu.wave_channel.sync_wave_ram();
+        assert_eq!(gba.memory_bus.read_u32(0x4000090), 0);
+        assert_eq!(gba.apu.wave_channel.banks[1][3], 0xDE);
+    }
+
+    #[test]
+    fn wave_sample_uses_high_nibble_first() {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.write_u32(0x4000090, 0x000000F0);
+        gba.memory_bus.write_u16(0x4000070, 0x00C0);
+        gba.apu.wave_channel.sync_wave_ram();
+        gba.memory_bus.write_u16(0x4000072, 0x2000);
+        gba.memory_bus.write_u16(0x4000074, 0x8000);
+        gba.apu.wave_channel.check_trigger();
+
+        assert!(gba.apu.wave_channel.enabled);
+        assert_eq!(gba.apu.wave_channel.sample(), 15);
+    }
+}
@@ -92,7 +92,7 @@ impl Instruction for BlockDataTransfer {
     fn asm(&self) -> String {
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 3;}
+    fn cycles(&self, _cpu: &CPU) -> u32 { if self.load {1} else {0} } // LDM nS + 1N + 1I, STM (n-1)S + 2N
 }
 
 impl BlockDataTransfer {
@@ -147,9 +147,9 @@ impl BlockDataTransfer {
         if self.register_list.len() == 0 {
 
             if self.up {
-                mem_bus.write_u32(current_address as u32, cpu.get_register_override_opmode(15, current_operating_mode) + 8);
+                mem_bus.write_u32(current_address as u32, cpu.get_register_override_opmode(15, current_operating_mode) + 4);
             } else {
-                mem_bus.write_u32((current_address - 0x40) as u32, cpu.get_register_override_opmode(15, current_operating_mode) + 8);
+                mem_bus.write_u32((current_address - 0x40) as u32, cpu.get_register_override_opmode(15, current_operating_mode) + 4);
             }
 
             if write_back {
@@ -181,7 +181,7 @@ impl BlockDataTransfer {
                 // todo figure out write back with base in reg list
 
                 if *reg_num == 15 {
-                    mem_bus.write_u32(current_address as u32, cpu.get_register_override_opmode(*reg_num, current_operating_mode) + 8);
+                    mem_bus.write_u32(current_address as u32, cpu.get_register_override_opmode(*reg_num, current_operating_mode) + 4);
                 } else {
                     if *reg_num == self.base_register {
                         if *reg_num == self.register_list[0] {
@@ -45,7 +45,7 @@ impl fmt::Debug for Branch {
 impl Instruction for Branch {
     fn execute(&self, cpu: &mut CPU, _mem_bus: &mut MemoryBus) -> u32 {
         let current_pc = if cpu.get_instruction_set() == InstructionSet::Arm { ARM_PC } else { THUMB_PC };
-        let current_pc_value = cpu.get_register(current_pc) + 4; // because pipeline bullshit
+        let current_pc_value = cpu.get_register(current_pc);
         let mut offset = (self.offset << 2) as u32;
 
         if ((offset >> 25) & 0x1) != 0 {
@@ -69,6 +69,6 @@ impl Instruction for Branch {
     fn asm(&self) -> String {
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 3;}
+    fn cycles(&self, _cpu: &CPU) -> u32 { 0 } // 2S + 1N
 
 }
\ No newline at end of file
@@ -48,7 +48,7 @@ impl Instruction for BranchExchange {
     fn asm(&self) -> String {
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 3;}
+    fn cycles(&self, _cpu: &CPU) -> u32 { 0 } // 2S + 1N
 }
 
 // Unit Tests
@@ -134,7 +134,8 @@ impl DataProcessing {
         } else {
             let mut register_val;
             if self.operand2.rm == 15 { 
-                register_val = cpu.get_register(self.operand2.rm) + 4;
+                register_val = cpu.get_register(self.operand2.rm);
+                // the register shift takes an extra cycle, the pc has moved on by then
                 if !self.operand2.shift.immediate {
                     register_val += 4;
                 }
@@ -175,7 +176,6 @@ impl Instruction for DataProcessing {
         let (op2, carry_out) = self.barrel_shifter(cpu);
         let mut op1 = cpu.get_register(self.op1_register);
         if self.op1_register == 15 {
-            op1 += 4;
             if !self.operand2.immediate && !self.operand2.shift.immediate {
                 op1 += 4;
             }
@@ -391,7 +391,7 @@ impl Instruction for DataProcessing {
     fn asm(&self) -> String {
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 1;}
+    fn cycles(&self, _cpu: &CPU) -> u32 { if !self.operand2.immediate && !self.operand2.shift.immediate {1} else {0} } // 1S, + 1I for a register specified shift
 }
 
 
@@ -419,4 +419,19 @@ mod tests {
         assert_eq!(a.set_condition, true);
     }
 
+    #[test]
+    fn shift_by_pc_reads_pc_plus_12() {
+        use crate::gba::GBA;
+        use crate::cpu::cpu::ARM_PC;
+
+        let mut gba: GBA = GBA::default();
+        // mov r0, r1, lsl pc
+        gba.memory_bus.mem_map.write_block(0x0800_0000, &0xE1A0_0F11u32.to_le_bytes().to_vec());
+        gba.cpu.set_register(ARM_PC, 0x0800_0000);
+        gba.cpu.set_register(1, 1);
+
+        assert!(gba.try_single_step().is_ok());
+        assert_eq!(gba.cpu.get_register(0), 1 << 0x0C);
+    }
+
 }
\ No newline at end of file
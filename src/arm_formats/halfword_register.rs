@@ -166,7 +166,7 @@ impl Instruction for HalfwordRegisterOffset {
     fn asm(&self) -> String {
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 3;}
+    fn cycles(&self, _cpu: &CPU) -> u32 { if self.halfword_common.load {1} else {0} } // LDRH 1S + 1N + 1I, STRH 2N
 }
 
 impl From<u32> for HalfwordRegisterOffset {
@@ -192,7 +192,7 @@ impl Instruction for HalfwordImmediateOffset {
     fn asm(&self) -> String {
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 0;}
+    fn cycles(&self, _cpu: &CPU) -> u32 { if self.halfword_common.load {1} else {0} } // LDRH 1S + 1N + 1I, STRH 2N
 }
 
 impl From<u32> for HalfwordImmediateOffset {
@@ -1,4 +1,4 @@
-use crate::{operations::arm_arithmetic};
+use crate::{operations::arm_arithmetic, operations::timing::multiply_cycles};
 use crate::cpu::{cpu::CPU, condition::Condition};
 use crate::operations::instruction::Instruction;
 use crate::memory::memory_bus::MemoryBus;
@@ -59,7 +59,7 @@ impl Instruction for Multiply {
     fn asm(&self) -> String {
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 3;}
+    fn cycles(&self, cpu: &CPU) -> u32 { multiply_cycles(cpu.get_register(self.op2_register), true) + self.accumulate as u32 } // 1S + mI, MLA 1S + (m+1)I
 }
 
 // Unit Tests
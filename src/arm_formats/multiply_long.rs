@@ -1,4 +1,4 @@
-use crate::{operations::arm_arithmetic};
+use crate::{operations::arm_arithmetic, operations::timing::multiply_cycles};
 use crate::cpu::{cpu::CPU, condition::Condition};
 use crate::operations::instruction::Instruction;
 use crate::memory::memory_bus::MemoryBus;
@@ -78,7 +78,7 @@ impl Instruction for MultiplyLong {
     fn asm(&self) -> String {
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 3;}
+    fn cycles(&self, cpu: &CPU) -> u32 { multiply_cycles(cpu.get_register(self.op2_register), !self.unsigned) + 1 + self.accumulate as u32 } // 1S + (m+1)I, MLAL 1S + (m+2)I
 }
 
 // Unit Tests
@@ -109,12 +109,7 @@ impl From<u32> for SingleDataTransferOperand {
 impl Instruction for SingleDataTransfer {
     fn execute(&self, cpu: &mut CPU, mem_bus: &mut MemoryBus) -> u32{
         let address_with_offset;
-        let base;
-        if self.op1_register == 15 {
-            base = cpu.get_register(self.op1_register) + 4;
-        } else {
-            base = cpu.get_register(self.op1_register);
-        }
+        let base = cpu.get_register(self.op1_register);
         if !self.offset_is_register {
             address_with_offset = apply_offset(base, self.offset.immediate_value as u32, self.up_down, 0);
         } else {
@@ -140,7 +135,7 @@ impl Instruction for SingleDataTransfer {
         return format!("{:?}", self);
     }
 
-    fn cycles(&self) -> u32 {return 3;}
+    fn cycles(&self, _cpu: &CPU) -> u32 { if self.load {1} else {0} } // LDR 1S + 1N + 1I, STR 2N
 }
 
 #[cfg(test)]
@@ -1,6 +1,7 @@
-use crate::cpu::{cpu::CPU, cpu::InstructionSet, cpu::OperatingMode, cpu::ARM_PC, cpu::ARM_LR, condition::Condition};
+use crate::cpu::{cpu::CPU, cpu::Exception, condition::Condition};
 use crate::operations::instruction::Instruction;
 use crate::memory::memory_bus::MemoryBus;
+use crate::bios::hle;
 
 #[derive(Debug)]
 pub struct SoftwareInterrupt {
@@ -22,14 +23,11 @@ impl From<u32> for SoftwareInterrupt {
 impl Instruction for SoftwareInterrupt {
     fn execute(&self, cpu: &mut CPU, _mem_bus: &mut MemoryBus) -> u32 {
         // log::info!("{:?}", self);
-        let old_cpsr = cpu.cpsr;
-        let current_pc = cpu.get_register(ARM_PC);
-        cpu.set_instruction_set(InstructionSet::Arm);
-        cpu.set_operating_mode(OperatingMode::Supervisor);
-        cpu.cpsr.control_bits.irq_disable = true;
-        cpu.set_spsr(old_cpsr);
-        cpu.set_register(ARM_LR, current_pc); // set LR to the next instruction        
-        cpu.set_register(ARM_PC, 0x08);
+        if cpu.hle_bios && hle::handle_swi(self.comment_field_arm as u8, cpu, _mem_bus) {
+            return _mem_bus.cycle_clock.get_cycles();
+        }
+
+        cpu.take_exception(Exception::SoftwareInterrupt);
         _mem_bus.cycle_clock.get_cycles()
     }
 
@@ -37,5 +35,5 @@ impl Instruction for SoftwareInterrupt {
         return format!("{:?}", self);
     }
 
-    fn cycles(&self) -> u32 { return 3; }
+    fn cycles(&self, _cpu: &CPU) -> u32 { 0 } // 2S + 1N
 }
\ No newline at end of file
@@ -0,0 +1,197 @@
+use crate::memory::memory_bus::MemoryBus;
+
+// every compressed stream starts with a word holding the type in bits 4-7 and the decompressed size in bits 8-31
+fn read_header(mem_bus: &mut MemoryBus, source: u32) -> usize {
+    (mem_bus.read_u32(source) >> 8) as usize
+}
+
+pub fn lz77(mem_bus: &mut MemoryBus, source: u32) -> Vec<u8> {
+    let size = read_header(mem_bus, source);
+    let mut output: Vec<u8> = Vec::with_capacity(size);
+    let mut address = source.wrapping_add(4);
+
+    while output.len() < size {
+        let flags = mem_bus.read_u8(address);
+        address = address.wrapping_add(1);
+
+        for block in (0..8).rev() {
+            if output.len() >= size {
+                break;
+            }
+
+            if (flags >> block) & 1 == 0 {
+                output.push(mem_bus.read_u8(address));
+                address = address.wrapping_add(1);
+            } else {
+                let high = mem_bus.read_u8(address) as usize;
+                let low = mem_bus.read_u8(address.wrapping_add(1)) as usize;
+                address = address.wrapping_add(2);
+
+                let length = (high >> 4) + 3;
+                let displacement = (((high & 0xF) << 8) | low) + 1;
+                for _ in 0..length {
+                    // a bad displacement reads zeros instead of running off the front of the buffer
+                    let value = if displacement <= output.len() { output[output.len() - displacement] } else { 0 };
+                    output.push(value);
+                }
+            }
+        }
+    }
+
+    output.truncate(size);
+    output
+}
+
+pub fn run_length(mem_bus: &mut MemoryBus, source: u32) -> Vec<u8> {
+    let size = read_header(mem_bus, source);
+    let mut output: Vec<u8> = Vec::with_capacity(size);
+    let mut address = source.wrapping_add(4);
+
+    while output.len() < size {
+        let flag = mem_bus.read_u8(address);
+        address = address.wrapping_add(1);
+
+        if flag & 0x80 != 0 {
+            let value = mem_bus.read_u8(address);
+            address = address.wrapping_add(1);
+            for _ in 0..((flag & 0x7F) as usize + 3) {
+                output.push(value);
+            }
+        } else {
+            for _ in 0..((flag & 0x7F) as usize + 1) {
+                output.push(mem_bus.read_u8(address));
+                address = address.wrapping_add(1);
+            }
+        }
+    }
+
+    output.truncate(size);
+    output
+}
+
+pub fn huffman(mem_bus: &mut MemoryBus, source: u32) -> Vec<u8> {
+    let header = mem_bus.read_u32(source);
+    let size = (header >> 8) as usize;
+    let data_bits = match header & 0xF {
+        4 => 4,
+        _ => 8
+    };
+
+    let tree_size = mem_bus.read_u8(source.wrapping_add(4)) as u32;
+    let root = source.wrapping_add(5);
+    // the bitstream starts right after the tree, a bad tree size that wraps leaves no room for any nodes
+    let tree_end = source.wrapping_add(4).wrapping_add((tree_size + 1) * 2);
+    let mut address = Some(tree_end);
+
+    let mut output: Vec<u8> = Vec::with_capacity(size);
+    let mut unit: u32 = 0;
+    let mut unit_bits = 0;
+    let mut node_address = root;
+
+    // a malformed stream stops early with whatever was decoded so far
+    'decode: while output.len() < size {
+        let word_address = match address {
+            Some(word_address) => word_address,
+            None => break
+        };
+        let bitstream = mem_bus.read_u32(word_address);
+        address = word_address.checked_add(4);
+
+        for bit in (0..32).rev() {
+            // each node holds the offset to its children and flags for which children are leaves
+            let node = mem_bus.read_u8(node_address);
+            let direction = (bitstream >> bit) & 1;
+            let is_leaf = if direction == 0 { node & 0x80 != 0 } else { node & 0x40 != 0 };
+            node_address = match (node_address & !1).checked_add(((node & 0x3F) as u32) * 2 + 2 + direction) {
+                Some(child) if child >= root && child < tree_end => child,
+                _ => break 'decode
+            };
+
+            if is_leaf {
+                unit |= (mem_bus.read_u8(node_address) as u32 & ((1 << data_bits) - 1)) << unit_bits;
+                unit_bits += data_bits;
+                node_address = root;
+
+                if unit_bits == 32 {
+                    output.extend_from_slice(&unit.to_le_bytes());
+                    unit = 0;
+                    unit_bits = 0;
+                    if output.len() >= size {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    output.truncate(size);
+    output
+}
+
+// wram can take byte writes, vram has to be written 16 bits at a time
+pub fn write_output(mem_bus: &mut MemoryBus, destination: u32, output: &[u8], halfwords: bool) {
+    if halfwords {
+        for (offset, pair) in output.chunks(2).enumerate() {
+            let value = pair[0] as u16 | ((*pair.get(1).unwrap_or(&0) as u16) << 8);
+            mem_bus.write_u16(destination.wrapping_add((offset as u32) * 2), value);
+        }
+    } else {
+        for (offset, byte) in output.iter().enumerate() {
+            mem_bus.write_u8(destination.wrapping_add(offset as u32), *byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gba::GBA;
+
+    fn load(gba: &mut GBA, address: u32, bytes: &[u8]) {
+        for (offset, byte) in bytes.iter().enumerate() {
+            gba.memory_bus.write_u8(address + offset as u32, *byte);
+        }
+    }
+
+    #[test]
+    fn lz77_copies_back_references() {
+        let mut gba: GBA = GBA::default();
+        // "ABCABCABCD": 3 literals, a copy of 6 from 3 back, then a literal
+        load(&mut gba, 0x0200_0000, &[0x10, 10, 0, 0, 0x10, b'A', b'B', b'C', 0x30, 0x02, b'D']);
+        assert_eq!(lz77(&mut gba.memory_bus, 0x0200_0000), b"ABCABCABCD".to_vec());
+    }
+
+    #[test]
+    fn run_length_mixes_runs_and_literals() {
+        let mut gba: GBA = GBA::default();
+        load(&mut gba, 0x0200_0000, &[0x30, 7, 0, 0, 0x81, 0xEE, 0x02, 1, 2, 3]);
+        assert_eq!(run_length(&mut gba.memory_bus, 0x0200_0000), vec![0xEE, 0xEE, 0xEE, 0xEE, 1, 2, 3]);
+    }
+
+    #[test]
+    fn huffman_decodes_8_bit_symbols() {
+        let mut gba: GBA = GBA::default();
+        // root with two leaf children, 'A' for a 0 bit and 'B' for a 1 bit
+        load(&mut gba, 0x0200_0000, &[0x28, 4, 0, 0, 0x01, 0xC0, b'A', b'B']);
+        // bits 0 1 1 0 from the top of the word
+        load(&mut gba, 0x0200_0008, &0x6000_0000u32.to_le_bytes());
+        assert_eq!(huffman(&mut gba.memory_bus, 0x0200_0000), b"ABBA".to_vec());
+    }
+
+    #[test]
+    fn huffman_stops_on_a_malformed_tree() {
+        let mut gba: GBA = GBA::default();
+        // the root's children are far past the end of a one node tree
+        load(&mut gba, 0x0200_0000, &[0x28, 4, 0, 0, 0x00, 0x3F]);
+        assert!(huffman(&mut gba.memory_bus, 0x0200_0000).is_empty());
+    }
+
+    #[test]
+    fn streams_wrap_at_the_top_of_the_address_space() {
+        let mut gba: GBA = GBA::default();
+        // whatever open bus hands back, running off the end wraps instead of overflowing
+        lz77(&mut gba.memory_bus, 0xFFFF_FFFC);
+        run_length(&mut gba.memory_bus, 0xFFFF_FFFC);
+        write_output(&mut gba.memory_bus, 0xFFFF_FFFE, &[1, 2, 3, 4], true);
+    }
+}
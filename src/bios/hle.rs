@@ -0,0 +1,477 @@
+use crate::cpu::{cpu::CPU, cpu::InstructionSet, cpu::OperatingMode, cpu::ARM_PC, cpu::ARM_LR, cpu::ARM_SP, cpu::THUMB_PC};
+use crate::cpu::program_status_register::ProgramStatusRegister;
+use crate::memory::memory_bus::MemoryBus;
+use crate::memory::memory_map::HaltState;
+use super::decompress;
+use std::f64::consts::PI;
+
+pub const SOFT_RESET: u8 = 0x00;
+pub const REGISTER_RAM_RESET: u8 = 0x01;
+pub const HALT: u8 = 0x02;
+pub const STOP: u8 = 0x03;
+pub const INTR_WAIT: u8 = 0x04;
+pub const VBLANK_INTR_WAIT: u8 = 0x05;
+pub const DIV: u8 = 0x06;
+pub const DIV_ARM: u8 = 0x07;
+pub const SQRT: u8 = 0x08;
+pub const ARC_TAN: u8 = 0x09;
+pub const ARC_TAN2: u8 = 0x0A;
+pub const CPU_SET: u8 = 0x0B;
+pub const CPU_FAST_SET: u8 = 0x0C;
+pub const GET_BIOS_CHECKSUM: u8 = 0x0D;
+pub const BG_AFFINE_SET: u8 = 0x0E;
+pub const OBJ_AFFINE_SET: u8 = 0x0F;
+pub const LZ77_UNCOMP_WRAM: u8 = 0x11;
+pub const LZ77_UNCOMP_VRAM: u8 = 0x12;
+pub const HUFF_UNCOMP: u8 = 0x13;
+pub const RL_UNCOMP_WRAM: u8 = 0x14;
+pub const RL_UNCOMP_VRAM: u8 = 0x15;
+
+const BIOS_CHECKSUM: u32 = 0xBAAE_187F;
+const BIOS_IRQ_FLAGS: u32 = 0x0300_7FF8;
+const IME_ADDRESS: u32 = 0x0400_0208;
+
+// the irq handler from the real bios, saves the scratch registers and calls the handler at 0x03007FFC
+const IRQ_VECTOR: u32 = 0x18;
+const IRQ_HANDLER: [u32; 6] = [
+    0xE92D_500F, // stmfd sp!, {r0-r3, r12, lr}
+    0xE3A0_0301, // mov r0, #0x04000000
+    0xE28F_E000, // add lr, pc, #0
+    0xE510_F004, // ldr pc, [r0, #-4]
+    0xE8BD_500F, // ldmfd sp!, {r0-r3, r12, lr}
+    0xE25E_F004  // subs pc, lr, #4
+];
+
+pub fn has_bios_image(mem_bus: &MemoryBus) -> bool {
+    mem_bus.mem_map.read_block_raw(0, 0x20).iter().any(|byte| *byte != 0)
+}
+
+// Without a bios image nothing sits at the irq vector, so put the real handler there.
+pub fn install_irq_handler(mem_bus: &mut MemoryBus) {
+    if has_bios_image(mem_bus) {
+        return;
+    }
+
+    let bytes: Vec<u8> = IRQ_HANDLER.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect();
+    mem_bus.mem_map.write_block(IRQ_VECTOR, &bytes);
+}
+
+// Sets up the cpu the way the bios leaves it when it jumps to the cartridge.
+pub fn direct_boot(cpu: &mut CPU, mem_bus: &mut MemoryBus) {
+    cpu.set_instruction_set(InstructionSet::Arm);
+    for (mode, stack) in [
+        (OperatingMode::Supervisor, 0x0300_7FE0),
+        (OperatingMode::Interrupt, 0x0300_7FA0),
+        (OperatingMode::System, 0x0300_7F00)
+    ].iter() {
+        cpu.set_operating_mode(*mode);
+        cpu.set_register(ARM_SP, *stack);
+        cpu.set_register(ARM_LR, 0);
+    }
+
+    cpu.cpsr = ProgramStatusRegister::from(0x1F);
+    for register in 0..13 {
+        cpu.set_register(register, 0);
+    }
+    cpu.set_register(ARM_PC, 0x0800_0000);
+
+    // POSTFLG, set once the bios has finished booting
+    mem_bus.write_u8(0x0400_0300, 1);
+    install_irq_handler(mem_bus);
+}
+
+// Runs a bios call in place of the real bios, returns false if the call isn't emulated so
+// the caller can fall back to jumping to the swi vector.
+pub fn handle_swi(function: u8, cpu: &mut CPU, mem_bus: &mut MemoryBus) -> bool {
+    match function {
+        SOFT_RESET => soft_reset(cpu, mem_bus),
+        REGISTER_RAM_RESET => register_ram_reset(cpu.get_register(0), mem_bus),
+        HALT => mem_bus.mem_map.halt_state = HaltState::Halt,
+        STOP => mem_bus.mem_map.halt_state = HaltState::Stop,
+        INTR_WAIT => intr_wait(cpu, mem_bus),
+        VBLANK_INTR_WAIT => {
+            cpu.set_register(0, 1);
+            cpu.set_register(1, 1);
+            intr_wait(cpu, mem_bus);
+        },
+        DIV => {
+            let (numerator, denominator) = (cpu.get_register(0), cpu.get_register(1));
+            div(cpu, numerator as i32, denominator as i32);
+        },
+        DIV_ARM => {
+            let (denominator, numerator) = (cpu.get_register(0), cpu.get_register(1));
+            div(cpu, numerator as i32, denominator as i32);
+        },
+        SQRT => {
+            let value = cpu.get_register(0);
+            cpu.set_register(0, sqrt(value));
+        },
+        ARC_TAN => {
+            let value = arc_tan(cpu.get_register(0) as i16 as i32);
+            cpu.set_register(0, value as u32);
+        },
+        ARC_TAN2 => {
+            let value = arc_tan2(cpu.get_register(0) as i16 as i32, cpu.get_register(1) as i16 as i32);
+            cpu.set_register(0, value as u32);
+        },
+        CPU_SET => cpu_set(cpu.get_register(0), cpu.get_register(1), cpu.get_register(2), mem_bus),
+        CPU_FAST_SET => cpu_fast_set(cpu.get_register(0), cpu.get_register(1), cpu.get_register(2), mem_bus),
+        GET_BIOS_CHECKSUM => cpu.set_register(0, BIOS_CHECKSUM),
+        BG_AFFINE_SET => bg_affine_set(cpu.get_register(0), cpu.get_register(1), cpu.get_register(2), mem_bus),
+        OBJ_AFFINE_SET => obj_affine_set(cpu.get_register(0), cpu.get_register(1), cpu.get_register(2), cpu.get_register(3), mem_bus),
+        LZ77_UNCOMP_WRAM | LZ77_UNCOMP_VRAM => {
+            let output = decompress::lz77(mem_bus, cpu.get_register(0));
+            decompress::write_output(mem_bus, cpu.get_register(1), &output, function == LZ77_UNCOMP_VRAM);
+        },
+        HUFF_UNCOMP => {
+            let output = decompress::huffman(mem_bus, cpu.get_register(0));
+            decompress::write_output(mem_bus, cpu.get_register(1), &output, false);
+        },
+        RL_UNCOMP_WRAM | RL_UNCOMP_VRAM => {
+            let output = decompress::run_length(mem_bus, cpu.get_register(0));
+            decompress::write_output(mem_bus, cpu.get_register(1), &output, function == RL_UNCOMP_VRAM);
+        },
+        _ => {
+            log::info!("Bios call {:X} is not emulated", function);
+            return false;
+        }
+    }
+
+    true
+}
+
+fn soft_reset(cpu: &mut CPU, mem_bus: &mut MemoryBus) {
+    // the flag picking the return address lives in the area that gets cleared
+    let return_to_ram = mem_bus.read_u8(0x0300_7FFA) != 0;
+    mem_bus.mem_map.write_block(0x0300_7E00, &vec![0; 0x200]);
+
+    direct_boot(cpu, mem_bus);
+    let entry = if return_to_ram { 0x0200_0000 } else { 0x0800_0000 };
+    cpu.set_register(ARM_LR, entry);
+    cpu.set_register(ARM_PC, entry);
+}
+
+fn register_ram_reset(flags: u32, mem_bus: &mut MemoryBus) {
+    // forced blank stays on so the cleared vram doesn't show
+    mem_bus.write_u16(0x0400_0000, 0x80);
+
+    let regions: [(u32, usize); 5] = [
+        (0x0200_0000, 0x40000),
+        // the top of iwram holds the stacks and bios variables and is left alone
+        (0x0300_0000, 0x7E00),
+        (0x0500_0000, 0x400),
+        (0x0600_0000, 0x18000),
+        (0x0700_0000, 0x400)
+    ];
+    for (bit, (address, size)) in regions.iter().enumerate() {
+        if (flags >> bit) & 1 != 0 {
+            mem_bus.mem_map.write_block(*address, &vec![0; *size]);
+        }
+    }
+
+    if flags & 0x20 != 0 {
+        // serial registers
+        mem_bus.mem_map.write_block(0x0400_0120, &vec![0; 0x10]);
+        mem_bus.mem_map.write_block(0x0400_0134, &vec![0; 0x2C]);
+    }
+    if flags & 0x40 != 0 {
+        // sound registers and wave ram
+        mem_bus.mem_map.write_block(0x0400_0060, &vec![0; 0x50]);
+    }
+    if flags & 0x80 != 0 {
+        // everything else apart from DISPCNT and the keypad
+        mem_bus.mem_map.write_block(0x0400_0002, &vec![0; 0x5E]);
+        mem_bus.mem_map.write_block(0x0400_00B0, &vec![0; 0x70]);
+        mem_bus.mem_map.write_block(0x0400_0200, &vec![0; 0x10]);
+    }
+}
+
+fn intr_wait(cpu: &mut CPU, mem_bus: &mut MemoryBus) {
+    let discard_old = cpu.get_register(0) & 1 != 0;
+    let wanted = (cpu.get_register(1) & 0x3FFF) as u16;
+    mem_bus.write_u16(IME_ADDRESS, 1);
+
+    let mut flags = mem_bus.read_u16(BIOS_IRQ_FLAGS);
+    if discard_old {
+        flags &= !wanted;
+        mem_bus.write_u16(BIOS_IRQ_FLAGS, flags);
+    }
+
+    if flags & wanted != 0 {
+        mem_bus.write_u16(BIOS_IRQ_FLAGS, flags & !wanted);
+        return;
+    }
+
+    // halt and run the swi again once an interrupt has been handled, the game's handler is
+    // what sets the flags we are waiting on
+    cpu.set_register(0, 0);
+    mem_bus.mem_map.halt_state = HaltState::Halt;
+    if cpu.get_instruction_set() == InstructionSet::Arm {
+        cpu.set_register(ARM_PC, cpu.instruction_address);
+    } else {
+        cpu.set_register(THUMB_PC, cpu.instruction_address);
+    }
+}
+
+fn div(cpu: &mut CPU, numerator: i32, denominator: i32) {
+    if denominator == 0 {
+        // the real bios gets stuck in a loop here
+        log::info!("Bios division by zero");
+        cpu.set_register(0, if numerator < 0 { -1i32 as u32 } else { 1 });
+        cpu.set_register(1, numerator as u32);
+        cpu.set_register(3, 1);
+        return;
+    }
+
+    let quotient = numerator.wrapping_div(denominator);
+    cpu.set_register(0, quotient as u32);
+    cpu.set_register(1, numerator.wrapping_rem(denominator) as u32);
+    cpu.set_register(3, quotient.unsigned_abs());
+}
+
+pub fn sqrt(value: u32) -> u32 {
+    let mut result = (value as f64).sqrt() as u32;
+    // correct for rounding in the float estimate
+    while (result as u64) * (result as u64) > value as u64 {
+        result -= 1;
+    }
+    while ((result + 1) as u64) * ((result + 1) as u64) <= value as u64 {
+        result += 1;
+    }
+    result
+}
+
+// Same polynomial approximation the bios uses, the input is a 1.14 fixed point tangent.
+pub fn arc_tan(value: i32) -> i32 {
+    // the bios does this with 32 bit muls, large inputs wrap the same way
+    let a = (value.wrapping_mul(value) >> 14).wrapping_neg();
+    let mut b = (0xA9i32.wrapping_mul(a) >> 14).wrapping_add(0x390);
+    for constant in [0x91C, 0xFB6, 0x16AA, 0x2081, 0x3651, 0xA2F9].iter() {
+        b = (b.wrapping_mul(a) >> 14).wrapping_add(*constant);
+    }
+    value.wrapping_mul(b) >> 16
+}
+
+// Angle of the vector (x, y) where 0x10000 is a full turn.
+pub fn arc_tan2(x: i32, y: i32) -> u16 {
+    if y == 0 {
+        return if x >= 0 { 0 } else { 0x8000 };
+    }
+    if x == 0 {
+        return if y >= 0 { 0x4000 } else { 0xC000 };
+    }
+
+    let angle = if y >= 0 {
+        if x >= 0 && x >= y {
+            arc_tan((y << 14) / x)
+        } else if x < 0 && -x >= y {
+            arc_tan((y << 14) / x) + 0x8000
+        } else {
+            0x4000 - arc_tan((x << 14) / y)
+        }
+    } else if x <= 0 && -x > -y {
+        arc_tan((y << 14) / x) + 0x8000
+    } else if x > 0 && x >= -y {
+        arc_tan((y << 14) / x) + 0x10000
+    } else {
+        0xC000 - arc_tan((x << 14) / y)
+    };
+
+    angle as u16
+}
+
+fn cpu_set(source: u32, destination: u32, control: u32, mem_bus: &mut MemoryBus) {
+    let count = control & 0x1F_FFFF;
+    let fill = control & (1 << 24) != 0;
+
+    if control & (1 << 26) != 0 {
+        let (source, destination) = (source & !3, destination & !3);
+        let value = mem_bus.read_u32(source);
+        for i in 0..count {
+            let word = if fill { value } else { mem_bus.read_u32(source.wrapping_add(i * 4)) };
+            mem_bus.write_u32(destination.wrapping_add(i * 4), word);
+        }
+    } else {
+        let (source, destination) = (source & !1, destination & !1);
+        let value = mem_bus.read_u16(source);
+        for i in 0..count {
+            let halfword = if fill { value } else { mem_bus.read_u16(source.wrapping_add(i * 2)) };
+            mem_bus.write_u16(destination.wrapping_add(i * 2), halfword);
+        }
+    }
+}
+
+fn cpu_fast_set(source: u32, destination: u32, control: u32, mem_bus: &mut MemoryBus) {
+    // copies in blocks of 8 words, so the count gets rounded up
+    let count = ((control & 0x1F_FFFF) + 7) & !7;
+    let fill = control & (1 << 24) != 0;
+    cpu_set(source, destination, count | (1 << 26) | if fill { 1 << 24 } else { 0 }, mem_bus);
+}
+
+// the bios only uses the top 8 bits of the angle
+fn angle_to_radians(angle: u16) -> f64 {
+    ((angle >> 8) as f64) / 128.0 * PI
+}
+
+fn bg_affine_set(mut source: u32, mut destination: u32, count: u32, mem_bus: &mut MemoryBus) {
+    for _ in 0..count {
+        let origin_x = mem_bus.read_u32(source) as i32 as f64 / 256.0;
+        let origin_y = mem_bus.read_u32(source.wrapping_add(4)) as i32 as f64 / 256.0;
+        let center_x = mem_bus.read_u16(source.wrapping_add(8)) as i16 as f64;
+        let center_y = mem_bus.read_u16(source.wrapping_add(10)) as i16 as f64;
+        let scale_x = mem_bus.read_u16(source.wrapping_add(12)) as i16 as f64 / 256.0;
+        let scale_y = mem_bus.read_u16(source.wrapping_add(14)) as i16 as f64 / 256.0;
+        let theta = angle_to_radians(mem_bus.read_u16(source.wrapping_add(16)));
+        source = source.wrapping_add(20);
+
+        let pa = theta.cos() * scale_x;
+        let pb = -theta.sin() * scale_x;
+        let pc = theta.sin() * scale_y;
+        let pd = theta.cos() * scale_y;
+        let x = origin_x - (pa * center_x + pb * center_y);
+        let y = origin_y - (pc * center_x + pd * center_y);
+
+        mem_bus.write_u16(destination, (pa * 256.0) as i16 as u16);
+        mem_bus.write_u16(destination.wrapping_add(2), (pb * 256.0) as i16 as u16);
+        mem_bus.write_u16(destination.wrapping_add(4), (pc * 256.0) as i16 as u16);
+        mem_bus.write_u16(destination.wrapping_add(6), (pd * 256.0) as i16 as u16);
+        mem_bus.write_u32(destination.wrapping_add(8), (x * 256.0) as i32 as u32);
+        mem_bus.write_u32(destination.wrapping_add(12), (y * 256.0) as i32 as u32);
+        destination = destination.wrapping_add(16);
+    }
+}
+
+fn obj_affine_set(mut source: u32, mut destination: u32, count: u32, stride: u32, mem_bus: &mut MemoryBus) {
+    for _ in 0..count {
+        let scale_x = mem_bus.read_u16(source) as i16 as f64 / 256.0;
+        let scale_y = mem_bus.read_u16(source.wrapping_add(2)) as i16 as f64 / 256.0;
+        let theta = angle_to_radians(mem_bus.read_u16(source.wrapping_add(4)));
+        source = source.wrapping_add(8);
+
+        let parameters = [
+            theta.cos() * scale_x,
+            -theta.sin() * scale_x,
+            theta.sin() * scale_y,
+            theta.cos() * scale_y
+        ];
+        for parameter in parameters.iter() {
+            mem_bus.write_u16(destination, (parameter * 256.0) as i16 as u16);
+            destination = destination.wrapping_add(stride);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gba::GBA;
+    use crate::cpu::cpu::THUMB_PC;
+
+    fn hle_gba() -> GBA {
+        let mut gba: GBA = GBA::default();
+        gba.enable_hle_bios();
+        gba
+    }
+
+    #[test]
+    fn div_sets_quotient_remainder_and_abs() {
+        let mut gba = hle_gba();
+        gba.cpu.set_register(0, -7i32 as u32);
+        gba.cpu.set_register(1, 2);
+        assert!(handle_swi(DIV, &mut gba.cpu, &mut gba.memory_bus));
+        assert_eq!(gba.cpu.get_register(0) as i32, -3);
+        assert_eq!(gba.cpu.get_register(1) as i32, -1);
+        assert_eq!(gba.cpu.get_register(3), 3);
+    }
+
+    #[test]
+    fn math_calls() {
+        assert_eq!(sqrt(0xFFFF_FFFF), 0xFFFF);
+        assert_eq!(sqrt(99), 9);
+        assert_eq!(arc_tan2(0x100, 0), 0);
+        assert_eq!(arc_tan2(0, 0x100), 0x4000);
+        assert_eq!(arc_tan2(-0x100, 0), 0x8000);
+        // 45 degrees, the polynomial is off by a little
+        assert!((arc_tan2(0x100, 0x100) as i32 - 0x2000).abs() < 8);
+    }
+
+    #[test]
+    fn arc_tan_wraps_on_large_tangents() {
+        let mut gba = hle_gba();
+        for value in [0x7FFF, 0x8000].iter() {
+            gba.cpu.set_register(0, *value);
+            assert!(handle_swi(ARC_TAN, &mut gba.cpu, &mut gba.memory_bus));
+        }
+    }
+
+    #[test]
+    fn cpu_set_fill_and_copy() {
+        let mut gba = hle_gba();
+        gba.memory_bus.write_u32(0x0200_0000, 0x1234_5678);
+        cpu_set(0x0200_0000, 0x0300_0000, 4 | (1 << 24) | (1 << 26), &mut gba.memory_bus);
+        assert_eq!(gba.memory_bus.read_u32(0x0300_000C), 0x1234_5678);
+
+        cpu_fast_set(0x0300_0000, 0x0300_1000, 1, &mut gba.memory_bus);
+        assert_eq!(gba.memory_bus.read_u32(0x0300_100C), 0x1234_5678);
+        assert_eq!(gba.memory_bus.read_u32(0x0300_101C), 0);
+    }
+
+    #[test]
+    fn obj_affine_set_identity() {
+        let mut gba = hle_gba();
+        gba.memory_bus.write_u16(0x0200_0000, 0x100);
+        gba.memory_bus.write_u16(0x0200_0002, 0x200);
+        gba.memory_bus.write_u16(0x0200_0004, 0);
+        obj_affine_set(0x0200_0000, 0x0700_0006, 1, 8, &mut gba.memory_bus);
+        assert_eq!(gba.memory_bus.read_u16(0x0700_0006), 0x100);
+        assert_eq!(gba.memory_bus.read_u16(0x0700_000E), 0);
+        assert_eq!(gba.memory_bus.read_u16(0x0700_001E), 0x200);
+    }
+
+    #[test]
+    fn intr_wait_halts_until_flag_is_set() {
+        let mut gba = hle_gba();
+        gba.cpu.set_instruction_set(InstructionSet::Thumb);
+        gba.cpu.instruction_address = 0x0800_0100;
+        gba.cpu.set_register(THUMB_PC, 0x0800_0104);
+        gba.cpu.set_register(0, 1);
+        gba.cpu.set_register(1, 1);
+
+        handle_swi(VBLANK_INTR_WAIT, &mut gba.cpu, &mut gba.memory_bus);
+        assert_eq!(gba.memory_bus.mem_map.halt_state, HaltState::Halt);
+        assert_eq!(gba.cpu.get_register(THUMB_PC), 0x0800_0100);
+
+        // what the game's irq handler would do
+        gba.memory_bus.mem_map.halt_state = HaltState::Running;
+        gba.memory_bus.write_u16(BIOS_IRQ_FLAGS, 1);
+        gba.cpu.set_register(THUMB_PC, 0x0800_0102);
+        handle_swi(INTR_WAIT, &mut gba.cpu, &mut gba.memory_bus);
+        assert_eq!(gba.memory_bus.mem_map.halt_state, HaltState::Running);
+        assert_eq!(gba.memory_bus.read_u16(BIOS_IRQ_FLAGS), 0);
+    }
+
+    #[test]
+    fn swi_instruction_uses_hle() {
+        let mut gba = hle_gba();
+        gba.cpu.set_instruction_set(InstructionSet::Thumb);
+        gba.cpu.set_register(THUMB_PC, 0x0800_0000);
+        // swi 0x08, sqrt
+        gba.memory_bus.mem_map.write_block(0x0800_0000, &vec![0x08, 0xDF]);
+        gba.cpu.set_register(0, 144);
+        gba.cpu.fetch(&mut gba.memory_bus);
+        assert_eq!(gba.cpu.get_register(0), 12);
+        assert_eq!(gba.cpu.get_register(THUMB_PC), 0x0800_0002);
+        assert_eq!(gba.cpu.get_instruction_set(), InstructionSet::Thumb);
+    }
+
+    #[test]
+    fn direct_boot_without_bios() {
+        let mut gba: GBA = GBA::default();
+        gba.direct_boot();
+        assert_eq!(gba.cpu.get_operating_mode(), OperatingMode::System);
+        assert_eq!(gba.cpu.get_register(ARM_PC), 0x0800_0000);
+        assert_eq!(gba.cpu.get_register(ARM_SP), 0x0300_7F00);
+        assert_eq!(gba.memory_bus.read_u32(IRQ_VECTOR), IRQ_HANDLER[0]);
+        assert!(gba.cpu.hle_bios);
+    }
+}
@@ -0,0 +1,2 @@
+pub mod hle;
+pub mod decompress;
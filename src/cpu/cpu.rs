@@ -15,9 +15,14 @@ use super::{arm_instr::ARM_INSTRUCTIONS};
 use super::{thumb_instr::THUMB_INSTRUCTIONS};
 use super::{decode_error::DecodeError};
 use super::{condition::Condition};
+use super::tracer::{Tracer, TraceRecord};
+use super::decode_cache::DecodeCache;
+use super::pipeline::{Pipeline, Stage};
+use crate::memory::GbaMem;
 use crate::operations::instruction::Instruction;
-use std::borrow::{BorrowMut};
+use std::rc::Rc;
 use crate::memory::memory_bus::MemoryBus;
+use crate::gba::error::{GbaError, ErrorContext, Subsystem};
 use serde::{Serialize, Deserialize};
 
 
@@ -74,6 +79,31 @@ pub enum InstructionSet {
     Thumb
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Exception {
+    Undefined,
+    SoftwareInterrupt,
+    Irq
+}
+
+impl Exception {
+    pub fn vector(self) -> u32 {
+        match self {
+            Exception::Undefined => 0x04,
+            Exception::SoftwareInterrupt => 0x08,
+            Exception::Irq => 0x18
+        }
+    }
+
+    pub fn mode(self) -> OperatingMode {
+        match self {
+            Exception::Undefined => OperatingMode::Undefined,
+            Exception::SoftwareInterrupt => OperatingMode::Supervisor,
+            Exception::Irq => OperatingMode::Interrupt
+        }
+    }
+}
+
 #[repr(u8)]
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum InstructionFormat {
@@ -127,6 +157,15 @@ pub struct CPU {
     spsr: [ProgramStatusRegister; 7],
     pub cpsr: ProgramStatusRegister,
     pub last_instruction: String,
+    pub hle_bios: bool,
+    // address and encoding of the instruction being executed, for error reporting
+    pub instruction_address: u32,
+    pub instruction: u32,
+    #[serde(skip)]
+    pub tracer: Option<Tracer>,
+    #[serde(skip)]
+    pub decode_cache: DecodeCache,
+    pub pipeline: Pipeline,
 }
 
 impl CPU {
@@ -136,6 +175,12 @@ impl CPU {
             spsr: [ProgramStatusRegister::from(0); 7],
             cpsr: ProgramStatusRegister::from(0b011111),
             last_instruction: "".to_string(),
+            hle_bios: false,
+            instruction_address: 0,
+            instruction: 0,
+            tracer: None,
+            decode_cache: DecodeCache::new(),
+            pipeline: Pipeline::new(),
         };
     }
 
@@ -267,6 +312,22 @@ impl CPU {
         }
     }
 
+    // decodes through the cache when the instruction is in a code page, otherwise every time
+    fn decode_cached(&mut self, address: u32, instruction: u32) -> Result<Rc<dyn Instruction>, DecodeError> {
+        let page = match GbaMem::code_page(address) {
+            Some(page) => page,
+            None => return self.decode(instruction).map(Rc::from)
+        };
+        let instruction_set = self.get_instruction_set();
+
+        if let Some(instr) = self.decode_cache.get(page, address, instruction_set, instruction) {
+            return Ok(instr);
+        }
+        let instr: Rc<dyn Instruction> = Rc::from(self.decode(instruction)?);
+        self.decode_cache.insert(page, address, instruction_set, instruction, instr.clone());
+        Ok(instr)
+    }
+
     pub fn get_pc(&self) -> u32 {
         let current_pc = if self.get_instruction_set() == InstructionSet::Arm { ARM_PC } else { THUMB_PC };
         let pc_contents = self.get_register(current_pc);
@@ -274,57 +335,117 @@ impl CPU {
     }
 
     pub fn fetch(&mut self, bus: &mut MemoryBus) -> usize {
-        let current_pc = if self.get_instruction_set() == InstructionSet::Arm { ARM_PC } else { THUMB_PC };
-        let pc_contents = self.get_register(current_pc);
-        // log::debug!("PC: {:X}", pc_contents);
+        match self.try_fetch(bus) {
+            Ok(cycles) => cycles,
+            Err(e) => panic!("{}", e)
+        }
+    }
 
-        let instruction: u32 = if self.get_instruction_set() == InstructionSet::Arm { bus.read_u32(pc_contents) } else { bus.read_u16(pc_contents) as u32 };
+    pub fn try_fetch(&mut self, bus: &mut MemoryBus) -> Result<usize, GbaError> {
+        self.try_get_operating_mode()?;
 
-        if self.get_instruction_set() == InstructionSet::Arm { 
-            self.set_register(current_pc, pc_contents + ARM_WORD_SIZE as u32) 
-        } else { 
-            self.set_register(current_pc, pc_contents + THUMB_WORD_SIZE as u32) 
-        };
+        let thumb = self.get_instruction_set() == InstructionSet::Thumb;
+        let width = if thumb { THUMB_WORD_SIZE } else { ARM_WORD_SIZE } as u32;
+        let pc_contents = self.get_pc();
 
-        let condition = if self.get_instruction_set() == InstructionSet::Arm { Condition::from((instruction & 0xF000_0000) >> 28)} else {Condition::from(0x0)};//THUMB codes don't include conditions 
-        let check_condition = if self.get_instruction_set() == InstructionSet::Arm { self.check_condition(&condition) } else { true };//fine
-
-        let decode_result = self.decode(instruction);
-        let cycles: usize = match decode_result {
-            Ok(mut instr) => {
-                // info!("{:?}, {:?}, {:X}, {:X}, {:?}", self.get_operating_mode(), self.get_instruction_set(), pc_contents, instruction, instr.asm());
-                // info!("r0={:X} r1={:X} r2={:X} r3={:X} r4={:X} r5={:X} r6={:X} r7={:X} r8={:X} r9={:X} r10={:X} r11={:X} r12={:X} r13={:X} r14={:X} r15={:X}", 
-                //         self.get_register_unsafe(0), 
-                //         self.get_register_unsafe(1), 
-                //         self.get_register_unsafe(2), 
-                //         self.get_register_unsafe(3), 
-                //         self.get_register_unsafe(4), 
-                //         self.get_register_unsafe(5), 
-                //         self.get_register_unsafe(6), 
-                //         self.get_register_unsafe(7), 
-                //         self.get_register_unsafe(8), 
-                //         self.get_register_unsafe(9), 
-                //         self.get_register_unsafe(10), 
-                //         self.get_register_unsafe(11), 
-                //         self.get_register_unsafe(12), 
-                //         self.get_register_unsafe(13), 
-                //         self.get_register_unsafe(14), 
-                //         self.get_register_unsafe(15));
+        bus.mem_map.update_open_bus(pc_contents, thumb);
+        // after a flush the pipeline is refilled from the pc, otherwise only the fetch stage moves on
+        let current = match self.pipeline.take(pc_contents) {
+            Some(stage) => stage,
+            None => CPU::fetch_stage(bus, pc_contents, thumb)
+        };
+        let next = match self.pipeline.take(pc_contents.wrapping_add(width)) {
+            Some(stage) => stage,
+            None => CPU::fetch_stage(bus, pc_contents.wrapping_add(width), thumb)
+        };
+        self.pipeline.decode = Some(next);
+        self.pipeline.fetch = Some(CPU::fetch_stage(bus, pc_contents.wrapping_add(2 * width), thumb));
+        self.pipeline.flushed = false;
+
+        let instruction = current.opcode;
+        self.instruction_address = pc_contents;
+        self.instruction = instruction;
+        // r15 reads as the address of the instruction in the fetch stage while executing
+        self.registers[15] = pc_contents.wrapping_add(2 * width);
+
+        if let Some(mut tracer) = self.tracer.take() {
+            if tracer.filter.matches(pc_contents, self.get_instruction_set(), self.get_operating_mode()) {
+                tracer.record(TraceRecord::capture(self, pc_contents, instruction));
+            }
+            self.tracer = Some(tracer);
+        }
 
+        let condition = if !thumb { Condition::from((instruction & 0xF000_0000) >> 28)} else {Condition::from(0x0)};//THUMB codes don't include conditions 
+        let check_condition = if !thumb { self.check_condition(&condition) } else { true };//fine
 
+        // the fetch above is the 1S every instruction takes, everything else on the bus is added to
+        // the clock as it happens and the internal cycles come from the instruction
+        let decode_result = self.decode_cached(pc_contents, instruction);
+        let mut cycles = match decode_result {
+            Ok(instr) => {
                 if check_condition {
-                    let temp_cycles = (instr.borrow_mut() as &mut dyn Instruction).execute(self, bus);
-                    ((instr.borrow_mut() as &mut dyn Instruction).cycles() + temp_cycles) as usize
+                    let internal = instr.cycles(self);
+                    let cycles = instr.execute(self, bus);
+                    bus.cycle_clock.internal(internal);
+                    cycles
                 } else {
-                    1usize
+                    0
                 }
             },
             Err(e) => {
-                panic!("{:?}", e);
+                if check_condition {
+                    log::info!("Undefined instruction at {:X}: {}", pc_contents, e);
+                    self.take_undefined_exception();
+                    bus.cycle_clock.internal(1);
+                    0
+                } else {
+                    0
+                }
             }
         };
 
-        return cycles;
+        if self.pipeline.flushed {
+            // branches land on an instruction boundary of whatever state they left the cpu in
+            self.registers[15] &= if self.get_instruction_set() == InstructionSet::Thumb { !1 } else { !3 };
+            // refilling decode and fetch is the 1N + 1S a branch costs on top of its own fetch
+            let thumb = self.get_instruction_set() == InstructionSet::Thumb;
+            let width = if thumb { THUMB_WORD_SIZE } else { ARM_WORD_SIZE } as u32;
+            let pc = self.registers[15];
+            self.pipeline.decode = Some(CPU::fetch_stage(bus, pc, thumb));
+            self.pipeline.fetch = Some(CPU::fetch_stage(bus, pc.wrapping_add(width), thumb));
+        } else {
+            self.registers[15] = pc_contents.wrapping_add(width);
+        }
+        cycles += bus.cycle_clock.get_cycles();
+
+        Ok(cycles as usize)
+    }
+
+    fn fetch_stage(bus: &mut MemoryBus, address: u32, thumb: bool) -> Stage {
+        let opcode = if thumb { bus.fetch_u16(address) as u32 } else { bus.fetch_u32(address) };
+        Stage { address, opcode }
+    }
+
+    pub fn take_exception(&mut self, exception: Exception) {
+        let width = if self.get_instruction_set() == InstructionSet::Arm { ARM_WORD_SIZE } else { THUMB_WORD_SIZE } as u32;
+        let return_address = match exception {
+            // taken between instructions with the pc on the next one, handlers return with subs pc, lr, #4
+            Exception::Irq => self.get_pc().wrapping_add(4),
+            // raised while executing, the pc is two instructions ahead and lr gets the next one
+            Exception::Undefined | Exception::SoftwareInterrupt => self.get_pc().wrapping_sub(width)
+        };
+
+        let old_cpsr = self.cpsr;
+        self.set_instruction_set(InstructionSet::Arm);
+        self.set_operating_mode(exception.mode());
+        self.cpsr.control_bits.irq_disable = true;
+        self.set_spsr(old_cpsr);
+        self.set_register(ARM_LR, return_address);
+        self.set_register(ARM_PC, exception.vector());
+    }
+
+    pub fn take_undefined_exception(&mut self) {
+        self.take_exception(Exception::Undefined);
     }
 
     pub fn get_instruction_set(&self) -> InstructionSet {
@@ -342,19 +463,34 @@ impl CPU {
         }
     } 
 
+    pub fn operating_mode_from_bits(mode_bits: u8) -> Option<OperatingMode> {
+        match mode_bits {
+            0b10000 => Some(OperatingMode::User),
+            0b10001 => Some(OperatingMode::FastInterrupt),
+            0b10010 => Some(OperatingMode::Interrupt),
+            0b10011 => Some(OperatingMode::Supervisor),
+            0b10111 => Some(OperatingMode::Abort),
+            0b11011 => Some(OperatingMode::Undefined),
+            0b11111 => Some(OperatingMode::System),
+            _ => None
+        }
+    }
+
     pub fn get_operating_mode(&self) -> OperatingMode {
-        match self.cpsr.control_bits.mode_bits {
-            0b10000 => OperatingMode::User,
-            0b10001 => OperatingMode::FastInterrupt,
-            0b10010 => OperatingMode::Interrupt,
-            0b10011 => OperatingMode::Supervisor,
-            0b10111 => OperatingMode::Abort,
-            0b11011 => OperatingMode::Undefined,
-            0b11111 => OperatingMode::System,
-            _ => panic!("Mode bits set incorrectly {:b}", self.cpsr.control_bits.mode_bits)
+        match self.try_get_operating_mode() {
+            Ok(mode) => mode,
+            Err(e) => panic!("{}", e)
         }
     }
 
+    pub fn try_get_operating_mode(&self) -> Result<OperatingMode, GbaError> {
+        let mode_bits = self.cpsr.control_bits.mode_bits;
+        CPU::operating_mode_from_bits(mode_bits).ok_or_else(|| GbaError::InvalidMode {
+            mode_bits,
+            context: ErrorContext::from_cpu(self, Subsystem::Cpu)
+        })
+    }
+
     pub fn set_operating_mode(&mut self, mode: OperatingMode) {
         match mode {
             OperatingMode::User =>          self.cpsr.control_bits.mode_bits = 0b10000,
@@ -386,7 +522,7 @@ impl CPU {
 
     pub fn set_register(&mut self, reg_num: u8, value: u32) {
         CPU::check_reg_range(&reg_num, &self.get_instruction_set());
-        self.registers[REG_MAP[self.get_instruction_set() as usize][self.get_operating_mode() as usize][reg_num as usize]] = value;
+        self.write_register(REG_MAP[self.get_instruction_set() as usize][self.get_operating_mode() as usize][reg_num as usize], value);
     }
 
     pub fn get_register_override_opmode(&self, reg_num: u8, op_mode: OperatingMode) -> u32 {
@@ -396,7 +532,7 @@ impl CPU {
 
     pub fn set_register_override_opmode(&mut self, reg_num: u8, op_mode: OperatingMode, value: u32) {
         CPU::check_reg_range(&reg_num, &self.get_instruction_set());
-        self.registers[REG_MAP[self.get_instruction_set() as usize][op_mode as usize][reg_num as usize]] = value;
+        self.write_register(REG_MAP[self.get_instruction_set() as usize][op_mode as usize][reg_num as usize], value);
     }
     
     pub fn get_register_unsafe(&self, reg_num: u8) -> u32{
@@ -404,7 +540,15 @@ impl CPU {
     }
 
     pub fn set_register_unsafe(&mut self, reg_num: u8, value: u32){
-        self.registers[REG_MAP[InstructionSet::Arm as usize][self.get_operating_mode() as usize][reg_num as usize]] = value;
+        self.write_register(REG_MAP[InstructionSet::Arm as usize][self.get_operating_mode() as usize][reg_num as usize], value);
+    }
+
+    // a write to r15 is a branch, whatever was prefetched is thrown away
+    fn write_register(&mut self, index: usize, value: u32) {
+        self.registers[index] = value;
+        if index == 15 {
+            self.pipeline.flush();
+        }
     }
 
     pub fn check_condition(&self, cond: &Condition) -> bool {
@@ -424,20 +568,22 @@ impl CPU {
             Condition::GT => return !self.cpsr.flags.zero && (self.cpsr.flags.negative == self.cpsr.flags.signed_overflow),
             Condition::LE => return self.cpsr.flags.zero || (self.cpsr.flags.negative != self.cpsr.flags.signed_overflow),
             Condition::AL => return true,
-            Condition::Error => panic!("Condition::Error hit"),
+            // the NV condition, never executes on the arm7
+            Condition::Error => return false,
         }
     }
 
     pub fn get_spsr(&mut self) -> ProgramStatusRegister {
-        if self.get_operating_mode() == OperatingMode::User {
-            panic!("Invalid operating mode {:?}", self.get_operating_mode());
+        // user and system mode have no spsr, the arm7 gives back the cpsr
+        if self.get_operating_mode() == OperatingMode::User || self.get_operating_mode() == OperatingMode::System {
+            return self.cpsr;
         }
         return self.spsr[self.get_operating_mode() as usize];
     }
 
     pub fn set_spsr(&mut self, psr: ProgramStatusRegister) {
-        if self.get_operating_mode() == OperatingMode::User {
-            panic!("Invalid operating mode {:?}", self.get_operating_mode());
+        if self.get_operating_mode() == OperatingMode::User || self.get_operating_mode() == OperatingMode::System {
+            return;
         }
         self.spsr[self.get_operating_mode() as usize] = psr;
     }
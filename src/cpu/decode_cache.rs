@@ -0,0 +1,123 @@
+use super::cpu::InstructionSet;
+use crate::memory::gba_mem::CODE_PAGE_SHIFT;
+use crate::operations::instruction::Instruction;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const PAGE_SIZE: usize = 1 << CODE_PAGE_SHIFT;
+const PAGE_MASK: u32 = (PAGE_SIZE - 1) as u32;
+
+// the opcode is kept alongside, a hit needs it to match what was just fetched
+type Slot = Option<(u32, Rc<dyn Instruction>)>;
+
+struct CachedPage {
+    arm: Vec<Slot>,
+    thumb: Vec<Slot>
+}
+
+impl CachedPage {
+    fn new() -> CachedPage {
+        CachedPage {
+            arm: vec![None; PAGE_SIZE / 4],
+            thumb: vec![None; PAGE_SIZE / 2]
+        }
+    }
+
+    fn slot(&mut self, address: u32, instruction_set: InstructionSet) -> &mut Slot {
+        match instruction_set {
+            InstructionSet::Arm => &mut self.arm[((address & PAGE_MASK) >> 2) as usize],
+            InstructionSet::Thumb => &mut self.thumb[((address & PAGE_MASK) >> 1) as usize]
+        }
+    }
+}
+
+// Decoded instructions by code page and address. Decoding only depends on the opcode, so code
+// that was overwritten is caught by the opcode check and only its own slot gets decoded again.
+#[derive(Default)]
+pub struct DecodeCache {
+    pages: HashMap<usize, CachedPage>
+}
+
+impl DecodeCache {
+    pub fn new() -> DecodeCache {
+        DecodeCache::default()
+    }
+
+    pub fn get(&mut self, page: usize, address: u32, instruction_set: InstructionSet, opcode: u32) -> Option<Rc<dyn Instruction>> {
+        let cached = self.pages.get_mut(&page)?;
+        match cached.slot(address, instruction_set) {
+            Some((cached_opcode, instruction)) if *cached_opcode == opcode => Some(instruction.clone()),
+            _ => None
+        }
+    }
+
+    pub fn insert(&mut self, page: usize, address: u32, instruction_set: InstructionSet, opcode: u32, instruction: Rc<dyn Instruction>) {
+        let cached = self.pages.entry(page).or_insert_with(CachedPage::new);
+        *cached.slot(address, instruction_set) = Some((opcode, instruction));
+    }
+
+    pub fn clear(&mut self) {
+        self.pages.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.pages.values().map(|page| page.arm.iter().chain(page.thumb.iter()).filter(|slot| slot.is_some()).count()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gba::GBA;
+    use crate::gba::test_programs::loop_gba;
+    use crate::cpu::cpu::ARM_PC;
+
+    #[test]
+    fn loops_decode_once() {
+        let mut gba = loop_gba(0x0300_0000);
+        for _ in 0..10 {
+            gba.single_step();
+        }
+        assert_eq!(gba.cpu.get_register(0), 5);
+        assert_eq!(gba.cpu.decode_cache.len(), 2);
+    }
+
+    #[test]
+    fn writes_invalidate_cached_code() {
+        let mut gba = loop_gba(0x0300_0000);
+        gba.single_step();
+
+        // add r0, r0, #2 through a mirror of the page, the branch refetches it
+        gba.memory_bus.mem_map.write_u32(0x0300_8000, 0xE280_0002);
+        gba.single_step();
+        gba.single_step();
+        assert_eq!(gba.cpu.get_register(0), 3);
+        assert_eq!(gba.cpu.decode_cache.len(), 2);
+    }
+
+    #[test]
+    fn data_writes_keep_the_page() {
+        let mut gba = loop_gba(0x0300_0000);
+        gba.single_step();
+        gba.single_step();
+
+        // a store next to the code doesn't throw away what was already decoded
+        gba.memory_bus.mem_map.write_u32(0x0300_0100, 0x1234_5678);
+        assert_eq!(gba.cpu.decode_cache.len(), 2);
+        gba.single_step();
+        assert_eq!(gba.cpu.decode_cache.len(), 2);
+    }
+
+    #[test]
+    fn uncached_regions_still_run() {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.mem_map.write_u32(0x0600_0000, 0xE280_0001);
+        gba.cpu.set_register(ARM_PC, 0x0600_0000);
+        gba.single_step();
+        assert_eq!(gba.cpu.get_register(0), 1);
+        assert!(gba.cpu.decode_cache.is_empty());
+    }
+}
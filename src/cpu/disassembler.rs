@@ -0,0 +1,503 @@
+use super::cpu::{InstructionFormat, InstructionSet, ThumbInstructionFormat};
+use super::arm_instr::ARM_INSTRUCTIONS;
+use super::thumb_instr::THUMB_INSTRUCTIONS;
+use super::condition::Condition;
+use crate::operations::shift::{Shift, ShiftType};
+
+// Turns an encoding into GNU assembler syntax. Nothing is executed, `address` is only used to
+// resolve pc relative targets. In thumb state a BL pair can be passed as one word, first half
+// in the low 16 bits, and comes out as a single bl.
+pub fn disassemble(address: u32, instruction: u32, instruction_set: InstructionSet) -> String {
+    match instruction_set {
+        InstructionSet::Arm => disassemble_arm(address, instruction),
+        InstructionSet::Thumb => disassemble_thumb(address, instruction)
+    }
+}
+
+const DATA_PROCESSING_OPS: [&str; 16] = [
+    "and", "eor", "sub", "rsb", "add", "adc", "sbc", "rsc",
+    "tst", "teq", "cmp", "cmn", "orr", "mov", "bic", "mvn"
+];
+
+const THUMB_ALU_OPS: [&str; 16] = [
+    "ands", "eors", "lsls", "lsrs", "asrs", "adcs", "sbcs", "rors",
+    "tst", "negs", "cmp", "cmn", "orrs", "muls", "bics", "mvns"
+];
+
+fn register(register: u32) -> String {
+    match register & 0xF {
+        13 => "sp".to_string(),
+        14 => "lr".to_string(),
+        15 => "pc".to_string(),
+        n => format!("r{}", n)
+    }
+}
+
+fn register_list(list: u32) -> String {
+    let registers: Vec<String> = (0..16).filter(|bit| list & (1 << bit) != 0).map(register).collect();
+    format!("{{{}}}", registers.join(", "))
+}
+
+fn condition(instruction: u32) -> String {
+    match Condition::from(instruction >> 28) {
+        Condition::Error => "nv".to_string(),
+        condition => format!("{:?}", condition).to_lowercase()
+    }
+}
+
+fn immediate(value: u32) -> String {
+    if value < 10 {
+        format!("#{}", value)
+    } else {
+        format!("#0x{:x}", value)
+    }
+}
+
+fn signed_immediate(up: bool, value: u32) -> String {
+    if up {
+        immediate(value)
+    } else {
+        format!("#-{}", &immediate(value)[1..])
+    }
+}
+
+fn sign_extend(value: u32, bits: u32) -> u32 {
+    let shift = 32 - bits;
+    (((value << shift) as i32) >> shift) as u32
+}
+
+fn shift_name(shift_type: ShiftType) -> &'static str {
+    match shift_type {
+        ShiftType::LogicalLeft => "lsl",
+        ShiftType::LogicalRight => "lsr",
+        ShiftType::ArithmeticRight => "asr",
+        _ => "ror"
+    }
+}
+
+// rm with the shift from bits 4-11, immediate shifts of 0 mean 32 for lsr/asr and rrx for ror
+fn shifted_register(instruction: u32) -> String {
+    let rm = register(instruction);
+    let shift = Shift::from(instruction);
+    let name = shift_name(shift.shift_type);
+
+    if !shift.immediate {
+        return format!("{}, {} {}", rm, name, register(shift.shift_register as u32));
+    }
+    match (shift.shift_type, shift.shift_amount) {
+        (ShiftType::LogicalLeft, 0) => rm,
+        (ShiftType::RotateRight, 0) => format!("{}, rrx", rm),
+        (_, 0) => format!("{}, {} #32", rm, name),
+        (_, amount) => format!("{}, {} #{}", rm, name, amount)
+    }
+}
+
+fn arm_immediate(instruction: u32) -> u32 {
+    (instruction & 0xFF).rotate_right(((instruction >> 8) & 0xF) * 2)
+}
+
+fn disassemble_arm(address: u32, instruction: u32) -> String {
+    let opcode = (((instruction >> 16) & 0xFF0) | ((instruction >> 4) & 0x0F)) as usize;
+    let cond = condition(instruction);
+
+    match ARM_INSTRUCTIONS[opcode] {
+        InstructionFormat::DataProcessing | InstructionFormat::PsrTransfer => data_processing(address, instruction, &cond),
+        InstructionFormat::Multiply => {
+            let set = if instruction & (1 << 20) != 0 { "s" } else { "" };
+            let (rd, rn, rs, rm) = (instruction >> 16, instruction >> 12, instruction >> 8, instruction);
+            if instruction & (1 << 21) != 0 {
+                format!("mla{}{} {}, {}, {}, {}", set, cond, register(rd), register(rm), register(rs), register(rn))
+            } else {
+                format!("mul{}{} {}, {}, {}", set, cond, register(rd), register(rm), register(rs))
+            }
+        },
+        InstructionFormat::MultiplyLong => {
+            let sign = if instruction & (1 << 22) != 0 { "s" } else { "u" };
+            let op = if instruction & (1 << 21) != 0 { "mlal" } else { "mull" };
+            let set = if instruction & (1 << 20) != 0 { "s" } else { "" };
+            let (rd_hi, rd_lo, rs, rm) = (instruction >> 16, instruction >> 12, instruction >> 8, instruction);
+            format!("{}{}{}{} {}, {}, {}, {}", sign, op, set, cond, register(rd_lo), register(rd_hi), register(rm), register(rs))
+        },
+        InstructionFormat::SingleDataSwap => {
+            let byte = if instruction & (1 << 22) != 0 { "b" } else { "" };
+            format!("swp{}{} {}, {}, [{}]", byte, cond, register(instruction >> 12), register(instruction), register(instruction >> 16))
+        },
+        InstructionFormat::BranchAndExchange => format!("bx{} {}", cond, register(instruction)),
+        InstructionFormat::HalfwordDataTransfer => halfword_transfer(address, instruction, &cond),
+        InstructionFormat::SingleDataTransfer => single_data_transfer(address, instruction, &cond),
+        InstructionFormat::BlockDataTransfer => block_data_transfer(instruction, &cond),
+        InstructionFormat::Branch => {
+            let link = if instruction & (1 << 24) != 0 { "l" } else { "" };
+            let target = address.wrapping_add(8).wrapping_add(sign_extend(instruction & 0xFF_FFFF, 24) << 2);
+            format!("b{}{} 0x{:08x}", link, cond, target)
+        },
+        InstructionFormat::SoftwareInterrupt => format!("swi{} 0x{:06x}", cond, instruction & 0xFF_FFFF),
+        _ => format!(".word 0x{:08x}", instruction)
+    }
+}
+
+fn data_processing(address: u32, instruction: u32, cond: &str) -> String {
+    let opcode = (instruction >> 21) & 0xF;
+    let set_condition = instruction & (1 << 20) != 0;
+    let immediate_operand = instruction & (1 << 25) != 0;
+    let rn = (instruction >> 16) & 0xF;
+    let rd = (instruction >> 12) & 0xF;
+
+    // the test and compare opcodes without the s bit are the psr transfers
+    if (0x8..=0xB).contains(&opcode) && !set_condition {
+        let psr = if instruction & (1 << 22) != 0 { "spsr" } else { "cpsr" };
+        if instruction & (1 << 21) == 0 {
+            return format!("mrs{} {}, {}", cond, register(rd), psr);
+        }
+        let fields: String = [(19, 'f'), (18, 's'), (17, 'x'), (16, 'c')].iter()
+            .filter(|(bit, _)| instruction & (1 << bit) != 0)
+            .map(|(_, field)| field)
+            .collect();
+        let source = if immediate_operand { immediate(arm_immediate(instruction)) } else { register(instruction) };
+        return format!("msr{} {}_{}, {}", cond, psr, fields, source);
+    }
+
+    let operand = if immediate_operand { immediate(arm_immediate(instruction)) } else { shifted_register(instruction) };
+    let mnemonic = DATA_PROCESSING_OPS[opcode as usize];
+    match opcode {
+        // compares always set the flags so the s is implied
+        0x8..=0xB => format!("{}{} {}, {}", mnemonic, cond, register(rn), operand),
+        0xD | 0xF => format!("{}{}{} {}, {}", mnemonic, if set_condition { "s" } else { "" }, cond, register(rd), operand),
+        _ => {
+            let text = format!("{}{}{} {}, {}, {}", mnemonic, if set_condition { "s" } else { "" }, cond, register(rd), register(rn), operand);
+            // add/sub off the pc is how arm code takes an address
+            if rn == 15 && immediate_operand && (opcode == 0x2 || opcode == 0x4) {
+                let offset = arm_immediate(instruction);
+                let base = address.wrapping_add(8);
+                let target = if opcode == 0x4 { base.wrapping_add(offset) } else { base.wrapping_sub(offset) };
+                format!("{} @ 0x{:08x}", text, target)
+            } else {
+                text
+            }
+        }
+    }
+}
+
+// [rn, offset]{!} when pre-indexed, [rn], offset when post-indexed
+fn addressing(address: u32, rn: u32, offset: Option<String>, pre_indexed: bool, write_back: bool, pc_offset: Option<u32>) -> String {
+    let base = register(rn);
+    let text = match (offset, pre_indexed) {
+        (None, _) => format!("[{}]", base),
+        (Some(offset), true) => format!("[{}, {}]{}", base, offset, if write_back { "!" } else { "" }),
+        (Some(offset), false) => format!("[{}], {}", base, offset)
+    };
+
+    match pc_offset {
+        Some(offset) if rn == 15 && pre_indexed && !write_back => format!("{} @ 0x{:08x}", text, address.wrapping_add(8).wrapping_add(offset)),
+        _ => text
+    }
+}
+
+fn single_data_transfer(address: u32, instruction: u32, cond: &str) -> String {
+    let register_offset = instruction & (1 << 25) != 0;
+    let pre_indexed = instruction & (1 << 24) != 0;
+    let up = instruction & (1 << 23) != 0;
+    let byte = if instruction & (1 << 22) != 0 { "b" } else { "" };
+    let write_back = instruction & (1 << 21) != 0;
+    let op = if instruction & (1 << 20) != 0 { "ldr" } else { "str" };
+    // write back on a post-indexed transfer is the user mode translation bit
+    let translate = if !pre_indexed && write_back { "t" } else { "" };
+
+    let (offset, pc_offset) = if register_offset {
+        (Some(format!("{}{}", if up { "" } else { "-" }, shifted_register(instruction))), None)
+    } else {
+        let value = instruction & 0xFFF;
+        let offset = if value == 0 && up { None } else { Some(signed_immediate(up, value)) };
+        (offset, Some(if up { value } else { value.wrapping_neg() }))
+    };
+
+    format!("{}{}{}{} {}, {}", op, byte, translate, cond, register(instruction >> 12),
+        addressing(address, (instruction >> 16) & 0xF, offset, pre_indexed, write_back, pc_offset))
+}
+
+fn halfword_transfer(address: u32, instruction: u32, cond: &str) -> String {
+    let pre_indexed = instruction & (1 << 24) != 0;
+    let up = instruction & (1 << 23) != 0;
+    let immediate_offset = instruction & (1 << 22) != 0;
+    let write_back = instruction & (1 << 21) != 0;
+    let load = instruction & (1 << 20) != 0;
+
+    let op = match ((instruction >> 5) & 0x3, load) {
+        (1, true) => "ldrh",
+        (1, false) => "strh",
+        (2, _) => "ldrsb",
+        _ => "ldrsh"
+    };
+
+    let (offset, pc_offset) = if immediate_offset {
+        let value = ((instruction >> 4) & 0xF0) | (instruction & 0xF);
+        let offset = if value == 0 && up { None } else { Some(signed_immediate(up, value)) };
+        (offset, Some(if up { value } else { value.wrapping_neg() }))
+    } else {
+        (Some(format!("{}{}", if up { "" } else { "-" }, register(instruction))), None)
+    };
+
+    format!("{}{} {}, {}", op, cond, register(instruction >> 12),
+        addressing(address, (instruction >> 16) & 0xF, offset, pre_indexed, write_back, pc_offset))
+}
+
+fn block_data_transfer(instruction: u32, cond: &str) -> String {
+    let pre_indexed = instruction & (1 << 24) != 0;
+    let up = instruction & (1 << 23) != 0;
+    let user_bank = if instruction & (1 << 22) != 0 { "^" } else { "" };
+    let write_back = instruction & (1 << 21) != 0;
+    let load = instruction & (1 << 20) != 0;
+    let rn = (instruction >> 16) & 0xF;
+    let list = register_list(instruction & 0xFFFF);
+
+    // full descending stack operations on sp
+    if rn == 13 && write_back && user_bank.is_empty() {
+        if load && !pre_indexed && up {
+            return format!("pop{} {}", cond, list);
+        }
+        if !load && pre_indexed && !up {
+            return format!("push{} {}", cond, list);
+        }
+    }
+
+    let op = if load { "ldm" } else { "stm" };
+    let mode = match (pre_indexed, up) {
+        (false, true) => "ia",
+        (true, true) => "ib",
+        (false, false) => "da",
+        (true, false) => "db"
+    };
+    format!("{}{}{} {}{}, {}{}", op, mode, cond, register(rn), if write_back { "!" } else { "" }, list, user_bank)
+}
+
+fn disassemble_thumb(address: u32, instruction: u32) -> String {
+    let low = instruction & 0xFFFF;
+    let high = instruction >> 16;
+
+    match THUMB_INSTRUCTIONS[(low >> 8) as usize] {
+        ThumbInstructionFormat::MoveShiftedRegister => {
+            let (rd, rs, amount) = (register(low & 0x7), register((low >> 3) & 0x7), (low >> 6) & 0x1F);
+            match (low >> 11) & 0x3 {
+                0 if amount == 0 => format!("movs {}, {}", rd, rs),
+                0 => format!("lsls {}, {}, #{}", rd, rs, amount),
+                op => format!("{}s {}, {}, #{}", if op == 1 { "lsr" } else { "asr" }, rd, rs, if amount == 0 { 32 } else { amount })
+            }
+        },
+        ThumbInstructionFormat::AddSubtract => {
+            let (rd, rs) = (register(low & 0x7), register((low >> 3) & 0x7));
+            let op = if low & (1 << 9) != 0 { "subs" } else { "adds" };
+            let operand = if low & (1 << 10) != 0 { immediate((low >> 6) & 0x7) } else { register((low >> 6) & 0x7) };
+            format!("{} {}, {}, {}", op, rd, rs, operand)
+        },
+        ThumbInstructionFormat::ImmediateOp | ThumbInstructionFormat::MoveCompare => {
+            let op = ["movs", "cmp", "adds", "subs"][((low >> 11) & 0x3) as usize];
+            format!("{} {}, {}", op, register((low >> 8) & 0x7), immediate(low & 0xFF))
+        },
+        ThumbInstructionFormat::ALU => {
+            format!("{} {}, {}", THUMB_ALU_OPS[((low >> 6) & 0xF) as usize], register(low & 0x7), register((low >> 3) & 0x7))
+        },
+        ThumbInstructionFormat::HiRegister => {
+            let rd = (low & 0x7) | ((low >> 4) & 0x8);
+            let rs = (low >> 3) & 0xF;
+            match (low >> 8) & 0x3 {
+                0 => format!("add {}, {}", register(rd), register(rs)),
+                1 => format!("cmp {}, {}", register(rd), register(rs)),
+                2 => format!("mov {}, {}", register(rd), register(rs)),
+                _ => format!("bx {}", register(rs))
+            }
+        },
+        ThumbInstructionFormat::LoadPC => {
+            let offset = (low & 0xFF) << 2;
+            let target = (address.wrapping_add(4) & !2).wrapping_add(offset);
+            format!("ldr {}, [pc, {}] @ 0x{:08x}", register((low >> 8) & 0x7), immediate(offset), target)
+        },
+        ThumbInstructionFormat::LoadStoreOffset => {
+            let op = if low & (1 << 11) != 0 { "ldr" } else { "str" };
+            let byte = if low & (1 << 10) != 0 { "b" } else { "" };
+            format!("{}{} {}, [{}, {}]", op, byte, register(low & 0x7), register((low >> 3) & 0x7), register((low >> 6) & 0x7))
+        },
+        ThumbInstructionFormat::LoadStoreExtended => {
+            let op = ["strh", "ldrh", "ldrsb", "ldrsh"][(((low >> 10) & 0x1) << 1 | (low >> 11) & 0x1) as usize];
+            format!("{} {}, [{}, {}]", op, register(low & 0x7), register((low >> 3) & 0x7), register((low >> 6) & 0x7))
+        },
+        ThumbInstructionFormat::LoadStoreImmediateOffset => {
+            let op = if low & (1 << 11) != 0 { "ldr" } else { "str" };
+            let (byte, offset) = if low & (1 << 12) != 0 { ("b", (low >> 6) & 0x1F) } else { ("", ((low >> 6) & 0x1F) << 2) };
+            format!("{}{} {}, [{}, {}]", op, byte, register(low & 0x7), register((low >> 3) & 0x7), immediate(offset))
+        },
+        ThumbInstructionFormat::LoadStoreHalfWord => {
+            let op = if low & (1 << 11) != 0 { "ldrh" } else { "strh" };
+            format!("{} {}, [{}, {}]", op, register(low & 0x7), register((low >> 3) & 0x7), immediate(((low >> 6) & 0x1F) << 1))
+        },
+        ThumbInstructionFormat::LoadStoreSP => {
+            let op = if low & (1 << 11) != 0 { "ldr" } else { "str" };
+            format!("{} {}, [sp, {}]", op, register((low >> 8) & 0x7), immediate((low & 0xFF) << 2))
+        },
+        ThumbInstructionFormat::LoadAddress | ThumbInstructionFormat::GetAddress => {
+            let rd = register((low >> 8) & 0x7);
+            let offset = (low & 0xFF) << 2;
+            if low & (1 << 11) != 0 {
+                format!("add {}, sp, {}", rd, immediate(offset))
+            } else {
+                format!("add {}, pc, {} @ 0x{:08x}", rd, immediate(offset), (address.wrapping_add(4) & !2).wrapping_add(offset))
+            }
+        },
+        ThumbInstructionFormat::AddOffsetSP => {
+            let op = if low & (1 << 7) != 0 { "sub" } else { "add" };
+            format!("{} sp, {}", op, immediate((low & 0x7F) << 2))
+        },
+        ThumbInstructionFormat::PushPopRegister => {
+            let load = low & (1 << 11) != 0;
+            let extra = if low & (1 << 8) == 0 { 0 } else if load { 1 << 15 } else { 1 << 14 };
+            format!("{} {}", if load { "pop" } else { "push" }, register_list((low & 0xFF) | extra))
+        },
+        ThumbInstructionFormat::MultipleLoadStore => {
+            let op = if low & (1 << 11) != 0 { "ldmia" } else { "stmia" };
+            format!("{} {}!, {}", op, register((low >> 8) & 0x7), register_list(low & 0xFF))
+        },
+        ThumbInstructionFormat::ConditionalBranch => {
+            let target = address.wrapping_add(4).wrapping_add(sign_extend(low & 0xFF, 8) << 1);
+            format!("b{} 0x{:08x}", condition(low << 20), target)
+        },
+        ThumbInstructionFormat::SoftwareInterrupt => format!("swi 0x{:02x}", low & 0xFF),
+        ThumbInstructionFormat::BreakpointInterrupt => format!("bkpt 0x{:04x}", low & 0xFF),
+        ThumbInstructionFormat::UnConditonalBranch => {
+            format!("b 0x{:08x}", address.wrapping_add(4).wrapping_add(sign_extend(low & 0x7FF, 11) << 1))
+        },
+        ThumbInstructionFormat::LongBranchLink => {
+            let offset = low & 0x7FF;
+            if low & (1 << 11) == 0 && high & 0xF800 == 0xF800 {
+                let target = address.wrapping_add(4).wrapping_add(sign_extend(offset, 11) << 12).wrapping_add((high & 0x7FF) << 1);
+                format!("bl 0x{:08x}", target)
+            } else if low & (1 << 11) == 0 {
+                // first half on its own, lr = pc + offset << 12
+                let value = (sign_extend(offset, 11) << 12) as i32;
+                format!("add lr, pc, {}", signed_immediate(value >= 0, value.unsigned_abs()))
+            } else {
+                // second half on its own, pc = lr + offset << 1 and lr = the next instruction
+                format!("bl lr, #0x{:x}", offset << 1)
+            }
+        },
+        _ => format!(".hword 0x{:04x}", low)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arm(address: u32, instruction: u32) -> String {
+        disassemble(address, instruction, InstructionSet::Arm)
+    }
+
+    fn thumb(address: u32, instruction: u32) -> String {
+        disassemble(address, instruction, InstructionSet::Thumb)
+    }
+
+    #[test]
+    fn arm_data_processing() {
+        assert_eq!(arm(0, 0xE280_0001), "add r0, r0, #1");
+        assert_eq!(arm(0, 0x1091_2003), "addsne r2, r1, r3");
+        assert_eq!(arm(0, 0xE1A0_0000), "mov r0, r0");
+        assert_eq!(arm(0, 0xE1A0_1102), "mov r1, r2, lsl #2");
+        assert_eq!(arm(0, 0xE1A0_1022), "mov r1, r2, lsr #32");
+        assert_eq!(arm(0, 0xE1A0_1062), "mov r1, r2, rrx");
+        assert_eq!(arm(0, 0xE1A0_1352), "mov r1, r2, asr r3");
+        assert_eq!(arm(0, 0xE3A0_0301), "mov r0, #0x4000000");
+        assert_eq!(arm(0, 0xE350_0000), "cmp r0, #0");
+        assert_eq!(arm(0x0800_0000, 0xE28F_0004), "add r0, pc, #4 @ 0x0800000c");
+        assert_eq!(arm(0, 0xE10F_0000), "mrs r0, cpsr");
+        assert_eq!(arm(0, 0xE129_F000), "msr cpsr_fc, r0");
+        assert_eq!(arm(0, 0xE368_F201), "msr spsr_f, #0x10000000");
+    }
+
+    #[test]
+    fn arm_multiply_and_swap() {
+        assert_eq!(arm(0, 0xE000_0291), "mul r0, r1, r2");
+        assert_eq!(arm(0, 0xE031_3492), "mlas r1, r2, r4, r3");
+        assert_eq!(arm(0, 0xE0C1_0392), "smull r0, r1, r2, r3");
+        assert_eq!(arm(0, 0xE0A1_0392), "umlal r0, r1, r2, r3");
+        assert_eq!(arm(0, 0xE102_0091), "swp r0, r1, [r2]");
+        assert_eq!(arm(0, 0xE142_0091), "swpb r0, r1, [r2]");
+    }
+
+    #[test]
+    fn arm_transfers() {
+        assert_eq!(arm(0, 0xE591_0004), "ldr r0, [r1, #4]");
+        assert_eq!(arm(0, 0xE5B1_0004), "ldr r0, [r1, #4]!");
+        assert_eq!(arm(0, 0xE491_0004), "ldr r0, [r1], #4");
+        assert_eq!(arm(0, 0xE4B1_0004), "ldrt r0, [r1], #4");
+        assert_eq!(arm(0, 0xE5C1_0000), "strb r0, [r1]");
+        assert_eq!(arm(0, 0xE711_0102), "ldr r0, [r1, -r2, lsl #2]");
+        assert_eq!(arm(0x0800_0000, 0xE59F_0010), "ldr r0, [pc, #0x10] @ 0x08000018");
+        assert_eq!(arm(0, 0xE1D1_00B2), "ldrh r0, [r1, #2]");
+        assert_eq!(arm(0, 0xE191_00D2), "ldrsb r0, [r1, r2]");
+        assert_eq!(arm(0, 0xE151_00F2), "ldrsh r0, [r1, #-2]");
+        assert_eq!(arm(0, 0xE0C1_00B2), "strh r0, [r1], #2");
+    }
+
+    #[test]
+    fn arm_block_transfers() {
+        assert_eq!(arm(0, 0xE92D_4010), "push {r4, lr}");
+        assert_eq!(arm(0, 0xE8BD_8010), "pop {r4, pc}");
+        assert_eq!(arm(0, 0xE891_000F), "ldmia r1, {r0, r1, r2, r3}");
+        assert_eq!(arm(0, 0x0920_0003), "stmdbeq r0!, {r0, r1}");
+        assert_eq!(arm(0, 0xE8D0_8000), "ldmia r0, {pc}^");
+    }
+
+    #[test]
+    fn arm_branches() {
+        assert_eq!(arm(0x0800_0004, 0xEAFF_FFFD), "b 0x08000000");
+        assert_eq!(arm(0x0800_0000, 0xEB00_0002), "bl 0x08000010");
+        assert_eq!(arm(0x0800_0000, 0x0A00_0000), "beq 0x08000008");
+        assert_eq!(arm(0, 0xE12F_FF10), "bx r0");
+        assert_eq!(arm(0, 0xEF00_0005), "swi 0x000005");
+        assert_eq!(arm(0, 0xEE00_0000), ".word 0xee000000");
+    }
+
+    #[test]
+    fn thumb_alu_and_registers() {
+        assert_eq!(thumb(0, 0x0088), "lsls r0, r1, #2");
+        assert_eq!(thumb(0, 0x0008), "movs r0, r1");
+        assert_eq!(thumb(0, 0x0808), "lsrs r0, r1, #32");
+        assert_eq!(thumb(0, 0x1888), "adds r0, r1, r2");
+        assert_eq!(thumb(0, 0x1E48), "subs r0, r1, #1");
+        assert_eq!(thumb(0, 0x2005), "movs r0, #5");
+        assert_eq!(thumb(0, 0x2AFF), "cmp r2, #0xff");
+        assert_eq!(thumb(0, 0x4248), "negs r0, r1");
+        assert_eq!(thumb(0, 0x4348), "muls r0, r1");
+        assert_eq!(thumb(0, 0x4687), "mov pc, r0");
+        assert_eq!(thumb(0, 0x4770), "bx lr");
+        assert_eq!(thumb(0, 0xB082), "sub sp, #8");
+        assert_eq!(thumb(0, 0xA902), "add r1, sp, #8");
+        assert_eq!(thumb(0x0800_0002, 0xA001), "add r0, pc, #4 @ 0x08000008");
+    }
+
+    #[test]
+    fn thumb_transfers() {
+        assert_eq!(thumb(0x0800_0002, 0x4801), "ldr r0, [pc, #4] @ 0x08000008");
+        assert_eq!(thumb(0, 0x5088), "str r0, [r1, r2]");
+        assert_eq!(thumb(0, 0x5C88), "ldrb r0, [r1, r2]");
+        assert_eq!(thumb(0, 0x5E88), "ldrsh r0, [r1, r2]");
+        assert_eq!(thumb(0, 0x5688), "ldrsb r0, [r1, r2]");
+        assert_eq!(thumb(0, 0x6848), "ldr r0, [r1, #4]");
+        assert_eq!(thumb(0, 0x7048), "strb r0, [r1, #1]");
+        assert_eq!(thumb(0, 0x8848), "ldrh r0, [r1, #2]");
+        assert_eq!(thumb(0, 0x9001), "str r0, [sp, #4]");
+        assert_eq!(thumb(0, 0xB510), "push {r4, lr}");
+        assert_eq!(thumb(0, 0xBD10), "pop {r4, pc}");
+        assert_eq!(thumb(0, 0xC103), "stmia r1!, {r0, r1}");
+    }
+
+    #[test]
+    fn thumb_branches() {
+        assert_eq!(thumb(0x0800_0004, 0xD0FC), "beq 0x08000000");
+        assert_eq!(thumb(0x0800_0000, 0xE002), "b 0x08000008");
+        assert_eq!(thumb(0, 0xDF05), "swi 0x05");
+        assert_eq!(thumb(0x0800_0000, 0xF802_F000), "bl 0x08000008");
+        assert_eq!(thumb(0x0800_1000, 0xFFFE_F7FF), "bl 0x08001000");
+        assert_eq!(thumb(0x0800_0000, 0xF000), "add lr, pc, #0");
+        assert_eq!(thumb(0x0800_0000, 0xF7FF), "add lr, pc, #-0x1000");
+        assert_eq!(thumb(0, 0xF802), "bl lr, #0x4");
+    }
+}
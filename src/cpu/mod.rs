@@ -3,4 +3,8 @@ pub mod program_status_register;
 pub mod arm_instr;
 pub mod thumb_instr;
 pub mod decode_error;
-pub mod condition;
\ No newline at end of file
+pub mod condition;
+pub mod disassembler;
+pub mod tracer;
+pub mod decode_cache;
+pub mod pipeline;
\ No newline at end of file
@@ -0,0 +1,150 @@
+use serde::{Serialize, Deserialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Stage {
+    pub address: u32,
+    pub opcode: u32
+}
+
+// The ARM7TDMI fetches two instructions ahead of the one executing, which is why r15 reads as
+// the executing address plus 8 in arm state and plus 4 in thumb state. Between steps r15 holds
+// the address of the next instruction to execute, which is the one sitting in decode.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+pub struct Pipeline {
+    pub decode: Option<Stage>,
+    pub fetch: Option<Stage>,
+    // r15 was written by the executing instruction
+    pub flushed: bool
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    // anything prefetched is thrown away and refilled from the new pc
+    pub fn flush(&mut self) {
+        self.decode = None;
+        self.fetch = None;
+        self.flushed = true;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decode.is_none() && self.fetch.is_none()
+    }
+
+    // the stage at `address`, if the pipeline already holds it
+    pub fn take(&mut self, address: u32) -> Option<Stage> {
+        match (self.decode, self.fetch) {
+            (Some(stage), _) if stage.address == address => {
+                self.decode = None;
+                Some(stage)
+            },
+            (_, Some(stage)) if stage.address == address => {
+                self.fetch = None;
+                Some(stage)
+            },
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stage;
+    use crate::gba::GBA;
+    use crate::cpu::cpu::{InstructionSet, OperatingMode, Exception, ARM_PC, ARM_LR, THUMB_PC};
+
+    fn arm_gba(program: &[u32]) -> GBA {
+        let mut gba: GBA = GBA::default();
+        for (index, instruction) in program.iter().enumerate() {
+            gba.memory_bus.mem_map.write_u32(0x0300_0000 + 4 * index as u32, *instruction);
+        }
+        gba.cpu.set_register(ARM_PC, 0x0300_0000);
+        gba
+    }
+
+    #[test]
+    fn pc_reads_two_instructions_ahead() {
+        // mov r0, pc ; ldr r1, [pc, #-4] ; add r2, pc, pc, lsl r3 ; str pc, [r4]
+        let mut gba = arm_gba(&[0xE1A0_000F, 0xE51F_1004, 0xE08F_231F, 0xE584_F000]);
+        gba.cpu.set_register(3, 0);
+        gba.cpu.set_register(4, 0x0300_0100);
+        for _ in 0..4 {
+            gba.single_step();
+        }
+        assert_eq!(gba.cpu.get_register(0), 0x0300_0008);
+        assert_eq!(gba.cpu.get_register(1), 0xE08F_231F);
+        // a register specified shift reads the pc a cycle later
+        assert_eq!(gba.cpu.get_register(2), 0x0300_0014 + 0x0300_0014);
+        assert_eq!(gba.memory_bus.read_u32(0x0300_0100), 0x0300_0018);
+        assert_eq!(gba.cpu.get_register(ARM_PC), 0x0300_0010);
+    }
+
+    #[test]
+    fn thumb_pc_reads_are_word_aligned_for_loads() {
+        let mut gba: GBA = GBA::default();
+        // ldr r0, [pc, #0] ; add r1, pc, #0 ; mov r2, pc
+        gba.memory_bus.mem_map.write_block(0x0300_0002, &vec![0x00, 0x48, 0x00, 0xA1, 0x7A, 0x46]);
+        gba.cpu.set_instruction_set(InstructionSet::Thumb);
+        gba.cpu.set_register(THUMB_PC, 0x0300_0002);
+        for _ in 0..3 {
+            gba.single_step();
+        }
+        assert_eq!(gba.cpu.get_register(0), 0x467A_A100);
+        assert_eq!(gba.cpu.get_register(1), 0x0300_0008);
+        assert_eq!(gba.cpu.get_register(2), 0x0300_000A);
+    }
+
+    #[test]
+    fn branches_flush_what_was_prefetched() {
+        // b +0 ; mov r0, #1 ; mov r1, #1
+        let mut gba = arm_gba(&[0xEA00_0000, 0xE3A0_0001, 0xE3A0_1001]);
+        gba.single_step();
+        // refilled from the target as part of the branch
+        assert_eq!(gba.cpu.pipeline.decode, Some(Stage { address: 0x0300_0008, opcode: 0xE3A0_1001 }));
+        assert_eq!(gba.cpu.get_register(ARM_PC), 0x0300_0008);
+        gba.single_step();
+        assert_eq!(gba.cpu.get_register(0), 0);
+        assert_eq!(gba.cpu.get_register(1), 1);
+    }
+
+    #[test]
+    fn prefetched_opcodes_survive_writes() {
+        // str r1, [r2] overwrites the instruction after it, which is already in decode
+        let mut gba = arm_gba(&[0xE582_1000, 0xE3A0_0001]);
+        gba.cpu.set_register(1, 0xE3A0_0002);
+        gba.cpu.set_register(2, 0x0300_0004);
+        gba.single_step();
+        gba.single_step();
+        assert_eq!(gba.cpu.get_register(0), 1);
+    }
+
+    #[test]
+    fn exception_return_addresses() {
+        // swi 0
+        let mut gba = arm_gba(&[0xEF00_0000]);
+        gba.single_step();
+        assert_eq!(gba.cpu.get_operating_mode(), OperatingMode::Supervisor);
+        assert_eq!(gba.cpu.get_register(ARM_LR), 0x0300_0004);
+        assert_eq!(gba.cpu.get_register(ARM_PC), 0x08);
+
+        // undefined, with the cpu in thumb state
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.mem_map.write_block(0x0300_0000, &vec![0x00, 0xDE]);
+        gba.cpu.set_instruction_set(InstructionSet::Thumb);
+        gba.cpu.set_register(THUMB_PC, 0x0300_0000);
+        gba.single_step();
+        assert_eq!(gba.cpu.get_operating_mode(), OperatingMode::Undefined);
+        assert_eq!(gba.cpu.get_register(ARM_LR), 0x0300_0002);
+        assert_eq!(gba.cpu.get_register(ARM_PC), 0x04);
+
+        // irqs are taken between instructions, handlers return with subs pc, lr, #4
+        let mut gba = arm_gba(&[0xE1A0_0000]);
+        gba.single_step();
+        gba.cpu.take_exception(Exception::Irq);
+        assert_eq!(gba.cpu.get_operating_mode(), OperatingMode::Interrupt);
+        assert_eq!(gba.cpu.get_register(ARM_LR), 0x0300_0008);
+        assert_eq!(gba.cpu.get_register(ARM_PC), 0x18);
+    }
+}
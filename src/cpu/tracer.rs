@@ -0,0 +1,291 @@
+use super::cpu::{CPU, InstructionSet, OperatingMode};
+use super::disassembler::disassemble;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+
+// 16 registers, cpsr, address and opcode as little endian words
+pub const BINARY_RECORD_SIZE: usize = 19 * 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TraceFormat {
+    // one line per instruction: registers, cpsr, address, opcode and disassembly
+    Text,
+    Binary
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TraceRecord {
+    pub address: u32,
+    pub opcode: u32,
+    // r15 reads as the address plus two instructions, the same as the instruction sees it
+    pub registers: [u32; 16],
+    pub cpsr: u32,
+    // second half of a thumb bl when this is the first, only used to show the pair as one bl
+    pub bl_suffix: Option<u32>
+}
+
+impl TraceRecord {
+    pub fn capture(cpu: &CPU, address: u32, opcode: u32) -> TraceRecord {
+        let mut registers = [0u32; 16];
+        for (index, register) in registers.iter_mut().enumerate() {
+            *register = cpu.get_register_unsafe(index as u8);
+        }
+
+        let bl_suffix = match cpu.pipeline.decode {
+            Some(next) if cpu.get_instruction_set() == InstructionSet::Thumb && opcode & 0xF800 == 0xF000 && next.opcode & 0xF800 == 0xF800 => Some(next.opcode),
+            _ => None
+        };
+
+        TraceRecord {
+            address,
+            opcode,
+            registers,
+            cpsr: u32::from(cpu.cpsr),
+            bl_suffix
+        }
+    }
+
+    pub fn instruction_set(&self) -> InstructionSet {
+        if self.cpsr & 0x20 != 0 { InstructionSet::Thumb } else { InstructionSet::Arm }
+    }
+
+    pub fn to_bytes(&self) -> [u8; BINARY_RECORD_SIZE] {
+        let mut bytes = [0u8; BINARY_RECORD_SIZE];
+        let trailer = [self.cpsr, self.address, self.opcode];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(self.registers.iter().chain(trailer.iter())) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn write(&self, out: &mut dyn Write, format: TraceFormat) -> io::Result<()> {
+        match format {
+            TraceFormat::Text => writeln!(out, "{}", self),
+            TraceFormat::Binary => out.write_all(&self.to_bytes())
+        }
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for register in self.registers.iter() {
+            write!(f, "{:08X} ", register)?;
+        }
+        write!(f, "cpsr: {:08X} | ", self.cpsr)?;
+        match self.instruction_set() {
+            InstructionSet::Arm => write!(f, "{:08X}: {:08X} ", self.address, self.opcode)?,
+            InstructionSet::Thumb => write!(f, "{:08X}:     {:04X} ", self.address, self.opcode)?
+        }
+        let instruction = self.opcode | (self.bl_suffix.unwrap_or(0) << 16);
+        write!(f, "{}", disassemble(self.address, instruction, self.instruction_set()))
+    }
+}
+
+// Everything unset matches, so the default filter traces every instruction.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct TraceFilter {
+    // inclusive start, exclusive end
+    pub address_range: Option<(u32, u32)>,
+    pub instruction_set: Option<InstructionSet>,
+    pub operating_mode: Option<OperatingMode>
+}
+
+impl TraceFilter {
+    pub fn matches(&self, address: u32, instruction_set: InstructionSet, operating_mode: OperatingMode) -> bool {
+        self.address_range.is_none_or(|(start, end)| start <= address && address < end)
+            && self.instruction_set.is_none_or(|set| set == instruction_set)
+            && self.operating_mode.is_none_or(|mode| mode == operating_mode)
+    }
+}
+
+enum Sink {
+    Stream(Box<dyn Write>),
+    Ring {
+        records: VecDeque<TraceRecord>,
+        capacity: usize
+    }
+}
+
+// Opt in tracer for CPU::fetch, either streaming every instruction out or keeping the last few
+// around to be dumped after something goes wrong.
+pub struct Tracer {
+    pub filter: TraceFilter,
+    format: TraceFormat,
+    sink: Sink,
+    // the first write error stops the stream
+    error: Option<io::Error>
+}
+
+impl Tracer {
+    pub fn to_writer<W: Write + 'static>(out: W, format: TraceFormat) -> Tracer {
+        Tracer {
+            filter: TraceFilter::default(),
+            format,
+            sink: Sink::Stream(Box::new(out)),
+            error: None
+        }
+    }
+
+    pub fn ring_buffer(capacity: usize, format: TraceFormat) -> Tracer {
+        Tracer {
+            filter: TraceFilter::default(),
+            format,
+            sink: Sink::Ring {
+                records: VecDeque::with_capacity(capacity),
+                capacity
+            },
+            error: None
+        }
+    }
+
+    pub fn with_filter(mut self, filter: TraceFilter) -> Tracer {
+        self.filter = filter;
+        self
+    }
+
+    pub fn format(&self) -> TraceFormat {
+        self.format
+    }
+
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    pub fn record(&mut self, record: TraceRecord) {
+        match &mut self.sink {
+            Sink::Stream(out) => {
+                if self.error.is_none() {
+                    if let Err(error) = record.write(out.as_mut(), self.format) {
+                        self.error = Some(error);
+                    }
+                }
+            },
+            Sink::Ring { records, capacity } => {
+                if *capacity == 0 {
+                    return;
+                }
+                if records.len() == *capacity {
+                    records.pop_front();
+                }
+                records.push_back(record);
+            }
+        }
+    }
+
+    // oldest first, always empty when streaming
+    pub fn records(&self) -> impl Iterator<Item = &TraceRecord> {
+        let records = match &self.sink {
+            Sink::Ring { records, .. } => Some(records.iter()),
+            Sink::Stream(_) => None
+        };
+        records.into_iter().flatten()
+    }
+
+    // writes out what the ring buffer is holding
+    pub fn dump(&self, out: &mut dyn Write) -> io::Result<()> {
+        for record in self.records() {
+            record.write(out, self.format)?;
+        }
+        out.flush()
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.sink {
+            Sink::Stream(out) => out.flush(),
+            Sink::Ring { .. } => Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gba::GBA;
+    use crate::gba::test_programs::loop_gba;
+    use crate::cpu::cpu::THUMB_PC;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn text_trace_lines() {
+        let mut gba = loop_gba(0x0800_0000);
+        let buffer = SharedBuffer::default();
+        gba.cpu.tracer = Some(Tracer::to_writer(buffer.clone(), TraceFormat::Text));
+        gba.single_step();
+        gba.single_step();
+
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("00000000 "));
+        assert!(lines[0].ends_with("| 08000000: E2800001 add r0, r0, #1"));
+        assert!(lines[1].starts_with("00000001 "));
+        assert!(lines[1].contains("0800000C cpsr: "));
+        assert!(lines[1].ends_with("| 08000004: EAFFFFFD b 0x08000000"));
+    }
+
+    #[test]
+    fn thumb_bl_pairs_trace_as_bl() {
+        let mut gba: GBA = GBA::default();
+        // bl 0x08000008
+        gba.memory_bus.mem_map.write_block(0x0800_0000, &vec![0x00, 0xF0, 0x02, 0xF8]);
+        gba.cpu.set_instruction_set(InstructionSet::Thumb);
+        gba.cpu.set_register(THUMB_PC, 0x0800_0000);
+        gba.cpu.tracer = Some(Tracer::ring_buffer(2, TraceFormat::Text));
+        gba.single_step();
+        gba.single_step();
+
+        let lines: Vec<String> = gba.cpu.tracer.as_ref().unwrap().records().map(|record| record.to_string()).collect();
+        assert!(lines[0].ends_with("| 08000000:     F000 bl 0x08000008"));
+        assert!(lines[1].ends_with("| 08000002:     F802 bl lr, #0x4"));
+    }
+
+    #[test]
+    fn ring_buffer_keeps_the_last_records() {
+        let mut gba = loop_gba(0x0800_0000);
+        gba.cpu.tracer = Some(Tracer::ring_buffer(3, TraceFormat::Binary));
+        for _ in 0..10 {
+            gba.single_step();
+        }
+
+        let tracer = gba.cpu.tracer.as_ref().unwrap();
+        let registers: Vec<u32> = tracer.records().map(|record| record.registers[0]).collect();
+        assert_eq!(registers, vec![4, 4, 5]);
+
+        let mut bytes = Vec::new();
+        tracer.dump(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 3 * BINARY_RECORD_SIZE);
+        assert_eq!(&bytes[BINARY_RECORD_SIZE - 4..BINARY_RECORD_SIZE], &0xEAFF_FFFDu32.to_le_bytes());
+    }
+
+    #[test]
+    fn filter_limits_what_is_traced() {
+        let mut gba = loop_gba(0x0800_0000);
+        let filter = TraceFilter { address_range: Some((0x0800_0004, 0x0800_0008)), ..TraceFilter::default() };
+        gba.cpu.tracer = Some(Tracer::ring_buffer(16, TraceFormat::Text).with_filter(filter));
+        for _ in 0..6 {
+            gba.single_step();
+        }
+        assert!(gba.cpu.tracer.as_ref().unwrap().records().all(|record| record.address == 0x0800_0004));
+        assert_eq!(gba.cpu.tracer.as_ref().unwrap().records().count(), 3);
+
+        let filter = TraceFilter { instruction_set: Some(InstructionSet::Thumb), ..TraceFilter::default() };
+        gba.cpu.tracer = Some(Tracer::ring_buffer(16, TraceFormat::Text).with_filter(filter));
+        gba.single_step();
+        assert_eq!(gba.cpu.tracer.as_ref().unwrap().records().count(), 0);
+    }
+}
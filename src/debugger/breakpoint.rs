@@ -0,0 +1,90 @@
+use crate::cpu::cpu::CPU;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual
+}
+
+impl Comparison {
+    pub fn compare(self, left: u32, right: u32) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Flag {
+    Negative,
+    Zero,
+    Carry,
+    Overflow,
+    IrqDisable,
+    Thumb
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BreakCondition {
+    // registers use the arm numbering in both states, r13-r15 are sp, lr and pc
+    Register {
+        register: u8,
+        comparison: Comparison,
+        value: u32
+    },
+    Flag {
+        flag: Flag,
+        set: bool
+    }
+}
+
+impl BreakCondition {
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            BreakCondition::Register { register, .. } => register <= 15,
+            BreakCondition::Flag { .. } => true
+        }
+    }
+
+    pub fn holds(&self, cpu: &CPU) -> bool {
+        match *self {
+            BreakCondition::Register { register, comparison, value } => comparison.compare(cpu.get_register_unsafe(register), value),
+            BreakCondition::Flag { flag, set } => {
+                let current = match flag {
+                    Flag::Negative => cpu.cpsr.flags.negative,
+                    Flag::Zero => cpu.cpsr.flags.zero,
+                    Flag::Carry => cpu.cpsr.flags.carry,
+                    Flag::Overflow => cpu.cpsr.flags.signed_overflow,
+                    Flag::IrqDisable => cpu.cpsr.control_bits.irq_disable,
+                    Flag::Thumb => cpu.cpsr.control_bits.state_bit
+                };
+                current == set
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub address: u32,
+    // all of them have to hold for the breakpoint to stop
+    pub conditions: Vec<BreakCondition>,
+    pub enabled: bool,
+    pub hit_count: u64
+}
+
+impl Breakpoint {
+    pub fn should_break(&self, address: u32, cpu: &CPU) -> bool {
+        self.enabled && self.address == address && self.conditions.iter().all(|condition| condition.holds(cpu))
+    }
+}
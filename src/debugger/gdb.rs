@@ -0,0 +1,560 @@
+use super::{Debugger, StopReason};
+use super::watchpoint::{AccessKind, WatchKind};
+use crate::cpu::cpu::CPU;
+use crate::cpu::program_status_register::ProgramStatusRegister;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <architecture>armv4t</architecture>
+  <feature name="org.gnu.gdb.arm.core">
+    <reg name="r0" bitsize="32" type="uint32"/>
+    <reg name="r1" bitsize="32" type="uint32"/>
+    <reg name="r2" bitsize="32" type="uint32"/>
+    <reg name="r3" bitsize="32" type="uint32"/>
+    <reg name="r4" bitsize="32" type="uint32"/>
+    <reg name="r5" bitsize="32" type="uint32"/>
+    <reg name="r6" bitsize="32" type="uint32"/>
+    <reg name="r7" bitsize="32" type="uint32"/>
+    <reg name="r8" bitsize="32" type="uint32"/>
+    <reg name="r9" bitsize="32" type="uint32"/>
+    <reg name="r10" bitsize="32" type="uint32"/>
+    <reg name="r11" bitsize="32" type="uint32"/>
+    <reg name="r12" bitsize="32" type="uint32"/>
+    <reg name="sp" bitsize="32" type="data_ptr"/>
+    <reg name="lr" bitsize="32"/>
+    <reg name="pc" bitsize="32" type="code_ptr"/>
+    <reg name="cpsr" bitsize="32" regnum="25"/>
+  </feature>
+</target>
+"#;
+
+// gdb numbers cpsr after the fpa registers the gba doesn't have
+const CPSR_REGNUM: usize = 25;
+const INTERRUPT: u8 = 0x03;
+// the largest packet gdb is told it can send or expect back, hex encoded memory takes two bytes a byte
+const PACKET_SIZE: usize = 0x4000;
+const MAX_MEMORY_READ: u32 = (PACKET_SIZE / 2) as u32;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+enum Input {
+    Packet(String),
+    Interrupt,
+    Closed
+}
+
+// Remote serial protocol server, gdb connects with `target remote`.
+// Breakpoints are kept by the debugger so nothing gets patched into memory.
+pub struct GdbStub<S: Read + Write> {
+    stream: S,
+    // polled between frames while running, returns true once gdb has sent a break
+    interrupt_check: fn(&mut S) -> bool,
+    no_ack: bool,
+    breakpoints: Vec<(u32, usize)>,
+    watchpoints: Vec<(WatchKind, u32, u32, usize)>
+}
+
+impl GdbStub<TcpStream> {
+    // blocks until gdb connects
+    pub fn listen<A: ToSocketAddrs>(address: A) -> io::Result<GdbStub<TcpStream>> {
+        let (stream, _) = TcpListener::bind(address)?.accept()?;
+        stream.set_nodelay(true)?;
+        Ok(GdbStub::with_interrupt_check(stream, tcp_interrupt_pending))
+    }
+}
+
+fn tcp_interrupt_pending(stream: &mut TcpStream) -> bool {
+    let mut byte = [0u8; 1];
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let pending = matches!(stream.peek(&mut byte), Ok(1)) && byte[0] == INTERRUPT;
+    let _ = stream.set_nonblocking(false);
+    pending && stream.read_exact(&mut byte).is_ok()
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+fn hex_u32(value: u32) -> String {
+    value.to_le_bytes().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex_u32(text: &str) -> Option<u32> {
+    if text.len() != 8 {
+        return None;
+    }
+    let mut bytes = [0u8; 4];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(index * 2..index * 2 + 2)?, 16).ok()?;
+    }
+    Some(u32::from_le_bytes(bytes))
+}
+
+// "addr,length" as used by m, M and the breakpoint packets
+fn parse_range(text: &str) -> Option<(u32, u32)> {
+    let mut parts = text.split(',');
+    let address = parse_hex(parts.next()?)?;
+    let length = parse_hex(parts.next()?)?;
+    Some((address, length))
+}
+
+impl<S: Read + Write> GdbStub<S> {
+    pub fn new(stream: S) -> GdbStub<S> {
+        GdbStub::with_interrupt_check(stream, |_| false)
+    }
+
+    pub fn with_interrupt_check(stream: S, interrupt_check: fn(&mut S) -> bool) -> GdbStub<S> {
+        GdbStub {
+            stream,
+            interrupt_check,
+            no_ack: false,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new()
+        }
+    }
+
+    pub fn into_stream(self) -> S {
+        self.stream
+    }
+
+    // handles packets until gdb detaches, kills the target or closes the connection
+    pub fn serve(&mut self, debugger: &mut Debugger) -> io::Result<()> {
+        loop {
+            match self.read_input()? {
+                Input::Packet(packet) => match self.handle_packet(debugger, &packet) {
+                    Some(reply) => self.send_packet(&reply)?,
+                    None => {
+                        self.send_packet("OK")?;
+                        return Ok(());
+                    }
+                },
+                Input::Interrupt => self.send_packet(&format!("S{:02x}", SIGINT))?,
+                Input::Closed => return Ok(())
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0u8; 1];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0]))
+        }
+    }
+
+    fn read_input(&mut self) -> io::Result<Input> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(Input::Closed),
+                Some(INTERRUPT) => return Ok(Input::Interrupt),
+                Some(b'$') => {},
+                // acks and anything between packets
+                Some(_) => continue
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(Input::Closed),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte)
+                }
+            }
+
+            let mut sum = [0u8; 2];
+            self.stream.read_exact(&mut sum)?;
+            let packet = String::from_utf8_lossy(&data).into_owned();
+            let valid = std::str::from_utf8(&sum).ok().and_then(|sum| u8::from_str_radix(sum, 16).ok()) == Some(checksum(&packet));
+
+            if !self.no_ack {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Input::Packet(packet));
+            }
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        write!(self.stream, "${}#{:02x}", data, checksum(data))?;
+        self.stream.flush()?;
+
+        if !self.no_ack {
+            // gdb acks every packet, a nak asks for it again
+            loop {
+                match self.read_byte()? {
+                    Some(b'+') | None => break,
+                    Some(b'-') => {
+                        write!(self.stream, "${}#{:02x}", data, checksum(data))?;
+                        self.stream.flush()?;
+                    },
+                    Some(_) => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn stop_reply(&self, reason: &StopReason) -> String {
+        match reason {
+            StopReason::Breakpoint { .. } => format!("T{:02x}swbreak:;", SIGTRAP),
+            StopReason::Watchpoint { address, kind, .. } => match kind {
+                AccessKind::Write => format!("T{:02x}watch:{:x};", SIGTRAP, address),
+                AccessKind::Read => format!("T{:02x}rwatch:{:x};", SIGTRAP, address),
+                AccessKind::Execute => format!("T{:02x}swbreak:;", SIGTRAP)
+            },
+            StopReason::Error(_) => format!("S{:02x}", SIGILL),
+            _ => format!("S{:02x}", SIGTRAP)
+        }
+    }
+
+    // runs a frame at a time so a break from gdb gets noticed
+    fn resume(&mut self, debugger: &mut Debugger) -> String {
+        loop {
+            let frame = debugger.gba.frame_count();
+            match debugger.run_until_frame(frame + 1) {
+                StopReason::FrameReached(_) => {
+                    if (self.interrupt_check)(&mut self.stream) {
+                        return format!("S{:02x}", SIGINT);
+                    }
+                },
+                reason => return self.stop_reply(&reason)
+            }
+        }
+    }
+
+    fn read_register(&self, debugger: &Debugger, register: usize) -> Option<u32> {
+        match register {
+            0..=15 => Some(debugger.gba.cpu.get_register_unsafe(register as u8)),
+            CPSR_REGNUM => Some(u32::from(debugger.gba.cpu.cpsr)),
+            _ => None
+        }
+    }
+
+    fn write_register(&self, debugger: &mut Debugger, register: usize, value: u32) -> bool {
+        match register {
+            0..=15 => debugger.gba.cpu.set_register_unsafe(register as u8, value),
+            // a cpsr with no valid mode would panic on the next register access
+            CPSR_REGNUM if CPU::operating_mode_from_bits((value & 0x1F) as u8).is_some() => debugger.gba.cpu.cpsr = ProgramStatusRegister::from(value),
+            _ => return false
+        }
+        true
+    }
+
+    fn insert_point(&mut self, debugger: &mut Debugger, kind: &str, address: u32, length: u32) -> &'static str {
+        match kind {
+            "0" | "1" => {
+                if !self.breakpoints.iter().any(|(existing, _)| *existing == address) {
+                    let id = debugger.add_breakpoint(address);
+                    self.breakpoints.push((address, id));
+                }
+                "OK"
+            },
+            "2" | "3" | "4" => {
+                let watch_kind = match kind {
+                    "2" => WatchKind::Write,
+                    "3" => WatchKind::Read,
+                    _ => WatchKind::Access
+                };
+                let id = debugger.add_watchpoint(address, length, watch_kind);
+                self.watchpoints.push((watch_kind, address, length, id));
+                "OK"
+            },
+            _ => ""
+        }
+    }
+
+    fn remove_point(&mut self, debugger: &mut Debugger, kind: &str, address: u32, length: u32) -> &'static str {
+        match kind {
+            "0" | "1" => {
+                if let Some(index) = self.breakpoints.iter().position(|(existing, _)| *existing == address) {
+                    debugger.remove_breakpoint(self.breakpoints.remove(index).1);
+                }
+                "OK"
+            },
+            "2" | "3" | "4" => {
+                if let Some(index) = self.watchpoints.iter().position(|(_, start, size, _)| *start == address && *size == length) {
+                    debugger.remove_watchpoint(self.watchpoints.remove(index).3);
+                }
+                "OK"
+            },
+            _ => ""
+        }
+    }
+
+    // returns the reply, None when the session is over
+    pub fn handle_packet(&mut self, debugger: &mut Debugger, packet: &str) -> Option<String> {
+        // packets are plain ascii, anything else got mangled on the way in
+        if !packet.is_ascii() {
+            return Some("E01".to_string());
+        }
+        let (command, arguments) = packet.split_at(packet.len().min(1));
+
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => {
+                let mut registers: String = (0..16).map(|register| hex_u32(debugger.gba.cpu.get_register_unsafe(register))).collect();
+                registers.push_str(&hex_u32(u32::from(debugger.gba.cpu.cpsr)));
+                registers
+            },
+            "G" => {
+                let values: Option<Vec<u32>> = (0..arguments.len() / 8).map(|index| arguments.get(index * 8..index * 8 + 8).and_then(parse_hex_u32)).collect();
+                match values {
+                    Some(values) if values.len() == 17 && CPU::operating_mode_from_bits((values[16] & 0x1F) as u8).is_some() => {
+                        for (register, value) in values.iter().enumerate() {
+                            let register = if register == 16 { CPSR_REGNUM } else { register };
+                            self.write_register(debugger, register, *value);
+                        }
+                        "OK".to_string()
+                    },
+                    _ => "E01".to_string()
+                }
+            },
+            "p" => match parse_hex(arguments).and_then(|register| self.read_register(debugger, register as usize)) {
+                Some(value) => hex_u32(value),
+                None => "E01".to_string()
+            },
+            "P" => {
+                let mut parts = arguments.split('=');
+                let register = parts.next().and_then(parse_hex);
+                let value = parts.next().and_then(parse_hex_u32);
+                match (register, value) {
+                    (Some(register), Some(value)) if self.write_register(debugger, register as usize, value) => "OK".to_string(),
+                    _ => "E01".to_string()
+                }
+            },
+            // a reply has to fit in a packet, gdb asks again for whatever was left out
+            "m" => match parse_range(arguments) {
+                Some((address, length)) => (0..length.min(MAX_MEMORY_READ)).map(|offset| format!("{:02x}", debugger.gba.memory_bus.mem_map.read_u8(address.wrapping_add(offset)))).collect(),
+                None => "E01".to_string()
+            },
+            "M" => {
+                let mut parts = arguments.split(':');
+                match (parts.next().and_then(parse_range), parts.next()) {
+                    (Some((address, length)), Some(data)) if data.len() as u64 == length as u64 * 2 => {
+                        for offset in 0..data.len() / 2 {
+                            match data.get(offset * 2..offset * 2 + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()) {
+                                Some(byte) => debugger.gba.memory_bus.mem_map.write_u8(address.wrapping_add(offset as u32), byte),
+                                None => return Some("E01".to_string())
+                            }
+                        }
+                        "OK".to_string()
+                    },
+                    _ => "E01".to_string()
+                }
+            },
+            "c" => {
+                if let Some(address) = parse_hex(arguments) {
+                    debugger.gba.cpu.set_register_unsafe(15, address);
+                }
+                self.resume(debugger)
+            },
+            "s" => {
+                if let Some(address) = parse_hex(arguments) {
+                    debugger.gba.cpu.set_register_unsafe(15, address);
+                }
+                let reason = debugger.step_in();
+                self.stop_reply(&reason)
+            },
+            "Z" | "z" => {
+                let mut parts = arguments.split(',');
+                let kind = parts.next().unwrap_or("");
+                let address = parts.next().and_then(parse_hex);
+                let length = parts.next().and_then(parse_hex).unwrap_or(4);
+                match address {
+                    Some(address) if command == "Z" => self.insert_point(debugger, kind, address, length).to_string(),
+                    Some(address) => self.remove_point(debugger, kind, address, length).to_string(),
+                    None => "E01".to_string()
+                }
+            },
+            "H" | "T" => "OK".to_string(),
+            "D" | "k" => return None,
+            "q" | "Q" | "v" => self.handle_query(debugger, packet),
+            // anything else is unsupported, gdb falls back to something it knows we have
+            _ => String::new()
+        };
+        Some(reply)
+    }
+
+    fn handle_query(&mut self, debugger: &mut Debugger, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return format!("PacketSize={:x};qXfer:features:read+;swbreak+;QStartNoAckMode+;vContSupported+", PACKET_SIZE);
+        }
+        if packet == "QStartNoAckMode" {
+            self.no_ack = true;
+            return "OK".to_string();
+        }
+        if let Some(request) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return match parse_range(request) {
+                Some((offset, length)) => {
+                    let start = (offset as usize).min(TARGET_XML.len());
+                    let end = (start + length as usize).min(TARGET_XML.len());
+                    let prefix = if end == TARGET_XML.len() { "l" } else { "m" };
+                    format!("{}{}", prefix, &TARGET_XML[start..end])
+                },
+                None => "E01".to_string()
+            };
+        }
+
+        match packet {
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "vCont?" => "vCont;c;C;s;S".to_string(),
+            _ if packet.starts_with("vCont;c") || packet.starts_with("vCont;C") => self.resume(debugger),
+            _ if packet.starts_with("vCont;s") || packet.starts_with("vCont;S") => {
+                let reason = debugger.step_in();
+                self.stop_reply(&reason)
+            },
+            _ => String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gba::test_programs::loop_gba;
+    use crate::cpu::cpu::ARM_PC;
+    use std::io::Cursor;
+
+    // gdb's side of the pipe is scripted up front, everything the stub sends is collected
+    struct Pipe {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn packet(data: &str) -> String {
+        format!("${}#{:02x}", data, checksum(data))
+    }
+
+    fn session(debugger: &mut Debugger, packets: &[&str]) -> Vec<String> {
+        let mut input = String::new();
+        for data in packets {
+            // ack the reply to the previous packet before sending the next one
+            input.push_str(&packet(data));
+            input.push('+');
+        }
+
+        let mut stub = GdbStub::new(Pipe { input: Cursor::new(input.into_bytes()), output: Vec::new() });
+        stub.serve(debugger).unwrap();
+
+        let output = String::from_utf8(stub.into_stream().output).unwrap();
+        output.split('$').skip(1).map(|reply| reply.split('#').next().unwrap().to_string()).collect()
+    }
+
+    fn loop_debugger() -> Debugger {
+        Debugger::new(loop_gba(0x0800_0000))
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let mut debugger = loop_debugger();
+        debugger.gba.cpu.set_register(3, 0x1234_5678);
+
+        let replies = session(&mut debugger, &["g", "p3", "P1=efbeadde", "m8000000,4", "M2000000,2:aabb", "k"]);
+        assert_eq!(&replies[0][24..32], "78563412");
+        assert_eq!(&replies[0][15 * 8..16 * 8], "00000008");
+        assert_eq!(replies[0].len(), 17 * 8);
+        assert_eq!(replies[1], "78563412");
+        assert_eq!(replies[2], "OK");
+        assert_eq!(debugger.gba.cpu.get_register(1), 0xDEAD_BEEF);
+        assert_eq!(replies[3], "010080e2");
+        assert_eq!(debugger.gba.memory_bus.mem_map.read_u16(0x0200_0000), 0xBBAA);
+    }
+
+    #[test]
+    fn breakpoints_step_and_continue() {
+        let mut debugger = loop_debugger();
+        let replies = session(&mut debugger, &["Z0,8000004,4", "c", "s", "z0,8000004,4", "D"]);
+
+        assert_eq!(replies[0], "OK");
+        assert_eq!(replies[1], "T05swbreak:;");
+        assert_eq!(replies[2], "S05");
+        assert_eq!(debugger.gba.cpu.get_register(ARM_PC), 0x0800_0000);
+        assert_eq!(replies[3], "OK");
+        assert!(debugger.breakpoints().is_empty());
+    }
+
+    #[test]
+    fn interrupt_stops_a_continue() {
+        let mut debugger = loop_debugger();
+        let mut stub = GdbStub::with_interrupt_check(Pipe { input: Cursor::new(Vec::new()), output: Vec::new() }, |_| true);
+
+        assert_eq!(stub.handle_packet(&mut debugger, "c"), Some("S02".to_string()));
+        assert_eq!(debugger.gba.frame_count(), 1);
+    }
+
+    #[test]
+    fn target_xml_is_served_in_chunks() {
+        let mut debugger = loop_debugger();
+        let replies = session(&mut debugger, &["qSupported:xmlRegisters=arm", "qXfer:features:read:target.xml:0,10", "qXfer:features:read:target.xml:10,4000", "k"]);
+
+        assert!(replies[0].contains("qXfer:features:read+"));
+        assert_eq!(replies[1], format!("m{}", &TARGET_XML[..0x10]));
+        assert!(replies[2].starts_with('l'));
+        assert!(replies[2].contains("armv4t"));
+    }
+
+    #[test]
+    fn malformed_packets_are_rejected() {
+        let mut debugger = loop_debugger();
+        let mut stub = GdbStub::new(Pipe { input: Cursor::new(Vec::new()), output: Vec::new() });
+
+        assert_eq!(stub.handle_packet(&mut debugger, "\u{fffd}abc"), Some("E01".to_string()));
+        assert_eq!(stub.handle_packet(&mut debugger, "M2000000,2:a\u{e9}a"), Some("E01".to_string()));
+        assert_eq!(stub.handle_packet(&mut debugger, "P1=efbeadd"), Some("E01".to_string()));
+        assert_eq!(stub.handle_packet(&mut debugger, "M2000000,1:zz"), Some("E01".to_string()));
+        assert_eq!(debugger.gba.memory_bus.mem_map.read_u8(0x0200_0000), 0);
+        assert_eq!(parse_hex_u32("a\u{e9}bcdef0"), None);
+    }
+
+    #[test]
+    fn memory_reads_fit_in_a_packet() {
+        let mut debugger = loop_debugger();
+        let mut stub = GdbStub::new(Pipe { input: Cursor::new(Vec::new()), output: Vec::new() });
+
+        let reply = stub.handle_packet(&mut debugger, "m0,ffffffff").unwrap();
+        assert_eq!(reply.len(), PACKET_SIZE);
+    }
+
+    #[test]
+    fn invalid_cpsr_mode_is_rejected() {
+        let mut debugger = loop_debugger();
+        let cpsr = u32::from(debugger.gba.cpu.cpsr);
+
+        let replies = session(&mut debugger, &["P19=00000000", &format!("G{}", "00000000".repeat(17)), "g", "k"]);
+        assert_eq!(replies[0], "E01");
+        assert_eq!(replies[1], "E01");
+        assert_eq!(&replies[2][16 * 8..], hex_u32(cpsr));
+        assert_eq!(u32::from(debugger.gba.cpu.cpsr), cpsr);
+    }
+}
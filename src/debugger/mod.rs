@@ -0,0 +1,505 @@
+pub mod breakpoint;
+pub mod gdb;
+pub mod watchpoint;
+
+use crate::gba::{GBA, error::GbaError};
+use crate::cpu::cpu::{InstructionSet, OperatingMode, ARM_SP};
+use crate::cpu::condition::Condition;
+use crate::memory::memory_map::HaltState;
+use crate::gpu::gpu::{DISPLAY_HEIGHT, VBLANK_LENGTH};
+use breakpoint::{Breakpoint, BreakCondition};
+use watchpoint::{AccessKind, Watchpoint, WatchKind};
+
+// the gpu wraps back to line 0 in the same step it would enter line 227
+const LAST_SCANLINE: u16 = (DISPLAY_HEIGHT + VBLANK_LENGTH - 2) as u16;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum StopReason {
+    // a step command finished
+    Step,
+    Breakpoint {
+        id: usize,
+        address: u32
+    },
+    Watchpoint {
+        id: usize,
+        address: u32,
+        kind: AccessKind,
+        value: u32
+    },
+    FrameReached(u64),
+    ScanlineReached(u16),
+    Error(GbaError)
+}
+
+// Where control flow goes from an instruction, used to step over calls and out of functions
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Flow {
+    Call { return_address: u32 },
+    Return,
+    Other
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Target {
+    Instruction,
+    Frame(u64),
+    Scanline(u16),
+    ReturnTo {
+        address: u32,
+        sp: u32,
+        mode: OperatingMode
+    },
+    Out {
+        depth: u32,
+        returning: bool
+    },
+    Event
+}
+
+pub struct Debugger {
+    pub gba: GBA,
+    breakpoints: Vec<Breakpoint>,
+    next_id: usize,
+    // where the last reported stop was, a breakpoint there is stepped off instead of hit again
+    resume_over: Option<u32>
+}
+
+impl Debugger {
+    pub fn new(gba: GBA) -> Debugger {
+        Debugger {
+            gba,
+            breakpoints: Vec::new(),
+            next_id: 0,
+            resume_over: None
+        }
+    }
+
+    pub fn into_gba(self) -> GBA {
+        self.gba
+    }
+
+    fn take_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    pub fn add_breakpoint(&mut self, address: u32) -> usize {
+        self.push_breakpoint(address, Vec::new())
+    }
+
+    // None when a condition names a register past r15
+    pub fn add_conditional_breakpoint(&mut self, address: u32, conditions: Vec<BreakCondition>) -> Option<usize> {
+        if conditions.iter().all(BreakCondition::is_valid) {
+            Some(self.push_breakpoint(address, conditions))
+        } else {
+            None
+        }
+    }
+
+    fn push_breakpoint(&mut self, address: u32, conditions: Vec<BreakCondition>) -> usize {
+        let id = self.take_id();
+        self.breakpoints.push(Breakpoint {
+            id,
+            address,
+            conditions,
+            enabled: true,
+            hit_count: 0
+        });
+        id
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn set_breakpoint_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        match self.breakpoints.iter_mut().find(|breakpoint| breakpoint.id == id) {
+            Some(breakpoint) => {
+                breakpoint.enabled = enabled;
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+        self.breakpoints.len() != count
+    }
+
+    pub fn add_watchpoint(&mut self, start: u32, length: u32, kind: WatchKind) -> usize {
+        let id = self.take_id();
+        self.gba.memory_bus.watchpoints.push(Watchpoint {
+            id,
+            start,
+            length,
+            kind
+        });
+        id
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.gba.memory_bus.watchpoints
+    }
+
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        let watchpoints = &mut self.gba.memory_bus.watchpoints;
+        let count = watchpoints.len();
+        watchpoints.retain(|watchpoint| watchpoint.id != id);
+        watchpoints.len() != count
+    }
+
+    // runs until a breakpoint or watchpoint is hit
+    pub fn run(&mut self) -> StopReason {
+        self.run_to(Target::Event)
+    }
+
+    pub fn run_until_frame(&mut self, frame: u64) -> StopReason {
+        self.run_to(Target::Frame(frame))
+    }
+
+    // lines past the last one never come around, those stop on the last one instead
+    pub fn run_until_scanline(&mut self, scanline: u16) -> StopReason {
+        self.run_to(Target::Scanline(scanline.min(LAST_SCANLINE)))
+    }
+
+    // executes one instruction, while halted this runs until an interrupt wakes the cpu or a frame passes
+    pub fn step_in(&mut self) -> StopReason {
+        self.run_to(Target::Instruction)
+    }
+
+    pub fn step_over(&mut self) -> StopReason {
+        match self.flow() {
+            Flow::Call { return_address } => {
+                let target = Target::ReturnTo {
+                    address: return_address,
+                    sp: self.gba.cpu.get_register_unsafe(ARM_SP),
+                    mode: self.gba.cpu.get_operating_mode()
+                };
+                self.run_to(target)
+            },
+            _ => self.step_in()
+        }
+    }
+
+    // runs until the current function returns to its caller
+    pub fn step_out(&mut self) -> StopReason {
+        self.run_to(Target::Out { depth: 0, returning: false })
+    }
+
+    fn current_scanline(&self) -> u16 {
+        self.gba.gpu.vertical_count.get_current_scanline() as u16
+    }
+
+    fn check_breakpoints(&mut self, pc: u32) -> Option<StopReason> {
+        let cpu = &self.gba.cpu;
+        if let Some(breakpoint) = self.breakpoints.iter_mut().find(|breakpoint| breakpoint.should_break(pc, cpu)) {
+            breakpoint.hit_count += 1;
+            return Some(StopReason::Breakpoint { id: breakpoint.id, address: pc });
+        }
+
+        let size = if cpu.get_instruction_set() == InstructionSet::Arm { 4 } else { 2 };
+        self.gba.memory_bus.watchpoints.iter()
+            .find(|watchpoint| watchpoint.matches(pc, size, AccessKind::Execute))
+            .map(|watchpoint| StopReason::Watchpoint {
+                id: watchpoint.id,
+                address: pc,
+                kind: AccessKind::Execute,
+                value: if size == 4 { self.gba.memory_bus.mem_map.read_u32(pc) } else { self.gba.memory_bus.mem_map.read_u16(pc) as u32 }
+            })
+    }
+
+    fn flow(&self) -> Flow {
+        let cpu = &self.gba.cpu;
+        let pc = cpu.get_pc();
+        let mem_map = &self.gba.memory_bus.mem_map;
+        // the hle bios handles a swi inline, no return from it ever runs
+        let swi_calls = !cpu.hle_bios;
+
+        if cpu.get_instruction_set() == InstructionSet::Arm {
+            let instruction = mem_map.read_u32(pc);
+            if !cpu.check_condition(&Condition::from(instruction >> 28)) {
+                return Flow::Other;
+            }
+
+            // data processing into pc from lr, like movs pc, lr or subs pc, lr, #4 out of an exception
+            let opcode = (instruction >> 21) & 0xF;
+            let data_processing_return = instruction & 0x0C00_F000 == 0x0000_F000
+                && instruction & 0x0200_0090 != 0x0000_0090                // multiplies and halfword transfers
+                && !(0x8..=0xB).contains(&opcode)                          // tst, teq, cmp, cmn and the psr transfers
+                && match opcode {
+                    0xD => instruction & 0x0200_0FFF == 0x0000_000E,       // mov with lr unshifted
+                    0xF => false,
+                    _ => (instruction >> 16) & 0xF == 14
+                };
+            let is_return = instruction & 0x0FFF_FFFF == 0x012F_FF1E       // bx lr
+                || data_processing_return
+                || instruction & 0x0E10_8000 == 0x0810_8000                // ldm with pc in the list
+                || instruction & 0x0C10_F000 == 0x0410_F000;               // ldr pc
+            match instruction & 0x0F00_0000 {
+                0x0B00_0000 => Flow::Call { return_address: pc + 4 },
+                0x0F00_0000 if swi_calls => Flow::Call { return_address: pc + 4 },
+                _ if is_return => Flow::Return,
+                _ => Flow::Other
+            }
+        } else {
+            let instruction = mem_map.read_u16(pc);
+            if instruction & 0xF800 == 0xF000 {
+                // the two halves of bl run as separate instructions
+                Flow::Call { return_address: pc + 4 }
+            } else if instruction & 0xFF00 == 0xDF00 && swi_calls {
+                Flow::Call { return_address: pc + 2 }
+            } else if instruction == 0x4770 || instruction & 0xFF00 == 0xBD00 {
+                Flow::Return
+            } else {
+                Flow::Other
+            }
+        }
+    }
+
+    fn run_to(&mut self, target: Target) -> StopReason {
+        let reason = self.advance(target);
+        self.resume_over = match reason {
+            StopReason::Step | StopReason::Breakpoint { .. } | StopReason::Watchpoint { .. } => Some(self.gba.cpu.get_pc()),
+            _ => None
+        };
+        reason
+    }
+
+    fn advance(&mut self, mut target: Target) -> StopReason {
+        let resume_over = self.resume_over.take();
+        let start_frame = self.gba.frame_count();
+        let mut scanline = self.current_scanline();
+        let mut executed = false;
+
+        loop {
+            let running = self.gba.memory_bus.mem_map.halt_state == HaltState::Running;
+            if running {
+                let pc = self.gba.cpu.get_pc();
+                if executed || Some(pc) != resume_over {
+                    if let Some(reason) = self.check_breakpoints(pc) {
+                        return reason;
+                    }
+                }
+
+                match &mut target {
+                    Target::ReturnTo { address, sp, mode } => {
+                        if executed && pc == *address && self.gba.cpu.get_operating_mode() == *mode && self.gba.cpu.get_register_unsafe(ARM_SP) >= *sp {
+                            return StopReason::Step;
+                        }
+                    },
+                    Target::Out { depth, returning } => {
+                        match self.flow() {
+                            Flow::Call { .. } => *depth += 1,
+                            Flow::Return if *depth == 0 => *returning = true,
+                            Flow::Return => *depth -= 1,
+                            Flow::Other => {}
+                        }
+                    },
+                    _ => {}
+                }
+            }
+
+            if let Err(e) = self.gba.try_single_step() {
+                return StopReason::Error(e);
+            }
+            // a stopped gba never finishes a frame, count them anyway so frame targets are reached
+            if self.gba.gpu.frame_ready || self.gba.is_stopped() {
+                self.gba.finish_frame();
+                self.gba.start_frame();
+            }
+            executed |= running;
+
+            if let Some(hit) = self.gba.memory_bus.watch_hit.take() {
+                return StopReason::Watchpoint { id: hit.id, address: hit.address, kind: hit.kind, value: hit.value };
+            }
+
+            match target {
+                Target::Instruction if executed => return StopReason::Step,
+                // nothing ran for a whole frame, give control back instead of spinning
+                Target::Instruction if self.gba.frame_count() > start_frame => return StopReason::Step,
+                Target::Out { returning: true, .. } if running => return StopReason::Step,
+                Target::Frame(frame) if self.gba.frame_count() >= frame => return StopReason::FrameReached(self.gba.frame_count()),
+                Target::Scanline(line) => {
+                    let current = self.current_scanline();
+                    if current != scanline && current == line {
+                        return StopReason::ScanlineReached(line);
+                    }
+                    scanline = current;
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::breakpoint::{Comparison, Flag};
+    use crate::cpu::cpu::{ARM_LR, ARM_PC, THUMB_LR, THUMB_PC};
+    use crate::gba::test_programs::LOOP;
+
+    fn load_words(gba: &mut GBA, address: u32, words: &[u32]) {
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect();
+        gba.memory_bus.mem_map.write_block(address, &bytes);
+    }
+
+    fn arm_debugger(words: &[u32]) -> Debugger {
+        let mut gba: GBA = GBA::default();
+        load_words(&mut gba, 0x0800_0000, words);
+        gba.cpu.set_register(ARM_PC, 0x0800_0000);
+        Debugger::new(gba)
+    }
+
+    #[test]
+    fn breakpoint_stops_and_resumes() {
+        let mut debugger = arm_debugger(&LOOP);
+        let id = debugger.add_breakpoint(0x0800_0004);
+
+        assert_eq!(debugger.run(), StopReason::Breakpoint { id, address: 0x0800_0004 });
+        assert_eq!(debugger.gba.cpu.get_register(0), 1);
+        // resuming steps off the breakpoint and comes back around
+        assert_eq!(debugger.run(), StopReason::Breakpoint { id, address: 0x0800_0004 });
+        assert_eq!(debugger.gba.cpu.get_register(0), 2);
+        assert_eq!(debugger.breakpoints()[0].hit_count, 2);
+    }
+
+    #[test]
+    fn conditional_breakpoint() {
+        let mut debugger = arm_debugger(&LOOP);
+        let id = debugger.add_conditional_breakpoint(0x0800_0000, vec![
+            BreakCondition::Register { register: 0, comparison: Comparison::GreaterOrEqual, value: 5 },
+            BreakCondition::Flag { flag: Flag::Thumb, set: false }
+        ]).unwrap();
+
+        assert_eq!(debugger.run(), StopReason::Breakpoint { id, address: 0x0800_0000 });
+        assert_eq!(debugger.gba.cpu.get_register(0), 5);
+    }
+
+    #[test]
+    fn conditions_on_missing_registers_are_rejected() {
+        let mut debugger = arm_debugger(&LOOP);
+        let condition = BreakCondition::Register { register: 16, comparison: Comparison::Equal, value: 0 };
+
+        assert_eq!(debugger.add_conditional_breakpoint(0x0800_0000, vec![condition]), None);
+        assert!(debugger.breakpoints().is_empty());
+    }
+
+    #[test]
+    fn watchpoints_report_accesses() {
+        // mov r1, #0x03000000 ; str r0, [r1] ; ldr r2, [r1, #4]
+        let mut debugger = arm_debugger(&[0xE3A0_1403, 0xE581_0000, 0xE591_2004]);
+        debugger.gba.cpu.set_register(0, 0x1234);
+        let write = debugger.add_watchpoint(0x0300_0000, 4, WatchKind::Write);
+        let read = debugger.add_watchpoint(0x0300_0004, 4, WatchKind::Read);
+
+        assert_eq!(debugger.run(), StopReason::Watchpoint { id: write, address: 0x0300_0000, kind: AccessKind::Write, value: 0x1234 });
+        assert_eq!(debugger.gba.cpu.get_register(ARM_PC), 0x0800_0008);
+        assert!(matches!(debugger.run(), StopReason::Watchpoint { id, kind: AccessKind::Read, .. } if id == read));
+
+        debugger.remove_watchpoint(write);
+        debugger.remove_watchpoint(read);
+        let execute = debugger.add_watchpoint(0x0800_0008, 4, WatchKind::Execute);
+        debugger.gba.cpu.set_register(ARM_PC, 0x0800_0000);
+        assert!(matches!(debugger.run(), StopReason::Watchpoint { id, address: 0x0800_0008, kind: AccessKind::Execute, .. } if id == execute));
+    }
+
+    #[test]
+    fn step_over_and_out_of_arm_calls() {
+        let mut debugger = arm_debugger(&[
+            0xEB00_0001,    // bl 0x0800000C
+            0xE3A0_2001,    // mov r2, #1
+            0xEAFF_FFFE,    // b .
+            0xE3A0_1001,    // mov r1, #1
+            0xE3A0_3001,    // mov r3, #1
+            0xE12F_FF1E     // bx lr
+        ]);
+
+        assert_eq!(debugger.step_over(), StopReason::Step);
+        assert_eq!(debugger.gba.cpu.get_register(ARM_PC), 0x0800_0004);
+        assert_eq!(debugger.gba.cpu.get_register(1), 1);
+
+        debugger.gba.cpu.set_register(ARM_PC, 0x0800_0000);
+        assert_eq!(debugger.step_in(), StopReason::Step);
+        assert_eq!(debugger.gba.cpu.get_register(ARM_PC), 0x0800_000C);
+        assert_eq!(debugger.step_out(), StopReason::Step);
+        assert_eq!(debugger.gba.cpu.get_register(ARM_PC), 0x0800_0004);
+    }
+
+    #[test]
+    fn step_out_of_an_exception_handler() {
+        let mut debugger = arm_debugger(&[
+            0xE3A0_1001,    // mov r1, #1
+            0xE1B0_F00E,    // movs pc, lr
+            0xE3A0_2001,    // mov r2, #1
+            0xE25E_F004,    // subs pc, lr, #4
+            0xEAFF_FFFE     // b .
+        ]);
+        debugger.add_breakpoint(0x0800_0014);
+        let caller = debugger.gba.cpu.cpsr;
+        let caller_mode = debugger.gba.cpu.get_operating_mode();
+
+        for (pc, lr) in [(0x0800_0000, 0x0800_0010), (0x0800_0008, 0x0800_0014)] {
+            debugger.gba.cpu.set_operating_mode(OperatingMode::Interrupt);
+            debugger.gba.cpu.set_spsr(caller);
+            debugger.gba.cpu.set_register(ARM_PC, pc);
+            debugger.gba.cpu.set_register(ARM_LR, lr);
+
+            assert_eq!(debugger.step_out(), StopReason::Step);
+            assert_eq!(debugger.gba.cpu.get_register(ARM_PC), 0x0800_0010);
+            assert_eq!(debugger.gba.cpu.get_operating_mode(), caller_mode);
+        }
+    }
+
+    #[test]
+    fn step_over_thumb_bl() {
+        let mut gba: GBA = GBA::default();
+        // bl +4 as two halves, then mov r1, #1 ; mov r1, #2 ; bx lr
+        let code: [u16; 6] = [0xF000, 0xF802, 0x2101, 0xE7FE, 0x2102, 0x4770];
+        let bytes: Vec<u8> = code.iter().flat_map(|half| half.to_le_bytes().to_vec()).collect();
+        gba.memory_bus.mem_map.write_block(0x0800_0000, &bytes);
+        gba.cpu.set_instruction_set(InstructionSet::Thumb);
+        gba.cpu.set_register(THUMB_PC, 0x0800_0000);
+        let mut debugger = Debugger::new(gba);
+
+        assert_eq!(debugger.step_over(), StopReason::Step);
+        assert_eq!(debugger.gba.cpu.get_register(THUMB_PC), 0x0800_0004);
+        assert_eq!(debugger.gba.cpu.get_register(1), 2);
+    }
+
+    #[test]
+    fn step_out_over_hle_swi() {
+        let mut gba: GBA = GBA::default();
+        gba.enable_hle_bios();
+        // push {lr} ; swi 6 ; pop {pc} ; b .
+        let code: [u16; 4] = [0xB500, 0xDF06, 0xBD00, 0xE7FE];
+        let bytes: Vec<u8> = code.iter().flat_map(|half| half.to_le_bytes().to_vec()).collect();
+        gba.memory_bus.mem_map.write_block(0x0800_0000, &bytes);
+        gba.cpu.set_instruction_set(InstructionSet::Thumb);
+        gba.cpu.set_register(THUMB_PC, 0x0800_0000);
+        gba.cpu.set_register(0, 10);
+        gba.cpu.set_register(1, 2);
+        gba.cpu.set_register(THUMB_LR, 0x0800_0007);
+        let mut debugger = Debugger::new(gba);
+        // only reached if step_out runs past the return
+        debugger.add_breakpoint(0x0800_0006);
+
+        assert_eq!(debugger.step_out(), StopReason::Step);
+        assert_eq!(debugger.gba.cpu.get_register(THUMB_PC), 0x0800_0006);
+        assert_eq!(debugger.gba.cpu.get_register(0), 5);
+    }
+
+    #[test]
+    fn run_until_frame_and_scanline() {
+        let mut debugger = arm_debugger(&LOOP);
+        assert_eq!(debugger.run_until_scanline(100), StopReason::ScanlineReached(100));
+        assert_eq!(debugger.gba.gpu.vertical_count.get_current_scanline(), 100);
+        assert_eq!(debugger.run_until_frame(2), StopReason::FrameReached(2));
+        assert_eq!(debugger.gba.frame_count(), 2);
+        assert_eq!(debugger.run_until_scanline(500), StopReason::ScanlineReached(226));
+        assert_eq!(debugger.gba.gpu.vertical_count.get_current_scanline(), 226);
+    }
+}
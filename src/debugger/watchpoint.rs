@@ -0,0 +1,49 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AccessKind {
+    Read,
+    Write,
+    Execute
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WatchKind {
+    Read,
+    Write,
+    // reads or writes
+    Access,
+    Execute
+}
+
+impl WatchKind {
+    pub fn includes(self, kind: AccessKind) -> bool {
+        match self {
+            WatchKind::Read => kind == AccessKind::Read,
+            WatchKind::Write => kind == AccessKind::Write,
+            WatchKind::Access => kind != AccessKind::Execute,
+            WatchKind::Execute => kind == AccessKind::Execute
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Watchpoint {
+    pub id: usize,
+    pub start: u32,
+    pub length: u32,
+    pub kind: WatchKind
+}
+
+impl Watchpoint {
+    // an access of any size that touches the range counts
+    pub fn matches(&self, address: u32, size: u32, kind: AccessKind) -> bool {
+        self.kind.includes(kind) && address < self.start.wrapping_add(self.length) && self.start < address.wrapping_add(size)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WatchHit {
+    pub id: usize,
+    pub address: u32,
+    pub kind: AccessKind,
+    pub value: u32
+}
@@ -1,5 +1,7 @@
 use crate::memory::{dma_registers::*, GbaMem, memory_bus::MemoryBus};
 use crate::interrupts::interrupts::Interrupts;
+use crate::apu::direct_sound::{FIFO_A_ADDRESS, FIFO_B_ADDRESS};
+use crate::gba::error::{GbaError, ErrorContext, Subsystem};
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::fmt;
@@ -47,35 +49,47 @@ impl DMAChannel {
         self.control.register(mem);
     }
 
-    pub fn update_source_address(&mut self) {
+    pub fn update_source_address(&mut self) -> Result<(), GbaError> {
         let word_size = if self.control.get_dma_transfer_type() == 0 { 2 } else { 4 };
 
         match self.control.get_source_address_control() {
             0 => {
-                self.internal_source_address += word_size;
+                self.internal_source_address = self.internal_source_address.wrapping_add(word_size);
             },
             1 => {
-                self.internal_source_address -= word_size;
+                self.internal_source_address = self.internal_source_address.wrapping_sub(word_size);
             },
             2 => {},
-            _ => panic!("Invalid source address control")
+            _ => return Err(self.control_error())
+        }
+
+        Ok(())
+    }
+
+    fn control_error(&self) -> GbaError {
+        GbaError::InvalidDmaControl {
+            channel: self.id,
+            control: self.control.get_register() as u32,
+            context: ErrorContext::new(Subsystem::Dma)
         }
     }
 
     
-    pub fn update_destination_address(&mut self) {
+    pub fn update_destination_address(&mut self) -> Result<(), GbaError> {
         let word_size = if self.control.get_dma_transfer_type() == 0 { 2 } else { 4 };
 
         match self.control.get_destination_address_control() {
             0 | 3 => {
-                self.internal_destination_address += word_size;
+                self.internal_destination_address = self.internal_destination_address.wrapping_add(word_size);
             },
             1 => {
-                self.internal_destination_address -= word_size;
+                self.internal_destination_address = self.internal_destination_address.wrapping_sub(word_size);
             },
             2 => {},
-            _ => panic!("Invalid source address control")
+            _ => return Err(self.control_error())
         }
+
+        Ok(())
     }
 
     fn reload_data(&mut self) {
@@ -104,15 +118,20 @@ impl DMAChannel {
         }
     }
 
-    pub fn transfer(&mut self, mem_map: &mut MemoryBus, irq_ctl: &mut Interrupts) {        
+    pub fn transfer(&mut self, mem_map: &mut MemoryBus, irq_ctl: &mut Interrupts) -> Result<(), GbaError> {
+        // the eeprom size can only be told from the length of the serial request
+        if mem_map.mem_map.is_eeprom_address(self.internal_destination_address) {
+            mem_map.mem_map.eeprom.detect_size(self.internal_word_count);
+        }
+
         match self.control.get_dma_transfer_type() {
             0 => {  // 16
                 for _ in 0..self.internal_word_count {
                     let value = mem_map.read_u16(self.internal_source_address & !1);
                     mem_map.write_u16(self.internal_destination_address & !1, value);
 
-                    self.update_source_address();
-                    self.update_destination_address();
+                    self.update_source_address()?;
+                    self.update_destination_address()?;
                 }
             },
             1 => { // 32
@@ -120,13 +139,46 @@ impl DMAChannel {
                     let value = mem_map.read_u32(self.internal_source_address & !3);
                     mem_map.write_u32(self.internal_destination_address & !3, value);
 
-                    self.update_source_address();
-                    self.update_destination_address();
+                    self.update_source_address()?;
+                    self.update_destination_address()?;
                 }
             },
-            _ => panic!("DMA transfer type error")
+            _ => return Err(self.control_error())
         } 
 
+        self.finish_transfer(irq_ctl);
+        Ok(())
+    }
+
+    // sound fifo mode always moves 4 words and never touches the destination address
+    pub fn transfer_fifo(&mut self, mem_map: &mut MemoryBus, irq_ctl: &mut Interrupts) {
+        for _ in 0..4 {
+            let value = mem_map.read_u32(self.internal_source_address & !3);
+            mem_map.write_u32(self.internal_destination_address & !3, value);
+
+            match self.control.get_source_address_control() {
+                0 => self.internal_source_address = self.internal_source_address.wrapping_add(4),
+                1 => self.internal_source_address = self.internal_source_address.wrapping_sub(4),
+                _ => {}
+            }
+        }
+
+        self.finish_transfer(irq_ctl);
+    }
+
+    pub fn fifo_index(&self) -> Option<usize> {
+        if self.id != 1 && self.id != 2 {
+            return None;
+        }
+
+        match self.internal_destination_address {
+            FIFO_A_ADDRESS => Some(0),
+            FIFO_B_ADDRESS => Some(1),
+            _ => None
+        }
+    }
+
+    fn finish_transfer(&mut self, irq_ctl: &mut Interrupts) {
         // trigger IRQ here
         if self.control.get_irq_upon_end_of_wordcount() != 0 {
             irq_ctl.if_interrupt.set_register((irq_ctl.if_interrupt.get_register() as u32) | (0x1 << (8 + self.id)));
@@ -151,7 +203,8 @@ impl DMAChannel {
 pub struct DMAController {
     pub dma_channels: [DMAChannel; 4],
     pub hblanking: bool,
-    pub vblanking: bool
+    pub vblanking: bool,
+    pub fifo_requests: [bool; 2]
 }
 
 impl DMAController {
@@ -161,7 +214,7 @@ impl DMAController {
         }
     }
 
-    pub fn update(&mut self, mem_map: &mut MemoryBus, irq_ctl: &mut Interrupts) {
+    pub fn update(&mut self, mem_map: &mut MemoryBus, irq_ctl: &mut Interrupts) -> Result<(), GbaError> {
         for i in 0..4 {
             if self.dma_channels[i].control.get_dma_enable() == 1 {
                 if self.dma_channels[i].previously_disabled {
@@ -172,36 +225,61 @@ impl DMAController {
                 match self.dma_channels[i].control.get_dma_start_timing() {
                     0 => {
                         // start immedietly
-                        self.dma_channels[i].transfer(mem_map, irq_ctl);
+                        self.dma_channels[i].transfer(mem_map, irq_ctl)?;
                     },
                     1 => {
                         // start at vblank
                         if self.vblanking {
-                            self.dma_channels[i].transfer(mem_map, irq_ctl);
+                            self.dma_channels[i].transfer(mem_map, irq_ctl)?;
                             self.vblanking = false;
                         }
                     },
                     2 => {
                         // start at hblank
                         if self.hblanking {
-                            self.dma_channels[i].transfer(mem_map, irq_ctl);
+                            self.dma_channels[i].transfer(mem_map, irq_ctl)?;
                             self.hblanking = false;
                         }
                         // self.dma_channels[i].control.set_dma_enable(0);
                     },
                     3 => {
-                        // special
-                        // TODO implement this
-                        self.dma_channels[i].control.set_dma_enable(0);
+                        // special, dma 1 and 2 refill the sound fifos
+                        match self.dma_channels[i].fifo_index() {
+                            Some(fifo) => {
+                                if self.fifo_requests[fifo] {
+                                    self.dma_channels[i].transfer_fifo(mem_map, irq_ctl);
+                                    self.fifo_requests[fifo] = false;
+                                }
+                            },
+                            None => {
+                                // TODO video capture for dma 3
+                                self.dma_channels[i].control.set_dma_enable(0);
+                            }
+                        }
                     },
-                    _ => {
-                        panic!("DMA Update fucked up")
-                    }
+                    _ => return Err(self.dma_channels[i].control_error())
                 }
             } else if !self.dma_channels[i].previously_disabled {
                 self.dma_channels[i].previously_disabled = true;
             }
         }
+
+        Ok(())
+    }
+
+    // a transfer that will run on the next update, without waiting on the gpu or a timer
+    pub fn has_pending_transfer(&self) -> bool {
+        self.dma_channels.iter().any(|channel| {
+            if channel.control.get_dma_enable() == 0 {
+                return false;
+            }
+            match channel.control.get_dma_start_timing() {
+                0 => true,
+                1 => self.vblanking,
+                2 => self.hblanking,
+                _ => channel.fifo_index().is_some_and(|fifo| self.fifo_requests[fifo])
+            }
+        })
     }
 
     pub fn new() -> DMAController {
@@ -213,7 +291,8 @@ impl DMAController {
                 DMAChannel::new(3),
             ],
             hblanking: false,
-            vblanking: false
+            vblanking: false,
+            fifo_requests: [false; 2]
         }
     }
 }
@@ -0,0 +1,283 @@
+use crate::memory::memory_map::MemoryMap;
+use crate::gamepak::BackupType;
+use serde::{Serialize, Deserialize};
+
+pub const EEPROM_512_SIZE: usize = 0x200;
+pub const EEPROM_8K_SIZE: usize = 0x2000;
+
+// dma word counts used for the read request and write request streams
+const SMALL_REQUEST_LENGTHS: [u32; 2] = [9, 73];
+const LARGE_REQUEST_LENGTHS: [u32; 2] = [17, 81];
+
+const LARGE_ROM_SIZE: usize = 0x0100_0000;
+const LARGE_ROM_EEPROM_START: u32 = 0x0DFF_FF00;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum EepromSize {
+    Unknown,
+    Size512,
+    Size8K
+}
+
+impl EepromSize {
+    pub fn address_bits(&self) -> Option<u32> {
+        match self {
+            EepromSize::Unknown => None,
+            EepromSize::Size512 => Some(6),
+            EepromSize::Size8K => Some(14)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum EepromState {
+    Idle,
+    Reading {
+        address: usize,
+        bit: u32
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Eeprom {
+    pub data: Vec<u8>,
+    pub size: EepromSize,
+    state: EepromState,
+    buffer: u128,
+    bit_count: u32
+}
+
+impl Eeprom {
+    pub fn new() -> Eeprom {
+        Eeprom {
+            data: vec![0xFF; EEPROM_8K_SIZE],
+            size: EepromSize::Unknown,
+            state: EepromState::Idle,
+            buffer: 0,
+            bit_count: 0
+        }
+    }
+
+    pub fn detect_size(&mut self, dma_word_count: u32) {
+        if self.size != EepromSize::Unknown {
+            return;
+        }
+
+        if SMALL_REQUEST_LENGTHS.contains(&dma_word_count) {
+            log::info!("Detected 512B eeprom");
+            self.size = EepromSize::Size512;
+        } else if LARGE_REQUEST_LENGTHS.contains(&dma_word_count) {
+            log::info!("Detected 8KB eeprom");
+            self.size = EepromSize::Size8K;
+        }
+    }
+
+    pub fn load(&mut self, save_data: &[u8]) {
+        self.size = if save_data.len() <= EEPROM_512_SIZE { EepromSize::Size512 } else { EepromSize::Size8K };
+        self.data = vec![0xFF; EEPROM_8K_SIZE];
+        let length = save_data.len().min(EEPROM_8K_SIZE);
+        self.data[..length].copy_from_slice(&save_data[..length]);
+    }
+
+    pub fn save_data(&self) -> Vec<u8> {
+        match self.size {
+            EepromSize::Size512 => self.data[..EEPROM_512_SIZE].to_vec(),
+            _ => self.data.clone()
+        }
+    }
+
+    fn request_bit(&self, index: u32) -> u128 {
+        (self.buffer >> (self.bit_count - 1 - index)) & 1
+    }
+
+    fn request_field(&self, start: u32, length: u32) -> u128 {
+        (self.buffer >> (self.bit_count - start - length)) & ((1u128 << length) - 1)
+    }
+
+    fn block_address(&self, address_bits: u32) -> usize {
+        // only the low 10 bits of a 14 bit address are wired up
+        (self.request_field(2, address_bits) as usize & 0x3FF) * 8
+    }
+
+    fn clear_request(&mut self) {
+        self.buffer = 0;
+        self.bit_count = 0;
+    }
+
+    pub fn write_bit(&mut self, bit: u8) {
+        if let EepromState::Reading { .. } = self.state {
+            self.state = EepromState::Idle;
+        }
+
+        // anything past the longest request is garbage
+        if self.bit_count == 81 {
+            self.clear_request();
+        }
+
+        self.buffer = (self.buffer << 1) | ((bit & 1) as u128);
+        self.bit_count += 1;
+
+        if let Some(address_bits) = self.size.address_bits() {
+            if self.bit_count == 2 + address_bits + 64 + 1 && self.request_bit(0) == 1 && self.request_bit(1) == 0 {
+                self.finish_write(address_bits);
+            }
+        }
+    }
+
+    fn finish_write(&mut self, address_bits: u32) {
+        let address = self.block_address(address_bits);
+        let value = self.request_field(2 + address_bits, 64) as u64;
+        self.data[address..address + 8].copy_from_slice(&value.to_be_bytes());
+        self.clear_request();
+    }
+
+    pub fn read_bit(&mut self) -> u16 {
+        if self.bit_count >= 2 && self.request_bit(0) == 1 {
+            if self.request_bit(1) == 1 {
+                // read request: 2 command bits, the address and a stop bit
+                let address_bits = self.size.address_bits().unwrap_or(self.bit_count.saturating_sub(3));
+                if address_bits == 6 || address_bits == 14 {
+                    self.state = EepromState::Reading {
+                        address: self.block_address(address_bits),
+                        bit: 0
+                    };
+                }
+            } else if self.size == EepromSize::Unknown && self.bit_count >= 67 {
+                // write request where the dma length never told us the size
+                let address_bits = self.bit_count - 67;
+                if address_bits == 6 || address_bits == 14 {
+                    self.finish_write(address_bits);
+                }
+            }
+            self.clear_request();
+        }
+
+        match self.state {
+            EepromState::Reading { address, bit } => {
+                // 4 junk bits followed by 64 data bits, msb first
+                let value = if bit < 4 {
+                    0
+                } else {
+                    let data_bit = bit - 4;
+                    let byte = self.data[address + (data_bit / 8) as usize];
+                    ((byte >> (7 - (data_bit % 8))) & 1) as u16
+                };
+
+                self.state = if bit + 1 == 68 {
+                    EepromState::Idle
+                } else {
+                    EepromState::Reading { address, bit: bit + 1 }
+                };
+
+                value
+            },
+            // ready
+            EepromState::Idle => 1
+        }
+    }
+}
+
+impl Default for Eeprom {
+    fn default() -> Self {
+        Eeprom::new()
+    }
+}
+
+impl MemoryMap {
+    // with more than 16mb of rom the eeprom only takes the last 256 bytes of 0x0D
+    pub fn is_eeprom_address(&self, address: u32) -> bool {
+        if self.backup_type != BackupType::Eeprom || (address >> 24) != 0x0D {
+            return false;
+        }
+        self.memory.borrow().rom.len() <= LARGE_ROM_SIZE || address >= LARGE_ROM_EEPROM_START
+    }
+
+    pub fn read_eeprom(&mut self) -> u16 {
+        self.eeprom.read_bit()
+    }
+
+    pub fn write_eeprom(&mut self, address: u32, value: u8) {
+        // only bit 0 of each halfword is connected
+        if address & 1 == 0 {
+            self.eeprom.write_bit(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::memory_bus::MemoryBus;
+
+    fn eeprom_bus() -> MemoryBus {
+        let mut bus = MemoryBus::new(BackupType::Eeprom);
+        bus.cycle_clock.register(&bus.mem_map.memory);
+        bus
+    }
+
+    fn send_bits(bus: &mut MemoryBus, value: u128, bits: u32) {
+        for i in (0..bits).rev() {
+            bus.write_u16(0x0D00_0000, ((value >> i) & 1) as u16);
+        }
+    }
+
+    fn read_block(bus: &mut MemoryBus, address: u128, address_bits: u32) -> u64 {
+        send_bits(bus, (0b11 << (address_bits + 1)) | (address << 1), address_bits + 3);
+        let mut value = 0u64;
+        for i in 0..68 {
+            let bit = bus.read_u16(0x0D00_0000) & 1;
+            if i >= 4 {
+                value = (value << 1) | bit as u64;
+            } else {
+                assert_eq!(bit, 0);
+            }
+        }
+        value
+    }
+
+    #[test]
+    fn write_then_read_8k() {
+        let mut bus = eeprom_bus();
+        bus.mem_map.eeprom.detect_size(81);
+        let data: u128 = 0x0123_4567_89AB_CDEF;
+        send_bits(&mut bus, (0b10 << 79) | (0x3 << 65) | (data << 1), 81);
+
+        assert_eq!(bus.read_u16(0x0D00_0000) & 1, 1);
+        assert_eq!(read_block(&mut bus, 3, 14), 0x0123_4567_89AB_CDEF);
+        assert_eq!(bus.mem_map.eeprom.data[24], 0x01);
+    }
+
+    #[test]
+    fn size_inferred_without_dma() {
+        let mut bus = eeprom_bus();
+        send_bits(&mut bus, (0b10 << 71) | (0x3F << 65) | (0xAAu128 << 1), 73);
+        assert_eq!(bus.read_u16(0x0D00_0000) & 1, 1);
+        assert_eq!(read_block(&mut bus, 0x3F, 6), 0xAA);
+    }
+
+    #[test]
+    fn large_roms_only_map_the_top_of_0x0d() {
+        let mut bus = eeprom_bus();
+        assert!(bus.mem_map.is_eeprom_address(0x0D00_0000));
+
+        bus.mem_map.memory.borrow_mut().rom = vec![0xAB; LARGE_ROM_SIZE + 0x100];
+        assert!(!bus.mem_map.is_eeprom_address(0x0D00_0000));
+        assert!(!bus.mem_map.is_eeprom_address(0x0DFF_FEFF));
+        assert!(bus.mem_map.is_eeprom_address(0x0DFF_FF00));
+        // the rest of 0x0D is rom again
+        assert_eq!(bus.read_u16(0x0D00_0000), 0xABAB);
+        assert_eq!(bus.read_u16(0x0DFF_FF00) & 1, 1);
+    }
+
+    #[test]
+    fn save_data_matches_detected_size() {
+        let mut eeprom = Eeprom::new();
+        eeprom.detect_size(9);
+        assert_eq!(eeprom.save_data().len(), EEPROM_512_SIZE);
+
+        let mut eeprom = Eeprom::new();
+        eeprom.load(&vec![0x12; EEPROM_8K_SIZE]);
+        assert_eq!(eeprom.size, EepromSize::Size8K);
+        assert_eq!(eeprom.save_data(), vec![0x12; EEPROM_8K_SIZE]);
+    }
+}
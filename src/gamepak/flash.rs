@@ -1,6 +1,7 @@
 use crate::memory::memory_map::MemoryMap;
 use crate::gamepak::BackupType;
 use serde::{Serialize, Deserialize};
+use std::convert::TryFrom;
 
 #[derive(Serialize, Deserialize)]
 pub enum FlashCommands {
@@ -13,17 +14,19 @@ pub enum FlashCommands {
     SelectBank = 0xb0
 }
 
-impl FlashCommands {
-    pub fn from(value: u8) -> FlashCommands {
+impl TryFrom<u8> for FlashCommands {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<FlashCommands, u8> {
         match value {
-            0x90 => FlashCommands::StartID,
-            0xF0 => FlashCommands::EndID,
-            0x80 => FlashCommands::EnableErase,
-            0x10 => FlashCommands::EraseChip,
-            0x30 => FlashCommands::EraseSector,
-            0xA0 => FlashCommands::WriteByte,
-            0xB0 => FlashCommands::SelectBank,
-            _ => panic!("Invalid flash command")
+            0x90 => Ok(FlashCommands::StartID),
+            0xF0 => Ok(FlashCommands::EndID),
+            0x80 => Ok(FlashCommands::EnableErase),
+            0x10 => Ok(FlashCommands::EraseChip),
+            0x30 => Ok(FlashCommands::EraseSector),
+            0xA0 => Ok(FlashCommands::WriteByte),
+            0xB0 => Ok(FlashCommands::SelectBank),
+            _ => Err(value)
         }
     }
 }
@@ -98,7 +101,15 @@ impl MemoryMap {
     }
 
     fn run_command(&mut self, address: u32, value: u8) {
-        let flash_command = FlashCommands::from(value);
+        let flash_command = match FlashCommands::try_from(value) {
+            Ok(command) => command,
+            Err(value) => {
+                // the chip drops back to waiting for a new command sequence
+                log::info!("Ignoring invalid flash command: {:X}", value);
+                self.flash.phase = FlashPhase::Phase1;
+                return;
+            }
+        };
 
         if address == 0x0E005555 || (address & !0xF000) == 0x0E000000 {
             
@@ -3,6 +3,8 @@ use std::fs::File;
 use serde::{Serialize, Deserialize};
 
 pub mod flash;
+pub mod eeprom;
+pub mod rtc;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
 pub enum BackupType {
@@ -0,0 +1,493 @@
+use crate::memory::memory_map::MemoryMap;
+use serde::{Serialize, Deserialize};
+
+pub const GPIO_DATA_ADDRESS: u32 = 0x080000C4;
+pub const GPIO_DIRECTION_ADDRESS: u32 = 0x080000C6;
+pub const GPIO_CONTROL_ADDRESS: u32 = 0x080000C8;
+
+// gpio pins the S-3511 is wired to
+const PIN_SCK: u8 = 0x1;
+const PIN_SIO: u8 = 0x2;
+const PIN_CS: u8 = 0x4;
+
+const COMMAND_RESET: u8 = 0;
+const COMMAND_STATUS: u8 = 1;
+const COMMAND_DATE_TIME: u8 = 2;
+const COMMAND_TIME: u8 = 3;
+
+const STATUS_24_HOUR: u8 = 0x40;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+// 2000-01-01 00:00:00, what the clock reads after a reset
+const RESET_TIMESTAMP: i64 = 946_684_800;
+
+// Anything that can tell the rtc the current time, as seconds since the unix epoch.
+pub trait TimeSource {
+    fn now(&self) -> i64;
+}
+
+// SystemTime::now panics on wasm32-unknown-unknown, so the host clock is only there natively
+#[cfg(not(target_arch = "wasm32"))]
+pub struct SystemClock;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TimeSource for SystemClock {
+    fn now(&self) -> i64 {
+        match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs() as i64,
+            Err(_) => 0
+        }
+    }
+}
+
+pub struct FixedClock(pub i64);
+
+impl TimeSource for FixedClock {
+    fn now(&self) -> i64 {
+        self.0
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_time_source() -> Box<dyn TimeSource> {
+    Box::new(SystemClock)
+}
+
+// the browser frontend is expected to supply its own time source, until then the clock reads
+// as if it was just reset
+#[cfg(target_arch = "wasm32")]
+fn default_time_source() -> Box<dyn TimeSource> {
+    Box::new(FixedClock(RESET_TIMESTAMP))
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+enum RtcState {
+    Idle,
+    Command,
+    Writing,
+    Reading
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u8,
+    pub day: u8,
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8
+}
+
+impl DateTime {
+    pub fn from_timestamp(timestamp: i64) -> DateTime {
+        let days = timestamp.div_euclid(SECONDS_PER_DAY);
+        let seconds = timestamp.rem_euclid(SECONDS_PER_DAY);
+
+        // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year,
+            month,
+            day,
+            // 1970-01-01 was a thursday, sunday is 0
+            weekday: (days + 4).rem_euclid(7) as u8,
+            hour: (seconds / 3600) as u8,
+            minute: ((seconds / 60) % 60) as u8,
+            second: (seconds % 60) as u8
+        }
+    }
+
+    pub fn to_timestamp(&self) -> i64 {
+        let year = if self.month <= 2 { self.year - 1 } else { self.year };
+        let month = self.month as i64;
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+
+        days * SECONDS_PER_DAY + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+}
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0xF)
+}
+
+// Seiko S-3511 real time clock, talked to by bit banging the cartridge gpio port.
+// Commands are sent msb first, parameter bytes lsb first.
+#[derive(Serialize, Deserialize)]
+pub struct Rtc {
+    pins: u8,
+    direction: u8,
+    readable: bool,
+    state: RtcState,
+    command: u8,
+    shift: u8,
+    bit_index: u32,
+    data: [u8; 7],
+    byte_index: usize,
+    length: usize,
+    pub status: u8,
+    // seconds between the time source and the time the game set
+    pub offset: i64,
+    #[serde(skip, default = "default_time_source")]
+    time_source: Box<dyn TimeSource>
+}
+
+impl Rtc {
+    pub fn new() -> Rtc {
+        Rtc {
+            pins: 0,
+            direction: 0,
+            readable: false,
+            state: RtcState::Idle,
+            command: 0,
+            shift: 0,
+            bit_index: 0,
+            data: [0; 7],
+            byte_index: 0,
+            length: 0,
+            status: STATUS_24_HOUR,
+            offset: 0,
+            time_source: default_time_source()
+        }
+    }
+
+    pub fn set_time_source(&mut self, time_source: Box<dyn TimeSource>) {
+        self.time_source = time_source;
+    }
+
+    pub fn take_time_source(&mut self) -> Box<dyn TimeSource> {
+        std::mem::replace(&mut self.time_source, default_time_source())
+    }
+
+    pub fn date_time(&self) -> DateTime {
+        DateTime::from_timestamp(self.time_source.now() + self.offset)
+    }
+
+    fn set_date_time(&mut self, date_time: DateTime) {
+        self.offset = date_time.to_timestamp() - self.time_source.now();
+    }
+
+    fn encode_hour(&self, hour: u8) -> u8 {
+        let pm = if hour >= 12 { 0x80 } else { 0 };
+        if self.status & STATUS_24_HOUR != 0 {
+            to_bcd(hour) | pm
+        } else {
+            to_bcd(hour % 12) | pm
+        }
+    }
+
+    fn decode_hour(&self, value: u8) -> u8 {
+        let hour = from_bcd(value & 0x3F);
+        if self.status & STATUS_24_HOUR == 0 && value & 0x80 != 0 {
+            (hour % 12) + 12
+        } else {
+            hour
+        }
+    }
+
+    fn encode_date_time(&mut self) {
+        let now = self.date_time();
+        self.data = [
+            to_bcd((now.year - 2000).clamp(0, 99) as u8),
+            to_bcd(now.month),
+            to_bcd(now.day),
+            to_bcd(now.weekday),
+            self.encode_hour(now.hour),
+            to_bcd(now.minute),
+            to_bcd(now.second)
+        ];
+    }
+
+    fn start_transfer(&mut self, length: usize, reading: bool) {
+        self.length = length;
+        self.byte_index = 0;
+        self.shift = 0;
+        self.state = if length == 0 {
+            RtcState::Idle
+        } else if reading {
+            RtcState::Reading
+        } else {
+            RtcState::Writing
+        };
+    }
+
+    fn process_command(&mut self, value: u8) {
+        if value >> 4 != 0x6 {
+            log::info!("Invalid rtc command: {:X}", value);
+            self.state = RtcState::Idle;
+            return;
+        }
+
+        self.command = (value >> 1) & 0x7;
+        let reading = value & 1 != 0;
+        match self.command {
+            COMMAND_RESET => {
+                self.status = 0;
+                self.offset = RESET_TIMESTAMP - self.time_source.now();
+                self.start_transfer(0, reading);
+            },
+            COMMAND_STATUS => {
+                self.data[0] = self.status;
+                self.start_transfer(1, reading);
+            },
+            COMMAND_DATE_TIME => {
+                self.encode_date_time();
+                self.start_transfer(7, reading);
+            },
+            COMMAND_TIME => {
+                self.encode_date_time();
+                self.data.copy_within(4..7, 0);
+                self.start_transfer(3, reading);
+            },
+            _ => {
+                // alarm and interrupt settings aren't used by any game
+                self.start_transfer(0, reading);
+            }
+        }
+    }
+
+    fn finish_write(&mut self) {
+        match self.command {
+            COMMAND_STATUS => {
+                self.status = self.data[0];
+            },
+            COMMAND_DATE_TIME | COMMAND_TIME => {
+                let mut date_time = self.date_time();
+                let time = if self.command == COMMAND_DATE_TIME {
+                    date_time.year = 2000 + from_bcd(self.data[0]) as i64;
+                    date_time.month = from_bcd(self.data[1] & 0x1F).clamp(1, 12);
+                    date_time.day = from_bcd(self.data[2] & 0x3F).clamp(1, 31);
+                    &self.data[4..7]
+                } else {
+                    &self.data[0..3]
+                };
+
+                date_time.hour = self.decode_hour(time[0]);
+                date_time.minute = from_bcd(time[1] & 0x7F);
+                date_time.second = from_bcd(time[2] & 0x7F);
+                self.set_date_time(date_time);
+            },
+            _ => {}
+        }
+    }
+
+    // returns the bit the rtc puts on SIO when it is being read
+    fn clock_bit(&mut self, bit: u8) -> Option<u8> {
+        match self.state {
+            RtcState::Idle => None,
+            RtcState::Command => {
+                self.shift = (self.shift << 1) | bit;
+                self.bit_index += 1;
+                if self.bit_index == 8 {
+                    self.bit_index = 0;
+                    self.process_command(self.shift);
+                }
+                None
+            },
+            RtcState::Writing => {
+                self.shift |= bit << self.bit_index;
+                self.bit_index += 1;
+                if self.bit_index == 8 {
+                    self.data[self.byte_index] = self.shift;
+                    self.shift = 0;
+                    self.bit_index = 0;
+                    self.byte_index += 1;
+                    if self.byte_index == self.length {
+                        self.finish_write();
+                        self.state = RtcState::Idle;
+                    }
+                }
+                None
+            },
+            RtcState::Reading => {
+                let out = (self.data[self.byte_index] >> self.bit_index) & 1;
+                self.bit_index += 1;
+                if self.bit_index == 8 {
+                    self.bit_index = 0;
+                    self.byte_index += 1;
+                    if self.byte_index == self.length {
+                        self.state = RtcState::Idle;
+                    }
+                }
+                Some(out)
+            }
+        }
+    }
+
+    pub fn write_pins(&mut self, value: u8) {
+        // pins set as inputs keep whatever the rtc is driving them with
+        let mut pins = ((value & self.direction) | (self.pins & !self.direction)) & 0xF;
+        let previous = self.pins;
+
+        if pins & PIN_CS == 0 {
+            self.state = RtcState::Idle;
+        } else if previous & PIN_CS == 0 {
+            self.state = RtcState::Command;
+            self.shift = 0;
+            self.bit_index = 0;
+        }
+
+        // data is latched on the rising edge of SCK
+        if pins & PIN_CS != 0 && previous & PIN_SCK == 0 && pins & PIN_SCK != 0 {
+            if let Some(out) = self.clock_bit((pins & PIN_SIO) >> 1) {
+                if self.direction & PIN_SIO == 0 {
+                    pins = (pins & !PIN_SIO) | (out << 1);
+                }
+            }
+        }
+
+        self.pins = pins;
+    }
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Rtc::new()
+    }
+}
+
+impl MemoryMap {
+    pub fn is_gpio_address(address: u32) -> bool {
+        (GPIO_DATA_ADDRESS..GPIO_CONTROL_ADDRESS + 2).contains(&address)
+    }
+
+    // the port reads back as rom unless the game has made it readable
+    pub fn read_gpio(&self, address: u32) -> Option<u8> {
+        if !self.rtc.readable {
+            return None;
+        }
+
+        match address {
+            GPIO_DATA_ADDRESS => Some(self.rtc.pins),
+            GPIO_DIRECTION_ADDRESS => Some(self.rtc.direction),
+            GPIO_CONTROL_ADDRESS => Some(self.rtc.readable as u8),
+            _ => Some(0)
+        }
+    }
+
+    pub fn write_gpio(&mut self, address: u32, value: u8) {
+        match address {
+            GPIO_DATA_ADDRESS => self.rtc.write_pins(value),
+            GPIO_DIRECTION_ADDRESS => self.rtc.direction = value & 0xF,
+            GPIO_CONTROL_ADDRESS => self.rtc.readable = value & 1 != 0,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gba::GBA;
+
+    // 2024-02-29 13:45:30, a thursday
+    const TEST_TIMESTAMP: i64 = 1_709_214_330;
+
+    fn setup() -> GBA {
+        let mut gba: GBA = GBA::default();
+        gba.set_rtc_time_source(Box::new(FixedClock(TEST_TIMESTAMP)));
+        gba.memory_bus.write_u16(GPIO_CONTROL_ADDRESS, 1);
+        gba
+    }
+
+    fn begin(gba: &mut GBA, command: u8) {
+        gba.memory_bus.write_u16(GPIO_DIRECTION_ADDRESS, 0x7);
+        gba.memory_bus.write_u16(GPIO_DATA_ADDRESS, PIN_SCK as u16);
+        gba.memory_bus.write_u16(GPIO_DATA_ADDRESS, (PIN_SCK | PIN_CS) as u16);
+        for i in (0..8).rev() {
+            let bit = ((command >> i) & 1) << 1;
+            gba.memory_bus.write_u16(GPIO_DATA_ADDRESS, (PIN_CS | bit) as u16);
+            gba.memory_bus.write_u16(GPIO_DATA_ADDRESS, (PIN_CS | PIN_SCK | bit) as u16);
+        }
+    }
+
+    fn write_byte(gba: &mut GBA, value: u8) {
+        for i in 0..8 {
+            let bit = ((value >> i) & 1) << 1;
+            gba.memory_bus.write_u16(GPIO_DATA_ADDRESS, (PIN_CS | bit) as u16);
+            gba.memory_bus.write_u16(GPIO_DATA_ADDRESS, (PIN_CS | PIN_SCK | bit) as u16);
+        }
+    }
+
+    fn read_byte(gba: &mut GBA) -> u8 {
+        gba.memory_bus.write_u16(GPIO_DIRECTION_ADDRESS, (PIN_SCK | PIN_CS) as u16);
+        let mut value = 0;
+        for i in 0..8 {
+            gba.memory_bus.write_u16(GPIO_DATA_ADDRESS, PIN_CS as u16);
+            gba.memory_bus.write_u16(GPIO_DATA_ADDRESS, (PIN_CS | PIN_SCK) as u16);
+            let bit = (gba.memory_bus.read_u16(GPIO_DATA_ADDRESS) as u8 & PIN_SIO) >> 1;
+            value |= bit << i;
+        }
+        value
+    }
+
+    fn end(gba: &mut GBA) {
+        gba.memory_bus.write_u16(GPIO_DIRECTION_ADDRESS, 0x7);
+        gba.memory_bus.write_u16(GPIO_DATA_ADDRESS, PIN_SCK as u16);
+    }
+
+    #[test]
+    fn timestamp_round_trip() {
+        let date_time = DateTime::from_timestamp(TEST_TIMESTAMP);
+        assert_eq!(date_time, DateTime { year: 2024, month: 2, day: 29, weekday: 4, hour: 13, minute: 45, second: 30 });
+        assert_eq!(date_time.to_timestamp(), TEST_TIMESTAMP);
+        assert_eq!(DateTime::from_timestamp(RESET_TIMESTAMP).year, 2000);
+    }
+
+    #[test]
+    fn read_date_time() {
+        let mut gba = setup();
+        begin(&mut gba, 0x65);
+        let bytes: Vec<u8> = (0..7).map(|_| read_byte(&mut gba)).collect();
+        end(&mut gba);
+        assert_eq!(bytes, vec![0x24, 0x02, 0x29, 0x04, 0x93, 0x45, 0x30]);
+    }
+
+    #[test]
+    fn write_time_then_status() {
+        let mut gba = setup();
+        begin(&mut gba, 0x66);
+        for value in [0x08, 0x15, 0x00].iter() {
+            write_byte(&mut gba, *value);
+        }
+        end(&mut gba);
+
+        let now = gba.memory_bus.mem_map.rtc.date_time();
+        assert_eq!((now.day, now.hour, now.minute, now.second), (29, 8, 15, 0));
+
+        begin(&mut gba, 0x63);
+        assert_eq!(read_byte(&mut gba), STATUS_24_HOUR);
+        end(&mut gba);
+
+        begin(&mut gba, 0x60);
+        end(&mut gba);
+        assert_eq!(gba.memory_bus.mem_map.rtc.date_time().year, 2000);
+        assert_eq!(gba.memory_bus.mem_map.rtc.status, 0);
+    }
+
+    #[test]
+    fn port_reads_as_rom_until_readable() {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.mem_map.write_block(GPIO_DATA_ADDRESS, &vec![0xAB, 0xCD]);
+        assert_eq!(gba.memory_bus.read_u16(GPIO_DATA_ADDRESS), 0xCDAB);
+        gba.memory_bus.write_u16(GPIO_CONTROL_ADDRESS, 1);
+        assert_eq!(gba.memory_bus.read_u16(GPIO_DATA_ADDRESS), 0);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::cpu::cpu::{CPU, InstructionSet, OperatingMode};
+use std::error;
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Subsystem {
+    Cpu,
+    Dma
+}
+
+// Where the emulator was when something went wrong.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ErrorContext {
+    pub subsystem: Subsystem,
+    pub pc: u32,
+    pub instruction: u32,
+    pub instruction_set: InstructionSet,
+    pub mode_bits: u8
+}
+
+impl ErrorContext {
+    pub fn new(subsystem: Subsystem) -> ErrorContext {
+        ErrorContext {
+            subsystem,
+            pc: 0,
+            instruction: 0,
+            instruction_set: InstructionSet::Arm,
+            mode_bits: 0
+        }
+    }
+
+    // reads the raw cpsr bits so it is safe to call when the mode is what went wrong
+    pub fn from_cpu(cpu: &CPU, subsystem: Subsystem) -> ErrorContext {
+        ErrorContext {
+            subsystem,
+            pc: cpu.instruction_address,
+            instruction: cpu.instruction,
+            instruction_set: cpu.get_instruction_set(),
+            mode_bits: cpu.cpsr.control_bits.mode_bits
+        }
+    }
+
+    pub fn mode(&self) -> Option<OperatingMode> {
+        CPU::operating_mode_from_bits(self.mode_bits)
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode() {
+            Some(mode) => write!(f, "{:?} at PC {:X} ({:?} {:X}, {:?} mode)", self.subsystem, self.pc, self.instruction_set, self.instruction, mode),
+            None => write!(f, "{:?} at PC {:X} ({:?} {:X}, mode bits {:b})", self.subsystem, self.pc, self.instruction_set, self.instruction, self.mode_bits)
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum GbaError {
+    InvalidMode {
+        mode_bits: u8,
+        context: ErrorContext
+    },
+    InvalidDmaControl {
+        channel: usize,
+        control: u32,
+        context: ErrorContext
+    }
+}
+
+impl GbaError {
+    pub fn context(&self) -> &ErrorContext {
+        match self {
+            GbaError::InvalidMode { context, .. } => context,
+            GbaError::InvalidDmaControl { context, .. } => context
+        }
+    }
+
+    // subsystems outside the cpu don't know where it was, this fills that in
+    pub fn with_cpu(mut self, cpu: &CPU) -> GbaError {
+        let context = match &mut self {
+            GbaError::InvalidMode { context, .. } => context,
+            GbaError::InvalidDmaControl { context, .. } => context
+        };
+        *context = ErrorContext::from_cpu(cpu, context.subsystem);
+        self
+    }
+}
+
+impl fmt::Display for GbaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GbaError::InvalidMode { mode_bits, context } => write!(f, "Invalid mode bits {:b}: {}", mode_bits, context),
+            GbaError::InvalidDmaControl { channel, control, context } => write!(f, "Invalid control {:X} for DMA {}: {}", control, channel, context)
+        }
+    }
+}
+
+impl error::Error for GbaError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gba::GBA;
+    use crate::cpu::cpu::{ARM_PC, ARM_LR};
+
+    #[test]
+    fn undefined_instruction_takes_vector() {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.mem_map.write_block(0x0800_0000, &0xE7F0_00F0u32.to_le_bytes().to_vec());
+        gba.cpu.set_register(ARM_PC, 0x0800_0000);
+
+        assert!(gba.try_single_step().is_ok());
+        assert_eq!(gba.cpu.get_operating_mode(), OperatingMode::Undefined);
+        assert_eq!(gba.cpu.get_register(ARM_PC), 0x04);
+        assert_eq!(gba.cpu.get_register(ARM_LR), 0x0800_0004);
+        assert!(gba.cpu.cpsr.control_bits.irq_disable);
+    }
+
+    #[test]
+    fn invalid_mode_is_an_error() {
+        let mut gba: GBA = GBA::default();
+        gba.cpu.cpsr.control_bits.mode_bits = 0;
+
+        match gba.try_single_step() {
+            Err(GbaError::InvalidMode { mode_bits, context }) => {
+                assert_eq!(mode_bits, 0);
+                assert_eq!(context.subsystem, Subsystem::Cpu);
+                assert_eq!(context.mode(), None);
+            },
+            _ => panic!("expected an invalid mode error")
+        }
+    }
+
+    #[test]
+    fn dma_error_carries_cpu_context() {
+        let mut gba: GBA = GBA::default();
+        gba.cpu.set_register(ARM_PC, 0x0800_0000);
+        gba.memory_bus.write_u16(0x40000DC, 1);
+        // enabled, immediate, prohibited source control
+        gba.memory_bus.write_u16(0x40000DE, 0x8180);
+
+        let error = gba.try_single_step().unwrap_err();
+        assert!(matches!(error, GbaError::InvalidDmaControl { channel: 3, .. }));
+        assert_eq!(error.context().subsystem, Subsystem::Dma);
+        assert_eq!(error.context().pc, 0x0800_0000);
+        assert_eq!(error.context().mode(), Some(OperatingMode::Supervisor));
+    }
+
+    #[test]
+    fn dma_addresses_wrap_below_zero() {
+        let mut gba: GBA = GBA::default();
+        gba.cpu.set_register(ARM_PC, 0x0800_0000);
+        gba.memory_bus.write_u32(0x40000D4, 0);
+        gba.memory_bus.write_u32(0x40000D8, 0x0200_0000);
+        gba.memory_bus.write_u16(0x40000DC, 2);
+        // enabled, immediate, 32 bit, source decrement
+        gba.memory_bus.write_u16(0x40000DE, 0x8480);
+
+        assert!(gba.try_single_step().is_ok());
+    }
+}
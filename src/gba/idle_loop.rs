@@ -0,0 +1,243 @@
+use crate::cpu::cpu::{CPU, InstructionSet};
+use crate::memory::memory_map::MemoryMap;
+use std::ops::Range;
+
+const IO_REGISTERS: Range<u32> = 0x4000000..0x4000400;
+// the timer counters change every cycle, a loop waiting on one has to actually run
+const TIMER_COUNTERS: Range<u32> = 0x4000100..0x4000110;
+
+// a load that doesn't write back, (destination, base, signed offset)
+struct Load {
+    rd: u8,
+    rn: u8,
+    offset: i32
+}
+
+// true when the cpu just took a branch back into a loop like
+//     loop: ldrh r1, [r0, #4]
+//           cmp  r1, #160
+//           bne  loop
+// polling an io register. nothing in it writes memory or a register the load depends on, so it
+// spins until the gpu, a timer, a dma or an interrupt changes the register
+pub fn is_idle_loop(cpu: &CPU, mem_map: &MemoryMap) -> bool {
+    let thumb = cpu.get_instruction_set() == InstructionSet::Thumb;
+    let width = if thumb { 2 } else { 4 };
+    let start = cpu.get_pc();
+    if cpu.instruction_address.wrapping_sub(start) != 2 * width {
+        return false;
+    }
+
+    let load = if thumb {
+        if !is_thumb_conditional_branch(cpu.instruction as u16) {
+            return false;
+        }
+        match (thumb_load(mem_map.read_u16(start)), mem_map.read_u16(start + 2)) {
+            (Some(load), compare) if is_thumb_compare(compare, load.rd) => load,
+            _ => return false
+        }
+    } else {
+        if !is_arm_conditional_branch(cpu.instruction) {
+            return false;
+        }
+        match (arm_load(mem_map.read_u32(start)), mem_map.read_u32(start + 4)) {
+            (Some(load), compare) if is_arm_compare(compare, load.rd) => load,
+            _ => return false
+        }
+    };
+
+    // the loaded register can't also be the base, the address would move every iteration
+    if load.rd == load.rn || load.rn == 15 {
+        return false;
+    }
+    let address = cpu.get_register(load.rn).wrapping_add(load.offset as u32);
+    IO_REGISTERS.contains(&address) && !TIMER_COUNTERS.contains(&address)
+}
+
+fn is_thumb_conditional_branch(opcode: u16) -> bool {
+    let condition = (opcode >> 8) & 0xF;
+    opcode & 0xF000 == 0xD000 && condition < 0xE
+}
+
+fn thumb_load(opcode: u16) -> Option<Load> {
+    let offset = ((opcode >> 6) & 0x1F) as i32;
+    let offset = match opcode & 0xF800 {
+        0x6800 => offset * 4, // ldr
+        0x7800 => offset,     // ldrb
+        0x8800 => offset * 2, // ldrh
+        _ => return None
+    };
+    Some(Load { rd: (opcode & 0x7) as u8, rn: ((opcode >> 3) & 0x7) as u8, offset })
+}
+
+// cmp rd, #imm or a tst, cmp or cmn of rd against another register
+fn is_thumb_compare(opcode: u16, rd: u8) -> bool {
+    if opcode & 0xF800 == 0x2800 {
+        return ((opcode >> 8) & 0x7) as u8 == rd;
+    }
+    let alu_op = (opcode >> 6) & 0xF;
+    opcode & 0xFC00 == 0x4000 && (alu_op == 0x8 || alu_op == 0xA || alu_op == 0xB) && (opcode & 0x7) as u8 == rd
+}
+
+fn is_arm_conditional_branch(opcode: u32) -> bool {
+    opcode & 0x0F00_0000 == 0x0A00_0000 && (opcode >> 28) < 0xE
+}
+
+fn arm_load(opcode: u32) -> Option<Load> {
+    if opcode >> 28 != 0xE {
+        return None;
+    }
+
+    let offset = if opcode & 0x0F30_0000 == 0x0510_0000 {
+        // ldr/ldrb with an immediate offset
+        (opcode & 0xFFF) as i32
+    } else if opcode & 0x0F70_0090 == 0x0150_0090 && opcode & 0x60 != 0 {
+        // ldrh/ldrsb/ldrsh with an immediate offset
+        (((opcode >> 4) & 0xF0) | (opcode & 0xF)) as i32
+    } else {
+        return None;
+    };
+    let offset = if opcode & (1 << 23) != 0 { offset } else { -offset };
+    Some(Load { rd: ((opcode >> 12) & 0xF) as u8, rn: ((opcode >> 16) & 0xF) as u8, offset })
+}
+
+// tst, teq, cmp or cmn of rd against an immediate or a register shifted by an immediate
+fn is_arm_compare(opcode: u32, rd: u8) -> bool {
+    let immediate = opcode & (1 << 25) != 0;
+    opcode >> 28 == 0xE
+        && opcode & 0x0D90_0000 == 0x0110_0000
+        && (immediate || opcode & 0x10 == 0)
+        && ((opcode >> 16) & 0xF) as u8 == rd
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gba::GBA;
+    use crate::cpu::cpu::{InstructionSet, ARM_PC, THUMB_PC};
+    use crate::memory::memory_map::HaltState;
+
+    fn load_halfwords(gba: &mut GBA, address: u32, halfwords: &[u16]) {
+        let bytes: Vec<u8> = halfwords.iter().flat_map(|halfword| halfword.to_le_bytes().to_vec()).collect();
+        gba.memory_bus.mem_map.write_block(address, &bytes);
+    }
+
+    fn load_words(gba: &mut GBA, address: u32, words: &[u32]) {
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect();
+        gba.memory_bus.mem_map.write_block(address, &bytes);
+    }
+
+    // mov r0, #0x04000000; loop: ldrh r1, [r0, #6]; cmp r1, #160; bne loop
+    fn vcount_loop(idle_loop_skip: bool) -> GBA {
+        let mut gba: GBA = GBA::default();
+        load_words(&mut gba, 0x0800_0000, &[0xE3A0_0301, 0xE1D0_10B6, 0xE351_00A0, 0x1AFF_FFFC]);
+        gba.cpu.set_register(ARM_PC, 0x0800_0000);
+        gba.set_idle_loop_skip(idle_loop_skip);
+        gba
+    }
+
+    fn steps_to_exit(gba: &mut GBA) -> usize {
+        let mut steps = 0;
+        while gba.cpu.get_pc() != 0x0800_0010 {
+            gba.single_step();
+            steps += 1;
+        }
+        steps
+    }
+
+    #[test]
+    fn polling_loops_are_fast_forwarded() {
+        let mut slow = vcount_loop(false);
+        let mut fast = vcount_loop(true);
+
+        let slow_steps = steps_to_exit(&mut slow);
+        let fast_steps = steps_to_exit(&mut fast);
+        assert!(fast_steps * 10 < slow_steps, "{} steps with skipping, {} without", fast_steps, slow_steps);
+        assert_eq!(slow.gpu.vertical_count.get_current_scanline(), 160);
+        assert_eq!(fast.gpu.vertical_count.get_current_scanline(), 160);
+    }
+
+    #[test]
+    fn loops_on_ram_or_timers_are_not_idle() {
+        // mov r0, #0x03000000; loop: ldrh r1, [r0, #6]; cmp r1, #160; bne loop
+        let mut gba = vcount_loop(true);
+        load_words(&mut gba, 0x0800_0000, &[0xE3A0_0403]);
+        gba.single_step();
+        for _ in 0..3 {
+            gba.single_step();
+        }
+        assert!(!super::is_idle_loop(&gba.cpu, &gba.memory_bus.mem_map));
+
+        // mov r0, #0x04000000; loop: ldr r1, [r0, #0x100]; ...
+        let mut gba = vcount_loop(true);
+        load_words(&mut gba, 0x0800_0004, &[0xE590_1100]);
+        gba.single_step();
+        for _ in 0..3 {
+            gba.single_step();
+        }
+        assert!(!super::is_idle_loop(&gba.cpu, &gba.memory_bus.mem_map));
+    }
+
+    #[test]
+    fn thumb_polling_loops_are_detected() {
+        let mut gba: GBA = GBA::default();
+        // loop: ldrh r1, [r0, #4]; cmp r1, #0; beq loop
+        load_halfwords(&mut gba, 0x0800_0000, &[0x8881, 0x2900, 0xD0FC]);
+        gba.cpu.set_instruction_set(InstructionSet::Thumb);
+        gba.cpu.set_register(THUMB_PC, 0x0800_0000);
+        gba.cpu.set_register(0, 0x0400_0000);
+        for _ in 0..3 {
+            gba.single_step();
+        }
+        assert_eq!(gba.cpu.get_pc(), 0x0800_0000);
+        assert!(super::is_idle_loop(&gba.cpu, &gba.memory_bus.mem_map));
+    }
+
+    #[test]
+    fn halt_stops_at_the_first_timer_overflow() {
+        let mut gba: GBA = GBA::default();
+        // timer 0 overflows after 0x100 cycles and raises its interrupt
+        gba.timer_handler.timers[0].timer.write_reload(0xFF00);
+        gba.timer_handler.timers[0].controller.set_register(0xC0);
+        gba.interrupt_handler.ie_interrupt.set_timer_zero_overflow(1);
+        gba.memory_bus.mem_map.halt_state = HaltState::Halt;
+
+        gba.single_step();
+        assert_eq!(gba.interrupt_handler.if_interrupt.get_timer_zero_overflow(), 1);
+        assert_eq!(gba.memory_bus.mem_map.halt_state, HaltState::Running);
+        assert_eq!(gba.timer_handler.timers[0].timer.get_data(), 0xFF00);
+    }
+
+    #[test]
+    fn leftover_prescaler_cycles_overflow_right_away() {
+        let mut gba: GBA = GBA::default();
+        // cycles counted under the 1024 prescaler, then switched down to 1
+        let timer = &mut gba.timer_handler.timers[0];
+        timer.timer.set_data(0xFFFF);
+        timer.controller.set_register(0x80);
+        timer.previously_disabled = false;
+        timer.cycles = 1000;
+        assert_eq!(timer.cycles_to_overflow(), Some(0));
+    }
+
+    #[test]
+    fn stop_freezes_the_clocks_until_a_cartridge_interrupt() {
+        let mut gba: GBA = GBA::default();
+        gba.timer_handler.timers[0].controller.set_register(0x80);
+        gba.interrupt_handler.ie_interrupt.set_lcd_v_blank(1);
+        gba.interrupt_handler.ie_interrupt.set_game_pack(1);
+        gba.memory_bus.mem_map.halt_state = HaltState::Stop;
+
+        let state = gba.gpu.cycles_to_next_state;
+        gba.frame();
+        gba.single_step();
+        assert_eq!(gba.gpu.cycles_to_next_state, state);
+        assert_eq!(gba.timer_handler.timers[0].timer.get_data(), 0);
+
+        // a pending vblank doesn't end stop, the cartridge interrupt does
+        gba.interrupt_handler.if_interrupt.set_lcd_v_blank(1);
+        gba.single_step();
+        assert_eq!(gba.memory_bus.mem_map.halt_state, HaltState::Stop);
+        gba.interrupt_handler.if_interrupt.set_game_pack(1);
+        gba.single_step();
+        assert_eq!(gba.memory_bus.mem_map.halt_state, HaltState::Running);
+    }
+}
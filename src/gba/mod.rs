@@ -1,13 +1,23 @@
 use crate::cpu::{cpu::CPU, cpu::OperatingMode, cpu::ARM_SP, cpu::ARM_PC};
-use crate::gpu::{gpu::GPU, gpu::DISPLAY_WIDTH, gpu::DISPLAY_HEIGHT};
+use crate::gpu::gpu::GPU;
 use crate::gpu::rgb15::Rgb15;
 use crate::memory::{key_input_registers::*};
 use crate::memory::{memory_bus::MemoryBus, memory_map::HaltState};
 use crate::interrupts::interrupts::Interrupts;
 use crate::dma::DMAController;
 use crate::timers::timer::TimerHandler;
-use crate::{gamepak::GamePack, gamepak::BackupType};
+use crate::apu::apu::APU;
+use crate::bios::hle;
+use crate::keypad::input::{Button, Buttons, InputEvent, Keypad};
+use crate::{gamepak::GamePack, gamepak::BackupType, gamepak::rtc::TimeSource};
 use serde::{Serialize, Deserialize};
+use error::GbaError;
+
+pub mod error;
+pub mod save_state;
+pub mod idle_loop;
+#[cfg(test)]
+pub mod test_programs;
 
 #[derive(Serialize, Deserialize)]
 pub struct GBA {
@@ -18,7 +28,15 @@ pub struct GBA {
     pub ket_interrupt_control: KeyInterruptControl,
     pub interrupt_handler: Interrupts,
     pub timer_handler: TimerHandler,
-    pub dma_control: DMAController
+    pub dma_control: DMAController,
+    pub apu: APU,
+    pub keypad: Keypad,
+    // identifies the game a save state belongs to, the rom itself isn't part of the state
+    #[serde(skip)]
+    pub rom_hash: u64,
+    // fast forwards loops polling an io register, a host setting so it isn't saved
+    #[serde(skip)]
+    pub idle_loop_skip: bool
 }
 
 impl Default for GBA {
@@ -40,7 +58,11 @@ impl GBA {
             ket_interrupt_control: KeyInterruptControl::new(),
             interrupt_handler: Interrupts::new(),
             timer_handler: TimerHandler::new(),
-            dma_control: DMAController::new()
+            dma_control: DMAController::new(),
+            apu: APU::new(),
+            keypad: Keypad::new(),
+            rom_hash: 0,
+            idle_loop_skip: false
         };
 
         temp.register_memory();
@@ -97,13 +119,34 @@ impl GBA {
         self.timer_handler.register(&self.memory_bus.mem_map.memory);
         self.memory_bus.cycle_clock.register(&self.memory_bus.mem_map.memory);
         self.dma_control.register(&self.memory_bus.mem_map.memory);
+        self.apu.register(&self.memory_bus.mem_map.memory);
     }
 
     pub fn load_bios(&mut self, bios: &Vec<u8>) {
         self.memory_bus.mem_map.write_block(0, bios)
     }
 
+    // bios calls get run in rust instead of jumping into the bios
+    pub fn enable_hle_bios(&mut self) {
+        self.cpu.hle_bios = true;
+        hle::install_irq_handler(&mut self.memory_bus);
+    }
+
+    // skips the boot animation and starts at the cartridge entry point, without a bios image
+    // the bios calls are emulated
+    pub fn direct_boot(&mut self) {
+        if !hle::has_bios_image(&self.memory_bus) {
+            self.enable_hle_bios();
+        }
+        hle::direct_boot(&mut self.cpu, &mut self.memory_bus);
+    }
+
+    pub fn set_idle_loop_skip(&mut self, enabled: bool) {
+        self.idle_loop_skip = enabled;
+    }
+
     pub fn load_rom(&mut self, rom: &Vec<u8>) {
+        self.rom_hash = save_state::hash_rom(rom);
         self.memory_bus.mem_map.write_block(0x08000000, rom)
     }
 
@@ -112,6 +155,9 @@ impl GBA {
             BackupType::Sram | BackupType::Flash64K | BackupType::Flash128K => {
                 self.memory_bus.mem_map.write_block(0x0E000000, save_data);
             },
+            BackupType::Eeprom => {
+                self.memory_bus.mem_map.eeprom.load(save_data);
+            },
             _ => {log::info!("Save data for this type is not implemented")} 
         }
     }
@@ -126,6 +172,9 @@ impl GBA {
             },
             BackupType::Flash128K => {
                 return self.memory_bus.mem_map.read_block_raw(0x0E000000, 0x20000);
+            },
+            BackupType::Eeprom => {
+                return self.memory_bus.mem_map.eeprom.save_data();
             }
             _ => {log::info!("Save data for this type is not implemented")} 
         }
@@ -133,30 +182,150 @@ impl GBA {
         return Vec::new();
     }
 
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.apu.set_sample_rate(sample_rate);
+    }
+
+    pub fn drain_samples(&mut self) -> Vec<(i16, i16)> {
+        self.apu.drain_samples()
+    }
+
+    // the time source is not part of the serialized state, set it again after deserializing
+    pub fn set_rtc_time_source(&mut self, time_source: Box<dyn TimeSource>) {
+        self.memory_bus.mem_map.rtc.set_time_source(time_source);
+    }
+
+    pub fn buttons(&self) -> Buttons {
+        Buttons::from_key_status(self.key_status.get_register())
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.key_status.set_register(buttons.to_key_status() as u32);
+        self.update_keypad_interrupt();
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.apply_input(InputEvent::Press(button));
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.apply_input(InputEvent::Release(button));
+    }
+
+    pub fn apply_input(&mut self, event: InputEvent) {
+        let buttons = event.apply(self.buttons());
+        self.set_buttons(buttons);
+    }
+
+    // the event is applied right before the given frame starts running
+    pub fn queue_input(&mut self, frame: u64, event: InputEvent) {
+        self.keypad.queue(frame, event);
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.keypad.frame
+    }
+
+    fn update_keypad_interrupt(&mut self) {
+        if Keypad::interrupt_requested(&self.key_status, &self.ket_interrupt_control) {
+            self.interrupt_handler.if_interrupt.set_keypad(1);
+            // stop only ends on keypad, serial or cartridge interrupts
+            if self.memory_bus.mem_map.halt_state == HaltState::Stop && self.interrupt_handler.should_wake_from_stop() {
+                self.memory_bus.mem_map.halt_state = HaltState::Running;
+            }
+        }
+    }
+
     pub fn frame(&mut self) {
+        if let Err(e) = self.try_frame() {
+            panic!("{}", e);
+        }
+    }
+
+    pub fn try_frame(&mut self) -> Result<(), GbaError> {
+        self.start_frame();
         while !self.gpu.frame_ready {
-            self.single_step();
+            self.try_single_step()?;
+            // the lcd doesn't run while stopped, hand the frame back so input can still arrive
+            if self.is_stopped() {
+                break;
+            }
+        }
+
+        self.finish_frame();
+        Ok(())
+    }
+
+    // anything stepping the emulator itself calls these around each frame, see the debugger
+    pub fn start_frame(&mut self) {
+        for event in self.keypad.take_due() {
+            self.apply_input(event);
         }
+    }
 
+    pub fn finish_frame(&mut self) {
         self.gpu.frame_ready = false;
         self.gpu.obj_buffer.iter_mut().for_each(|m|{*m = (Rgb15::new(0x8000), 4, 0)});
-        self.gpu.obj_window = [false; (DISPLAY_WIDTH as usize) * (DISPLAY_HEIGHT as usize)];
+        self.gpu.obj_window.iter_mut().for_each(|m|{*m = false});
+        self.keypad.frame += 1;
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.memory_bus.mem_map.halt_state == HaltState::Stop
+    }
+
+    // the earliest anything besides the cpu can change state, dmas are started by the gpu and
+    // timers or right away
+    fn cycles_to_next_event(&self) -> usize {
+        if self.interrupt_handler.should_service() || self.dma_control.has_pending_transfer() {
+            return 1;
+        }
+
+        let gpu = self.gpu.cycles_to_next_state.max(1) as usize;
+        match self.timer_handler.cycles_to_next_overflow() {
+            Some(timer) => gpu.min(timer.max(1)),
+            None => gpu
+        }
     }
 
     pub fn single_step(&mut self) {
+        if let Err(e) = self.try_single_step() {
+            panic!("{}", e);
+        }
+    }
+
+    pub fn try_single_step(&mut self) -> Result<(), GbaError> {
         // log::info!("Single stepping");
-        let cycles = if self.memory_bus.mem_map.halt_state == HaltState::Running {
-            // log::info!("Stepping cpu");
-            self.cpu.fetch(&mut self.memory_bus)
-        } else {
-            // log::info!("Skippig cpu {:?}", self.memory_bus.mem_map.halt_state);
-            self.gpu.cycles_to_next_state as usize
-            // 1
+        let cycles = match self.memory_bus.mem_map.halt_state {
+            HaltState::Running => {
+                // log::info!("Stepping cpu");
+                let cycles = self.cpu.try_fetch(&mut self.memory_bus)?;
+                // the loop reads the same value until the next event, go straight there
+                if self.idle_loop_skip && idle_loop::is_idle_loop(&self.cpu, &self.memory_bus.mem_map) {
+                    cycles + self.cycles_to_next_event()
+                } else {
+                    cycles
+                }
+            },
+            HaltState::Halt => self.cycles_to_next_event(),
+            HaltState::Stop => {
+                // every clock is stopped, only an external interrupt gets things going again
+                self.update_keypad_interrupt();
+                self.interrupt_handler.service(&mut self.cpu, &mut self.memory_bus);
+                return Ok(());
+            }
         };
 
         self.gpu.step(cycles, &mut self.memory_bus.mem_map, &mut self.interrupt_handler, &mut self.dma_control);
-        self.timer_handler.update(cycles, &mut self.interrupt_handler);
-        self.dma_control.update(&mut self.memory_bus, &mut self.interrupt_handler);
+        let timer_overflows = self.timer_handler.update(cycles, &mut self.interrupt_handler);
+        self.apu.step(cycles, &mut self.memory_bus.mem_map, &timer_overflows, &mut self.dma_control);
+        let cpu = &self.cpu;
+        self.dma_control.update(&mut self.memory_bus, &mut self.interrupt_handler).map_err(|e| e.with_cpu(cpu))?;
+
+        // an msr or spsr restore can leave garbage in the mode bits, catch it before the irq does
+        self.cpu.try_get_operating_mode()?;
+        self.update_keypad_interrupt();
         self.interrupt_handler.service(&mut self.cpu, &mut self.memory_bus);
+        Ok(())
     }
 }
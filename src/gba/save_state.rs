@@ -0,0 +1,217 @@
+use super::GBA;
+use std::error;
+use std::fmt;
+use std::mem;
+
+pub const STATE_MAGIC: [u8; 4] = *b"GBAS";
+// bump whenever a serialized struct changes shape
+pub const STATE_VERSION: u16 = 7;
+const HEADER_SIZE: usize = 20;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Compression {
+    None = 0,
+    Deflate = 1
+}
+
+// magic, version, compression, reserved byte, rom hash, uncompressed body length
+#[derive(Clone, PartialEq, Debug)]
+pub struct StateHeader {
+    pub version: u16,
+    pub compression: Compression,
+    pub rom_hash: u64,
+    pub body_length: u32
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnsupportedCompression(u8),
+    RomMismatch {
+        expected: u64,
+        found: u64
+    },
+    Corrupt(String)
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "Not a save state"),
+            StateError::UnsupportedVersion(version) => write!(f, "Unsupported save state version {}, expected {}", version, STATE_VERSION),
+            StateError::UnsupportedCompression(compression) => write!(f, "Unsupported save state compression {}", compression),
+            StateError::RomMismatch { expected, found } => write!(f, "Save state is for rom {:016X}, loaded rom is {:016X}", found, expected),
+            StateError::Corrupt(reason) => write!(f, "Corrupt save state: {}", reason)
+        }
+    }
+}
+
+impl error::Error for StateError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+// fnv-1a
+pub fn hash_rom(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01B3))
+}
+
+impl StateHeader {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE);
+        bytes.extend_from_slice(&STATE_MAGIC);
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.push(self.compression as u8);
+        bytes.push(0);
+        bytes.extend_from_slice(&self.rom_hash.to_le_bytes());
+        bytes.extend_from_slice(&self.body_length.to_le_bytes());
+        bytes
+    }
+
+    pub fn parse(data: &[u8]) -> Result<StateHeader, StateError> {
+        if data.len() < HEADER_SIZE || data[0..4] != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let compression = match data[6] {
+            0 => Compression::None,
+            1 => Compression::Deflate,
+            value => return Err(StateError::UnsupportedCompression(value))
+        };
+
+        let mut rom_hash = [0u8; 8];
+        rom_hash.copy_from_slice(&data[8..16]);
+        let mut body_length = [0u8; 4];
+        body_length.copy_from_slice(&data[16..20]);
+
+        Ok(StateHeader {
+            version,
+            compression,
+            rom_hash: u64::from_le_bytes(rom_hash),
+            body_length: u32::from_le_bytes(body_length)
+        })
+    }
+}
+
+impl GBA {
+    pub fn save_state(&self) -> Vec<u8> {
+        self.save_state_with(Compression::Deflate)
+    }
+
+    pub fn save_state_with(&self, compression: Compression) -> Vec<u8> {
+        let body = bincode::serialize(self).expect("GBA state is always serializable");
+        let header = StateHeader {
+            version: STATE_VERSION,
+            compression,
+            rom_hash: self.rom_hash,
+            body_length: body.len() as u32
+        };
+
+        let mut state = header.to_bytes();
+        match compression {
+            Compression::None => state.extend_from_slice(&body),
+            Compression::Deflate => state.extend_from_slice(&miniz_oxide::deflate::compress_to_vec(&body, 6))
+        }
+        state
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let header = StateHeader::parse(data)?;
+        if header.rom_hash != self.rom_hash {
+            return Err(StateError::RomMismatch { expected: self.rom_hash, found: header.rom_hash });
+        }
+
+        let body = match header.compression {
+            Compression::None => data[HEADER_SIZE..].to_vec(),
+            Compression::Deflate => miniz_oxide::inflate::decompress_to_vec_with_limit(&data[HEADER_SIZE..], header.body_length as usize)
+                .map_err(|e| StateError::Corrupt(format!("{:?}", e.status)))?
+        };
+        if body.len() != header.body_length as usize {
+            return Err(StateError::Corrupt(format!("body is {} bytes, header says {}", body.len(), header.body_length)));
+        }
+
+        let mut state: GBA = bincode::deserialize(&body).map_err(|e| StateError::Corrupt(e.to_string()))?;
+
+        // the bios and rom aren't in the state, keep the ones already loaded
+        {
+            let mut current = self.memory_bus.mem_map.memory.borrow_mut();
+            let mut restored = state.memory_bus.mem_map.memory.borrow_mut();
+            restored.bios = mem::take(&mut current.bios);
+            restored.rom = mem::take(&mut current.rom);
+        }
+        state.memory_bus.mem_map.rtc.set_time_source(self.memory_bus.mem_map.rtc.take_time_source());
+        state.cpu.tracer = self.cpu.tracer.take();
+        state.rom_hash = self.rom_hash;
+        state.idle_loop_skip = self.idle_loop_skip;
+        state.gpu.layer_toggles = mem::take(&mut self.gpu.layer_toggles);
+        state.gpu.layer_buffers = self.gpu.layer_buffers.take();
+
+        *self = state;
+        self.register_memory();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::cpu::ARM_PC;
+
+    fn test_gba() -> GBA {
+        let mut gba: GBA = GBA::default();
+        gba.load_rom(&vec![0xAB; 0x100]);
+        gba
+    }
+
+    #[test]
+    fn round_trip_restores_memory_and_registers() {
+        let mut gba = test_gba();
+        gba.memory_bus.write_u32(0x0200_1000, 0xDEAD_BEEF);
+        gba.memory_bus.write_u16(0x0500_0002, 0x7FFF);
+        gba.cpu.set_register(ARM_PC, 0x0800_0040);
+        gba.gpu.display_control.set_register(0x0403);
+        let state = gba.save_state();
+
+        gba.memory_bus.write_u32(0x0200_1000, 0);
+        gba.memory_bus.write_u16(0x0500_0002, 0);
+        gba.cpu.set_register(ARM_PC, 0);
+        gba.gpu.display_control.set_register(0);
+
+        gba.load_state(&state).unwrap();
+        assert_eq!(gba.memory_bus.read_u32(0x0200_1000), 0xDEAD_BEEF);
+        assert_eq!(gba.memory_bus.read_u16(0x0500_0002), 0x7FFF);
+        assert_eq!(gba.cpu.get_register(ARM_PC), 0x0800_0040);
+        // io registers read through the relinked memory
+        assert_eq!(gba.gpu.display_control.get_register(), 0x0403);
+        assert_eq!(gba.memory_bus.read_u8(0x0800_0000), 0xAB);
+    }
+
+    #[test]
+    fn state_is_compact() {
+        let gba = test_gba();
+        assert!(gba.save_state().len() < 0x10000);
+        assert!(gba.save_state_with(Compression::None).len() < 0x100000);
+    }
+
+    #[test]
+    fn rejects_bad_states() {
+        let mut gba = test_gba();
+        let mut state = gba.save_state();
+
+        assert_eq!(gba.load_state(&state[..10]), Err(StateError::BadMagic));
+
+        let mut other = GBA::default();
+        other.load_rom(&vec![0xCD; 0x100]);
+        assert!(matches!(other.load_state(&state), Err(StateError::RomMismatch { .. })));
+
+        state[4] = 0xFF;
+        assert!(matches!(gba.load_state(&state), Err(StateError::UnsupportedVersion(_))));
+    }
+}
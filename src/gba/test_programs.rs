@@ -0,0 +1,14 @@
+use super::GBA;
+use crate::cpu::cpu::ARM_PC;
+
+// add r0, r0, #1 ; b -8
+pub const LOOP: [u32; 2] = [0xE280_0001, 0xEAFF_FFFD];
+
+// a gba counting up in r0 forever from `address`, in arm state
+pub fn loop_gba(address: u32) -> GBA {
+    let mut gba: GBA = GBA::default();
+    let bytes: Vec<u8> = LOOP.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect();
+    gba.memory_bus.mem_map.write_block(address, &bytes);
+    gba.cpu.set_register(ARM_PC, address);
+    gba
+}
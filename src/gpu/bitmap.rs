@@ -2,7 +2,6 @@ use crate::{
     memory::{
         memory_map::{MemoryMap, PALETTE_RAM_START},
     },
-    operations::bitutils
 };
 use super::{
     gpu::{GPU, DISPLAY_WIDTH, DISPLAY_HEIGHT}, 
@@ -14,8 +13,7 @@ impl GPU {
         let map_start_address = 0x06000000;
         let pa = i32::from(&self.bg_affine_components[0].rotation_scaling_param_a);
         let pc = i32::from(&self.bg_affine_components[0].rotation_scaling_param_c);
-        let ref_point_x = bitutils::sign_extend_u32(self.bg_affine_components[0].refrence_point_x_internal, 27) as i32;
-        let ref_point_y =  bitutils::sign_extend_u32(self.bg_affine_components[0].refrence_point_y_internal, 27) as i32;
+        let (ref_point_x, ref_point_y) = self.affine_reference_point(2);
 
         for x in 0..DISPLAY_WIDTH {
             let pixel_x = (ref_point_x + (x as i32) * pa) >> 8;
@@ -41,8 +39,7 @@ impl GPU {
 
         let pa = i32::from(&self.bg_affine_components[0].rotation_scaling_param_a);
         let pc = i32::from(&self.bg_affine_components[0].rotation_scaling_param_c);
-        let ref_point_x = bitutils::sign_extend_u32(self.bg_affine_components[0].refrence_point_x_internal, 27) as i32;
-        let ref_point_y =  bitutils::sign_extend_u32(self.bg_affine_components[0].refrence_point_y_internal, 27) as i32;
+        let (ref_point_x, ref_point_y) = self.affine_reference_point(2);
 
         for x in 0..DISPLAY_WIDTH {
             let pixel_x = (ref_point_x + (x as i32) * pa) >> 8;
@@ -69,8 +66,7 @@ impl GPU {
 
         let pa = i32::from(&self.bg_affine_components[0].rotation_scaling_param_a);
         let pc = i32::from(&self.bg_affine_components[0].rotation_scaling_param_c);
-        let ref_point_x = bitutils::sign_extend_u32(self.bg_affine_components[0].refrence_point_x_internal, 27) as i32;
-        let ref_point_y =  bitutils::sign_extend_u32(self.bg_affine_components[0].refrence_point_y_internal, 27) as i32;
+        let (ref_point_x, ref_point_y) = self.affine_reference_point(2);
 
         for x in 0..160 {
             let t = ((ref_point_x + (x as i32) * pa) >> 8, (ref_point_y + (x as i32) * pc) >> 8);
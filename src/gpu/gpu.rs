@@ -1,6 +1,7 @@
 use crate::{
     memory::{
         memory_map::MemoryMap,
+        GbaMem,
         lcd_io_registers::*
     },
     operations::bitutils,
@@ -10,7 +11,9 @@ use crate::{
 use super::{
     rgb15::Rgb15, 
     object::Object,
-    object::AffineMatrix
+    object::AffineMatrix,
+    mosaic::MosaicCounter,
+    layers::{LayerToggles, LayerBuffers}
 };
 use std::{
     cell::RefCell,
@@ -51,7 +54,7 @@ pub struct Background {
 }
 
 impl Background {
-    pub fn register(&mut self, mem: &Rc<RefCell<Vec<u8>>>) {
+    pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>) {
         self.control.register(mem);
         self.horizontal_offset.register(mem);
         self.vertical_offset.register(mem);
@@ -77,7 +80,7 @@ pub struct BgAffineComponent {
 }
 
 impl BgAffineComponent {
-    pub fn register(&mut self, mem: &Rc<RefCell<Vec<u8>>>) {
+    pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>) {
         self.refrence_point_x_external.register(mem);
         self.refrence_point_y_external.register(mem);
         self.rotation_scaling_param_a.register(mem);
@@ -94,7 +97,7 @@ pub struct Window {
 }
 
 impl Window {
-    pub fn register(&mut self, mem: &Rc<RefCell<Vec<u8>>>) {
+    pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>) {
         self.horizontal_dimensions.register(mem);
         self.vertical_dimensions.register(mem);
     }
@@ -128,8 +131,7 @@ pub struct GPU {
     pub bg_affine_components: [BgAffineComponent; 2],
     pub windows: [Window; 2],
 
-    #[serde_as(as = "[_; WINDOW_SIZE]")]
-    pub obj_window: [bool; (DISPLAY_WIDTH as usize) * (DISPLAY_HEIGHT as usize)],
+    pub obj_window: Vec<bool>,
 
     #[serde_as(as = "[_; 128]")]
     pub objects: [Object; 128],
@@ -138,11 +140,19 @@ pub struct GPU {
     pub control_window_inside: ControlWindowInside,
     pub control_window_outside: ControlWindowOutside,
     pub mosaic_size: MosaicSize,
+    pub bg_mosaic: MosaicCounter,
+    pub obj_mosaic: MosaicCounter,
     pub color_special_effects_selection: ColorSpecialEffectsSelection,
 
     pub alpha_blending_coefficients: AlphaBlendingCoefficients,
     pub brightness_coefficient: BrightnessCoefficient,
 
+    // debugging aids set by the host, not part of the emulated state
+    #[serde(skip)]
+    pub layer_toggles: LayerToggles,
+    #[serde(skip)]
+    pub layer_buffers: Option<LayerBuffers>,
+
     pub cycles_to_next_state: i64,
     pub current_state: GpuState,
     pub frame_ready: bool,
@@ -222,7 +232,7 @@ impl GPU {
                     vertical_dimensions: WindowVerticalDimension::new(1)
                 }
             ],
-            obj_window: [false; (DISPLAY_WIDTH as usize) * (DISPLAY_HEIGHT as usize)],
+            obj_window: vec![false; WINDOW_SIZE],
 
             // Registers
             display_control: DisplayControl::new(),
@@ -233,11 +243,16 @@ impl GPU {
             control_window_inside: ControlWindowInside::new(),
             control_window_outside: ControlWindowOutside::new(),
             mosaic_size: MosaicSize::new(),
+            bg_mosaic: MosaicCounter::default(),
+            obj_mosaic: MosaicCounter::default(),
             color_special_effects_selection: ColorSpecialEffectsSelection::new(),
 
             alpha_blending_coefficients: AlphaBlendingCoefficients::new(),
             brightness_coefficient: BrightnessCoefficient::new(),
 
+            layer_toggles: LayerToggles::default(),
+            layer_buffers: None,
+
             cycles_to_next_state: HDRAW_CYCLES,
             current_state: GpuState::HDraw,
             frame_ready: false,
@@ -246,7 +261,7 @@ impl GPU {
         };
     }
 
-    pub fn register(&mut self, mem: &Rc<RefCell<Vec<u8>>>) {
+    pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>) {
         for i in 0..4 {
             self.backgrounds[i].register(mem);
         }
@@ -331,7 +346,16 @@ impl GPU {
             5 => {
                 self.render_mode_5(mem_map);
             },
-            _ => panic!("Unimplemented mode: {}", current_mode)
+            _ => {
+                // modes 6 and 7 are prohibited, the hardware draws no backgrounds at all
+                for i in 0..4 {
+                    self.backgrounds[i].scan_line.iter_mut().for_each(|pixel| *pixel = Rgb15::new(0x8000));
+                }
+            }
+        }
+
+        for i in 0..4 {
+            self.apply_bg_mosaic(i);
         }
 
         if self.display_control.get_screen_display_obj() == 1 {
@@ -360,11 +384,25 @@ impl GPU {
                 self.display_status.set_hblank_flag(0);
 
                 if current_scanline < DISPLAY_HEIGHT {
-                    // render scanline
-                    self.render_scanline(mem_map);
+                    self.next_mosaic_line(current_scanline);
+
+                    if self.display_control.get_forced_blank() != 0 {
+                        // the lcd is white and nothing is read from video memory
+                        self.blank_scanline();
+                        self.capture_layers(true);
+                    } else {
+                        // render scanline
+                        self.render_scanline(mem_map);
+
+                        // composite the backgrounds
+                        self.composite_background(mem_map);
 
-                    // composite the backgrounds
-                    self.composite_background(mem_map);
+                        if self.green_swap.get_green_swap() != 0 {
+                            self.apply_green_swap();
+                        }
+
+                        self.capture_layers(false);
+                    }
 
                     // update refrence points at end of scanline
                     for i in 0..2 {
@@ -431,6 +469,7 @@ impl GPU {
                     self.display_status.set_vblank_flag(0);
 
                     self.update_vcount(0, irq_ctl);
+                    self.start_mosaic_frame();
                     self.current_state = GpuState::HDraw;
                     self.cycles_to_next_state = HDRAW_CYCLES;
                     self.frame_ready = true;
@@ -449,3 +488,48 @@ impl GPU {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gba::GBA;
+    use crate::memory::memory_map::PALETTE_RAM_START;
+
+    fn draw_line(gba: &mut GBA, line: u8) -> Vec<u32> {
+        gba.gpu.vertical_count.set_current_scanline(line - 1);
+        gba.gpu.current_state = GpuState::HBlank;
+        gba.gpu.transition_state(&mut gba.memory_bus.mem_map, &mut gba.interrupt_handler, &mut gba.dma_control);
+        let start = (DISPLAY_WIDTH as usize) * (line as usize);
+        gba.gpu.frame_buffer[start..start + DISPLAY_WIDTH as usize].to_vec()
+    }
+
+    #[test]
+    fn forced_blank_draws_white() {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.mem_map.write_u16(PALETTE_RAM_START, 0x001F);
+        gba.gpu.display_control.set_forced_blank(1);
+
+        assert!(draw_line(&mut gba, 5).iter().all(|pixel| *pixel == 0xFFFFFF));
+    }
+
+    #[test]
+    fn prohibited_modes_show_the_backdrop() {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.mem_map.write_u16(PALETTE_RAM_START, 0x001F);
+        for mode in 6..8 {
+            gba.gpu.display_control.set_register(0x0F00 | mode);
+            assert!(draw_line(&mut gba, 5).iter().all(|pixel| *pixel == Rgb15::new(0x001F).to_0rgb()));
+        }
+    }
+
+    #[test]
+    fn green_swap_exchanges_neighbouring_greens() {
+        let mut gba: GBA = GBA::default();
+        gba.gpu.vertical_count.set_current_scanline(1);
+        gba.gpu.frame_buffer[240] = 0x11_22_33;
+        gba.gpu.frame_buffer[241] = 0x44_55_66;
+        gba.gpu.frame_buffer[242] = 0x77_88_99;
+        gba.gpu.apply_green_swap();
+        assert_eq!(gba.gpu.frame_buffer[240..243], [0x11_55_33, 0x44_22_66, 0x77_00_99]);
+    }
+}
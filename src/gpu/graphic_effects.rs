@@ -1,6 +1,6 @@
 use crate::gpu::gpu::GPU;
 use crate::memory::memory_map::MemoryMap;
-use super::{rgb15::Rgb15, gpu::DISPLAY_WIDTH};
+use super::{rgb15::Rgb15, gpu::DISPLAY_WIDTH, object::OBJ_SEMI_TRANSPARENT, layers::Layer};
 use crate::memory::memory_map::PALETTE_RAM_START;
 use std::cmp;
 use serde::{Serialize, Deserialize};
@@ -35,7 +35,7 @@ pub enum WindowTypes {
 
 impl GPU {
     fn get_window_type(&self, x: u32, y: u32) -> Option<WindowTypes>{
-        if self.display_control.using_windows() {
+        if self.display_control.using_windows() && self.layer_enabled(Layer::Windows) {
             if self.display_control.get_window_0_display_flag() != 0 && self.windows[0].inside(x, y) {
                 return Some(WindowTypes::Window0);
             }
@@ -113,7 +113,7 @@ impl GPU {
 
         for priority in (0..4).rev() {
             for bg in (0..4).rev() {
-                if self.display_control.should_display(bg) && 
+                if self.display_control.should_display(bg) && self.layer_enabled(Layer::bg(bg as usize)) &&
                    self.backgrounds[bg as usize].control.get_bg_priority() == priority {
 
                     bg_list[bg_count] = bg;
@@ -135,7 +135,7 @@ impl GPU {
             let obj_buffer_index: usize = (DISPLAY_WIDTH * (current_scanline as u32) + (x as u32)) as usize;
             let (obj_color, obj_priority, obj_gfx_mode) = self.obj_buffer[obj_buffer_index];
             let window_type = self.get_window_type(x, current_scanline);
-            let dsp_ctrl_obj = self.display_control.get_screen_display_obj() != 0;
+            let dsp_ctrl_obj = self.display_control.get_screen_display_obj() != 0 && self.layer_enabled(Layer::Obj);
 
             let (disp_sfx, disp_obj, bgs_to_disp) = match &window_type {
                 Some(val) => self.get_window_flags(&val),
@@ -184,16 +184,18 @@ impl GPU {
                 _ => panic!("THis should never hit")
             }
 
-            let is_alpha_obj = layer.0 == 4 && obj_gfx_mode == 0b01;
+            let is_alpha_obj = layer.0 == 4 && obj_gfx_mode == OBJ_SEMI_TRANSPARENT;
 
-            if disp_sfx || is_alpha_obj {
-                let mut blend_mode = self.color_special_effects_selection.get_blendmode();
-                let have_destination = self.color_special_effects_selection.has_destination(layer.0) | is_alpha_obj;
+            if disp_sfx && self.layer_enabled(Layer::Blending) {
                 let have_source = self.color_special_effects_selection.has_source(layer.1);
 
-                if is_alpha_obj && have_source {
-                    blend_mode = BlendMode::Alpha;
-                }
+                // semi-transparent sprites are always a first target and alpha blend with whatever
+                // second target is below them, without one they fall back to BLDCNT like any sprite
+                let (blend_mode, have_destination) = if is_alpha_obj && have_source {
+                    (BlendMode::Alpha, true)
+                } else {
+                    (self.color_special_effects_selection.get_blendmode(), self.color_special_effects_selection.has_destination(layer.0))
+                };
 
                 if blend_mode != BlendMode::Off && have_destination && (have_source || blend_mode != BlendMode::Alpha) {
                     // blend
@@ -205,4 +207,21 @@ impl GPU {
             self.frame_buffer[frame_buffer_index] = pixel.0.to_0rgb();
         }
     }
+
+    pub fn blank_scanline(&mut self) {
+        let current_scanline = self.vertical_count.get_current_scanline() as usize;
+        let start = (DISPLAY_WIDTH as usize) * current_scanline;
+        self.frame_buffer[start..start + DISPLAY_WIDTH as usize].iter_mut().for_each(|pixel| *pixel = Rgb15::new(0x7FFF).to_0rgb());
+    }
+
+    // undocumented, every pair of pixels swaps its green components
+    pub fn apply_green_swap(&mut self) {
+        let current_scanline = self.vertical_count.get_current_scanline() as usize;
+        let start = (DISPLAY_WIDTH as usize) * current_scanline;
+        for pair in self.frame_buffer[start..start + DISPLAY_WIDTH as usize].chunks_exact_mut(2) {
+            let (left, right) = (pair[0], pair[1]);
+            pair[0] = (left & !0xFF00) | (right & 0xFF00);
+            pair[1] = (right & !0xFF00) | (left & 0xFF00);
+        }
+    }
 }
@@ -0,0 +1,198 @@
+use super::{gpu::{GPU, DISPLAY_WIDTH, WINDOW_SIZE}, rgb15::Rgb15};
+
+// layers a debugger can hide, on top of whatever the game has in DISPCNT
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Layer {
+    Bg0,
+    Bg1,
+    Bg2,
+    Bg3,
+    Obj,
+    Windows,
+    Blending
+}
+
+impl Layer {
+    pub const ALL: [Layer; 7] = [Layer::Bg0, Layer::Bg1, Layer::Bg2, Layer::Bg3, Layer::Obj, Layer::Windows, Layer::Blending];
+
+    pub fn bg(bg_number: usize) -> Layer {
+        Layer::ALL[bg_number]
+    }
+}
+
+// only the backgrounds and sprites have pixels of their own
+const BUFFERED_LAYERS: usize = 5;
+
+pub struct LayerToggles {
+    enabled: [bool; Layer::ALL.len()]
+}
+
+impl Default for LayerToggles {
+    fn default() -> Self {
+        LayerToggles { enabled: [true; Layer::ALL.len()] }
+    }
+}
+
+// each background and the sprites drawn on their own, 4 bytes of rgba a pixel with transparent
+// pixels at 0 alpha
+pub struct LayerBuffers {
+    buffers: [Vec<u8>; BUFFERED_LAYERS]
+}
+
+impl Default for LayerBuffers {
+    fn default() -> Self {
+        LayerBuffers { buffers: [(); BUFFERED_LAYERS].map(|_| vec![0; 4 * WINDOW_SIZE]) }
+    }
+}
+
+fn to_rgba(color: &Rgb15) -> [u8; 4] {
+    if color.is_transparent() {
+        return [0; 4];
+    }
+    let rgb = color.to_0rgb();
+    [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 0xFF]
+}
+
+// which backgrounds the current mode actually draws, the others keep stale scanlines
+fn bg_in_mode(mode: u8, bg_number: usize) -> bool {
+    match mode {
+        0 => true,
+        1 => bg_number < 3,
+        2 => bg_number >= 2,
+        3..=5 => bg_number == 2,
+        _ => false
+    }
+}
+
+impl GPU {
+    pub fn layer_enabled(&self, layer: Layer) -> bool {
+        self.layer_toggles.enabled[layer as usize]
+    }
+
+    pub fn set_layer_enabled(&mut self, layer: Layer, enabled: bool) {
+        self.layer_toggles.enabled[layer as usize] = enabled;
+    }
+
+    // rendering every layer separately costs a copy per line, so it's off until asked for
+    pub fn enable_layer_buffers(&mut self, enabled: bool) {
+        self.layer_buffers = if enabled { Some(LayerBuffers::default()) } else { None };
+    }
+
+    pub fn layer_buffer(&self, layer: Layer) -> Option<&[u8]> {
+        match &self.layer_buffers {
+            Some(layers) if (layer as usize) < BUFFERED_LAYERS => Some(&layers.buffers[layer as usize]),
+            _ => None
+        }
+    }
+
+    // the layers as the game set them up, the debug toggles only apply to the composited frame
+    pub fn capture_layers(&mut self, blank: bool) {
+        let current_scanline = self.vertical_count.get_current_scanline() as usize;
+        let mode = self.display_control.get_bg_mode();
+        let start = (DISPLAY_WIDTH as usize) * current_scanline;
+
+        let mut layers = match self.layer_buffers.take() {
+            Some(layers) => layers,
+            None => return
+        };
+
+        for bg_number in 0..4 {
+            let shown = !blank && bg_in_mode(mode, bg_number) && self.display_control.should_display(bg_number as u8);
+            let buffer = &mut layers.buffers[bg_number][4 * start..4 * (start + DISPLAY_WIDTH as usize)];
+            for (x, pixel) in buffer.chunks_exact_mut(4).enumerate() {
+                let color = if shown { to_rgba(&self.backgrounds[bg_number].scan_line[x]) } else { [0; 4] };
+                pixel.copy_from_slice(&color);
+            }
+        }
+
+        let shown = !blank && self.display_control.get_screen_display_obj() != 0;
+        let buffer = &mut layers.buffers[Layer::Obj as usize][4 * start..4 * (start + DISPLAY_WIDTH as usize)];
+        for (x, pixel) in buffer.chunks_exact_mut(4).enumerate() {
+            let color = if shown { to_rgba(&self.obj_buffer[start + x].0) } else { [0; 4] };
+            pixel.copy_from_slice(&color);
+        }
+
+        self.layer_buffers = Some(layers);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gba::GBA;
+    use crate::memory::memory_map::PALETTE_RAM_START;
+
+    const RED: u16 = 0x001F;
+    const BLUE: u16 = 0x7C00;
+
+    // bg0 in red over bg1 in blue, with bg1 selected to blend into
+    fn two_backgrounds() -> GBA {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.mem_map.write_u16(PALETTE_RAM_START, 0);
+        gba.gpu.display_control.set_register(0x0300);
+        gba.gpu.backgrounds[1].control.set_bg_priority(1);
+        gba.gpu.backgrounds[0].scan_line.iter_mut().for_each(|pixel| *pixel = Rgb15::new(RED));
+        gba.gpu.backgrounds[1].scan_line.iter_mut().for_each(|pixel| *pixel = Rgb15::new(BLUE));
+        gba.gpu.color_special_effects_selection.set_register(0x0241);
+        gba.gpu.alpha_blending_coefficients.set_register(0x1010);
+        gba
+    }
+
+    fn first_pixel(gba: &mut GBA) -> u32 {
+        gba.gpu.composite_background(&mut gba.memory_bus.mem_map);
+        gba.gpu.frame_buffer[0]
+    }
+
+    #[test]
+    fn hidden_layers_drop_out_of_the_frame() {
+        let mut gba = two_backgrounds();
+        assert_eq!(first_pixel(&mut gba), Rgb15::new(RED | BLUE).to_0rgb());
+
+        gba.gpu.set_layer_enabled(Layer::Blending, false);
+        assert_eq!(first_pixel(&mut gba), Rgb15::new(RED).to_0rgb());
+
+        gba.gpu.set_layer_enabled(Layer::Bg0, false);
+        assert_eq!(first_pixel(&mut gba), Rgb15::new(BLUE).to_0rgb());
+
+        gba.gpu.set_layer_enabled(Layer::Bg1, false);
+        assert_eq!(first_pixel(&mut gba), 0);
+        // the game's own setup is untouched
+        assert_eq!(gba.gpu.display_control.get_register(), 0x0300);
+    }
+
+    #[test]
+    fn hidden_windows_show_everything() {
+        let mut gba = two_backgrounds();
+        gba.gpu.set_layer_enabled(Layer::Blending, false);
+        // window 0 covers the screen and only lets bg1 through
+        gba.gpu.display_control.set_register(0x2300);
+        gba.gpu.windows[0].horizontal_dimensions.set_register(0x00F0);
+        gba.gpu.windows[0].vertical_dimensions.set_register(0x00A0);
+        gba.gpu.control_window_inside.set_register(0x0002);
+        assert_eq!(first_pixel(&mut gba), Rgb15::new(BLUE).to_0rgb());
+
+        gba.gpu.set_layer_enabled(Layer::Windows, false);
+        assert_eq!(first_pixel(&mut gba), Rgb15::new(RED).to_0rgb());
+    }
+
+    #[test]
+    fn layers_are_captured_as_rgba() {
+        let mut gba = two_backgrounds();
+        assert!(gba.gpu.layer_buffer(Layer::Bg0).is_none());
+
+        gba.gpu.enable_layer_buffers(true);
+        gba.gpu.backgrounds[0].scan_line[1] = Rgb15::new(0x8000);
+        gba.gpu.set_layer_enabled(Layer::Bg0, false);
+        gba.gpu.capture_layers(false);
+
+        let bg0 = gba.gpu.layer_buffer(Layer::Bg0).unwrap();
+        assert_eq!(bg0[0..8], [0xFF, 0, 0, 0xFF, 0, 0, 0, 0]);
+        assert_eq!(gba.gpu.layer_buffer(Layer::Bg1).unwrap()[0..4], [0, 0, 0xFF, 0xFF]);
+        // bg2 isn't enabled by the game
+        assert_eq!(gba.gpu.layer_buffer(Layer::Bg2).unwrap()[0..4], [0; 4]);
+        assert!(gba.gpu.layer_buffer(Layer::Windows).is_none());
+
+        gba.gpu.capture_layers(true);
+        assert_eq!(gba.gpu.layer_buffer(Layer::Bg1).unwrap()[0..4], [0; 4]);
+    }
+}
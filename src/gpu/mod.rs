@@ -3,4 +3,6 @@ pub mod rgb15;
 pub mod graphic_effects;
 pub mod object;
 pub mod tile_map;
-pub mod bitmap;
\ No newline at end of file
+pub mod bitmap;
+pub mod mosaic;
+pub mod layers;
\ No newline at end of file
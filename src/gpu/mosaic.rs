@@ -0,0 +1,145 @@
+use super::gpu::{GPU, DISPLAY_WIDTH};
+use crate::operations::bitutils;
+use serde::{Serialize, Deserialize};
+
+// the line a vertical mosaic repeats, restarted at the top of every frame and moved down
+// every `size` lines after that, so the blocks line up with the frame and not with VCOUNT
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+pub struct MosaicCounter {
+    pub counter: u32,
+    pub line: u32
+}
+
+impl MosaicCounter {
+    pub fn reset(&mut self) {
+        self.counter = 0;
+        self.line = 0;
+    }
+
+    pub fn next_line(&mut self, size: u32, scanline: u32) {
+        self.counter += 1;
+        if self.counter >= size {
+            self.counter = 0;
+            self.line = scanline;
+        }
+    }
+}
+
+// blocks are aligned to the screen, every pixel in one shows the block's first pixel
+pub fn mosaic_x(x: i32, size: i32) -> i32 {
+    x - x.rem_euclid(size)
+}
+
+impl GPU {
+    pub fn bg_mosaic_size(&self) -> (u32, u32) {
+        (self.mosaic_size.get_bg_mosaic_hsize() as u32 + 1, self.mosaic_size.get_bg_mosaic_vsize() as u32 + 1)
+    }
+
+    pub fn obj_mosaic_size(&self) -> (u32, u32) {
+        (self.mosaic_size.get_obj_mosaic_hsize() as u32 + 1, self.mosaic_size.get_obj_mosaic_vsize() as u32 + 1)
+    }
+
+    pub fn start_mosaic_frame(&mut self) {
+        self.bg_mosaic.reset();
+        self.obj_mosaic.reset();
+    }
+
+    pub fn next_mosaic_line(&mut self, scanline: u32) {
+        let (_, bg_vsize) = self.bg_mosaic_size();
+        let (_, obj_vsize) = self.obj_mosaic_size();
+        self.bg_mosaic.next_line(bg_vsize, scanline);
+        self.obj_mosaic.next_line(obj_vsize, scanline);
+    }
+
+    // the line a background samples from, earlier than the current one inside a mosaic block
+    pub fn bg_line(&self, bg_number: usize) -> u32 {
+        let current_scanline = self.vertical_count.get_current_scanline() as u32;
+        if self.backgrounds[bg_number].control.get_mosaic() != 0 {
+            self.bg_mosaic.line.min(current_scanline)
+        } else {
+            current_scanline
+        }
+    }
+
+    // the internal reference point only moves forward, step it back to the line being repeated
+    pub fn affine_reference_point(&self, bg_number: usize) -> (i32, i32) {
+        let affine = &self.bg_affine_components[bg_number - 2];
+        let ref_point_x = bitutils::sign_extend_u32(affine.refrence_point_x_internal, 27) as i32;
+        let ref_point_y = bitutils::sign_extend_u32(affine.refrence_point_y_internal, 27) as i32;
+
+        let lines_back = (self.vertical_count.get_current_scanline() as u32 - self.bg_line(bg_number)) as i32;
+        let pb = i32::from(&affine.rotation_scaling_param_b);
+        let pd = i32::from(&affine.rotation_scaling_param_d);
+        (ref_point_x - lines_back * pb, ref_point_y - lines_back * pd)
+    }
+
+    pub fn apply_bg_mosaic(&mut self, bg_number: usize) {
+        let (hsize, _) = self.bg_mosaic_size();
+        if self.backgrounds[bg_number].control.get_mosaic() == 0 || hsize == 1 {
+            return;
+        }
+
+        let scan_line = &mut self.backgrounds[bg_number].scan_line;
+        for x in 0..DISPLAY_WIDTH as i32 {
+            scan_line[x as usize] = scan_line[mosaic_x(x, hsize as i32) as usize];
+        }
+    }
+
+    // where in a sprite's bounding box a pixel samples from, relative to its top left corner
+    pub fn obj_mosaic_position(&self, mosaic: bool, screen_x: i32, obj_x: i32, obj_y: i32) -> (i32, i32) {
+        let current_scanline = self.vertical_count.get_current_scanline() as i32;
+        if !mosaic {
+            return (screen_x - obj_x, current_scanline - obj_y);
+        }
+
+        // blocks starting before the sprite take its first row or column instead
+        let (hsize, _) = self.obj_mosaic_size();
+        let x = mosaic_x(screen_x, hsize as i32).max(obj_x);
+        let y = (self.obj_mosaic.line as i32).min(current_scanline).max(obj_y);
+        (x - obj_x, y - obj_y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gba::GBA;
+    use crate::gpu::rgb15::Rgb15;
+
+    #[test]
+    fn blocks_repeat_their_first_pixel() {
+        let mut gba: GBA = GBA::default();
+        gba.gpu.mosaic_size.set_bg_mosaic_hsize(3);
+        gba.gpu.backgrounds[0].control.set_mosaic(1);
+        for x in 0..DISPLAY_WIDTH as usize {
+            gba.gpu.backgrounds[0].scan_line[x] = Rgb15::new(x as u16);
+        }
+
+        gba.gpu.apply_bg_mosaic(0);
+        let colors: Vec<u16> = gba.gpu.backgrounds[0].scan_line[0..10].iter().map(|c| c.value).collect();
+        assert_eq!(colors, vec![0, 0, 0, 0, 4, 4, 4, 4, 8, 8]);
+    }
+
+    #[test]
+    fn vertical_blocks_start_at_the_top_of_the_frame() {
+        let mut counter = MosaicCounter::default();
+        let lines: Vec<u32> = (1..8).map(|line| {
+            counter.next_line(3, line);
+            counter.line
+        }).collect();
+        assert_eq!(lines, vec![0, 0, 3, 3, 3, 6, 6]);
+    }
+
+    #[test]
+    fn sprite_blocks_are_clamped_to_the_sprite() {
+        let mut gba: GBA = GBA::default();
+        gba.gpu.mosaic_size.set_obj_mosaic_hsize(7);
+        gba.gpu.mosaic_size.set_obj_mosaic_vsize(7);
+        gba.gpu.vertical_count.set_current_scanline(12);
+        gba.gpu.obj_mosaic.line = 8;
+
+        assert_eq!(gba.gpu.obj_mosaic_position(false, 13, 10, 4), (3, 8));
+        assert_eq!(gba.gpu.obj_mosaic_position(true, 13, 10, 4), (0, 4));
+        assert_eq!(gba.gpu.obj_mosaic_position(true, 17, 10, 10), (6, 0));
+    }
+}
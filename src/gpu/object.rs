@@ -1,6 +1,7 @@
 use super::{gpu::{GPU, DISPLAY_WIDTH, DISPLAY_HEIGHT}, rgb15::Rgb15};
 use crate::memory::{
     memory_map::MemoryMap, 
+    GbaMem,
     lcd_io_registers::PixelFormat, 
     lcd_io_registers::ObjAttribute0,
     lcd_io_registers::ObjAttribute1,
@@ -11,6 +12,15 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use serde::{Serialize, Deserialize};
 
+// obj gfx modes, 0b11 is prohibited and never drawn
+pub const OBJ_NORMAL: u8 = 0b00;
+pub const OBJ_SEMI_TRANSPARENT: u8 = 0b01;
+pub const OBJ_WINDOW: u8 = 0b10;
+
+// cycles the gpu has for sprites on each line, less when it leaves hblank free for the cpu
+pub const OBJ_LINE_CYCLES: u32 = 1210;
+pub const OBJ_LINE_CYCLES_HBLANK_FREE: u32 = 954;
+
 #[derive(Serialize, Deserialize)]
 pub struct Object {
     pub attr0: ObjAttribute0,
@@ -19,7 +29,7 @@ pub struct Object {
 }
 
 impl Object {
-    pub fn register(&mut self, mem: &Rc<RefCell<Vec<u8>>>){
+    pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>){
         self.attr0.register(mem);
         self.attr1.register(mem);
         self.attr2.register(mem);
@@ -47,6 +57,39 @@ impl Object {
         }
     }
 
+    pub fn is_affine(&self) -> bool {
+        self.attr0.get_obj_mode() & 0b01 != 0
+    }
+
+    // the area the sprite covers on screen, double size affine sprites get twice the room
+    pub fn bounding_box(&self) -> (i32, i32) {
+        let (width, height) = self.size();
+        if self.attr0.get_obj_mode() == 0b11 {
+            (2 * width, 2 * height)
+        } else {
+            (width, height)
+        }
+    }
+
+    pub fn on_line(&self, scanline: i32) -> bool {
+        let (_, mut y) = self.position();
+        if y >= (DISPLAY_HEIGHT as i32) {
+            y -= 1 << 8;
+        }
+        let (_, height) = self.bounding_box();
+        scanline >= y && scanline < y + height
+    }
+
+    // a pixel a cycle for normal sprites, affine ones take 10 to set up and two a pixel
+    pub fn render_cycles(&self) -> u32 {
+        let (width, _) = self.bounding_box();
+        if self.is_affine() {
+            10 + 2 * width as u32
+        } else {
+            width as u32
+        }
+    }
+
     pub fn color_format(&self) -> PixelFormat {
         if self.attr0.get_color_flag() == 0 {
             PixelFormat::FourBit
@@ -73,7 +116,7 @@ pub struct AffineMatrix {
 }
 
 impl AffineMatrix {
-    pub fn register(&mut self, mem: &Rc<RefCell<Vec<u8>>>){
+    pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>){
         self.pa.register(mem);
         self.pb.register(mem);
         self.pc.register(mem);
@@ -83,28 +126,47 @@ impl AffineMatrix {
 
 impl GPU {
     pub fn render_obj(&mut self, mem_map: &mut MemoryMap) {
+        let current_scanline = self.vertical_count.get_current_scanline() as i32;
+        let mut cycles_left = if self.display_control.get_hblank_interval_free() != 0 {
+            OBJ_LINE_CYCLES_HBLANK_FREE
+        } else {
+            OBJ_LINE_CYCLES
+        };
+
         for i in 0..128 {
-            match self.objects[i].attr0.get_obj_mode() {
-                0b10 => continue,
-                0b00 => self.render_normal_obj(i, mem_map),
-                0b01 | 0b11 => self.render_aff_obj(i, mem_map),
-                _ => unreachable!() 
-            };
+            // 0b10 hides a normal sprite, obj window ones included
+            if self.objects[i].attr0.get_gfx_mode() > OBJ_WINDOW || self.objects[i].attr0.get_obj_mode() == 0b10 {
+                continue;
+            }
+            if !self.objects[i].on_line(current_scanline) {
+                continue;
+            }
+
+            // sprites are fetched in oam order, once the line's out of time the rest are dropped
+            let cycles = self.objects[i].render_cycles();
+            if cycles > cycles_left {
+                break;
+            }
+            cycles_left -= cycles;
+
+            if self.objects[i].is_affine() {
+                self.render_aff_obj(i, mem_map);
+            } else {
+                self.render_normal_obj(i, mem_map);
+            }
         }
     }
 
     pub fn render_aff_obj(&mut self, sprite_num: usize, mem_map: &mut MemoryMap){
-        let sprite = &mut self.objects[sprite_num];
+        let sprite = &self.objects[sprite_num];
         let current_scanline = self.vertical_count.get_current_scanline() as i32;
         let priority = sprite.attr2.get_priority_rel_to_bg();
         let (obj_x, obj_y) = sprite.position();
         let (obj_w, obj_h) = sprite.size();
         let gfx_mode = sprite.attr0.get_gfx_mode();
+        let mosaic = sprite.attr0.get_mosaic_flag() != 0;
 
-        let (bbox_w, bbox_h) = match sprite.attr0.get_obj_mode() {
-            0b11 => (2 * obj_w, 2 * obj_h),
-            _ => (obj_w, obj_h),
-        };
+        let (bbox_w, bbox_h) = sprite.bounding_box();
 
         let mut ref_point_x = obj_x;
         let mut ref_point_y = obj_y;
@@ -148,8 +210,7 @@ impl GPU {
 
         let half_width = bbox_w / 2;
         let half_height = bbox_h / 2;
-        let iy = current_scanline - (ref_point_y + half_height);
-        
+
         for ix in -half_width..half_width {
             let screen_x = ref_point_x + half_width + ix;
             if screen_x < 0 {
@@ -159,11 +220,17 @@ impl GPU {
                 break;
             }
 
+            // the obj window takes every sprite pixel, whatever is drawn in front of it
             let obj_buffer_index: usize = (DISPLAY_WIDTH * (current_scanline as u32) + (screen_x as u32)) as usize;
-            if self.obj_buffer[obj_buffer_index].1 <= priority {
+            if gfx_mode != OBJ_WINDOW && self.obj_buffer[obj_buffer_index].1 <= priority {
                 continue;
             }
 
+            // with mosaic the whole block samples the texture where it starts
+            let (local_x, local_y) = self.obj_mosaic_position(mosaic, screen_x, ref_point_x, ref_point_y);
+            let ix = local_x - half_width;
+            let iy = local_y - half_height;
+
             let trans_x = (aff_matrix.pa.get_aff_param() as i16 as i32 * ix + aff_matrix.pb.get_aff_param() as i16 as i32 * iy) >> 8;
             let trans_y = (aff_matrix.pc.get_aff_param() as i16 as i32 * ix + aff_matrix.pd.get_aff_param() as i16 as i32 * iy) >> 8;
             let texture_x = trans_x + obj_w / 2;
@@ -197,7 +264,7 @@ impl GPU {
 
                 let obj_buffer_index: usize = (DISPLAY_WIDTH * (current_scanline as u32) + (screen_x as u32)) as usize;
                 if !color.is_transparent() {
-                    if gfx_mode == 0b10 {
+                    if gfx_mode == OBJ_WINDOW {
                         let obj_window_index: usize = (DISPLAY_WIDTH * (current_scanline as u32) + (screen_x as u32)) as usize;
                         self.obj_window[obj_window_index] = true;
                         continue;
@@ -212,11 +279,12 @@ impl GPU {
 
 
     pub fn render_normal_obj(&mut self, sprite_num: usize, mem_map: &mut MemoryMap) {
-        let sprite = &mut self.objects[sprite_num];
+        let sprite = &self.objects[sprite_num];
         let current_scanline = self.vertical_count.get_current_scanline() as i32;
         let (mut obj_x, mut obj_y) = sprite.position();
         let gfx_mode = sprite.attr0.get_gfx_mode();
         let priority = sprite.attr2.get_priority_rel_to_bg();
+        let mosaic = sprite.attr0.get_mosaic_flag() != 0;
 
         if obj_y >= (DISPLAY_HEIGHT as i32) {
             obj_y -= 1 << 8;
@@ -265,12 +333,11 @@ impl GPU {
             }
 
             let obj_buffer_index: usize = (DISPLAY_WIDTH * (current_scanline as u32) + (x as u32)) as usize;
-            if self.obj_buffer[obj_buffer_index].1 <= priority {
+            if gfx_mode != OBJ_WINDOW && self.obj_buffer[obj_buffer_index].1 <= priority {
                 continue;
             }
             
-            let mut sprite_y = current_scanline - obj_y;
-            let mut sprite_x = x - obj_x;
+            let (mut sprite_x, mut sprite_y) = self.obj_mosaic_position(mosaic, x, obj_x, obj_y);
 
             sprite_y = if sprite.attr1.get_vertical_flip() != 0 {
                 obj_h - sprite_y - 1
@@ -312,7 +379,7 @@ impl GPU {
 
             let obj_buffer_index: usize = (DISPLAY_WIDTH * (current_scanline as u32) + (x as u32)) as usize;
             if !color.is_transparent() {
-                if gfx_mode == 0b10 {
+                if gfx_mode == OBJ_WINDOW {
                     let obj_window_index: usize = (DISPLAY_WIDTH * (current_scanline as u32) + (x as u32)) as usize;
                     self.obj_window[obj_window_index] = true;
                     continue;
@@ -323,3 +390,117 @@ impl GPU {
 
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gba::GBA;
+
+    // an 8x8 sprite at (x, 0) filled with obj palette colour 1, size bits can go in with x
+    fn solid_sprite(gba: &mut GBA, index: u32, x: u16, priority: u16, gfx_mode: u16) {
+        let oam = 0x0700_0000 + 8 * index;
+        gba.memory_bus.mem_map.write_u16(oam, gfx_mode << 10);
+        gba.memory_bus.mem_map.write_u16(oam + 2, x);
+        gba.memory_bus.mem_map.write_u16(oam + 4, priority << 10);
+    }
+
+    fn sprite_gba() -> GBA {
+        let mut gba: GBA = GBA::default();
+        for offset in 0..0x20 {
+            gba.memory_bus.mem_map.write_u8(0x0601_0000 + offset, 0x11);
+        }
+        gba.memory_bus.mem_map.write_u16(0x0500_0202, 0x001F);
+        // oam is zeroed, every other sprite would be an 8x8 at (0, 0)
+        for index in 0..128 {
+            gba.memory_bus.mem_map.write_u16(0x0700_0000 + 8 * index, 0x0200);
+        }
+        gba.gpu.vertical_count.set_current_scanline(2);
+        gba
+    }
+
+    #[test]
+    fn window_sprites_only_shape_the_obj_window() {
+        let mut gba = sprite_gba();
+        solid_sprite(&mut gba, 0, 0, 0, 0);
+        // behind the first sprite, it still marks the window
+        solid_sprite(&mut gba, 1, 4, 3, OBJ_WINDOW as u16);
+        gba.gpu.render_obj(&mut gba.memory_bus.mem_map);
+
+        let line = 2 * DISPLAY_WIDTH as usize;
+        assert!((4..12).all(|x| gba.gpu.obj_window[line + x]));
+        assert!(!gba.gpu.obj_window[line + 3] && !gba.gpu.obj_window[line + 12]);
+        assert_eq!(gba.gpu.obj_buffer[line + 6].2, OBJ_NORMAL);
+        assert!(gba.gpu.obj_buffer[line + 10].0.is_transparent());
+    }
+
+    #[test]
+    fn prohibited_gfx_mode_is_not_drawn() {
+        let mut gba = sprite_gba();
+        solid_sprite(&mut gba, 0, 0, 0, 0b11);
+        gba.gpu.render_obj(&mut gba.memory_bus.mem_map);
+
+        let line = 2 * DISPLAY_WIDTH as usize;
+        assert!(gba.gpu.obj_buffer[line..line + 8].iter().all(|pixel| pixel.0.is_transparent()));
+        assert!(!gba.gpu.obj_window[line]);
+    }
+
+    #[test]
+    fn semi_transparent_sprites_blend_without_being_selected() {
+        let mut gba = sprite_gba();
+        solid_sprite(&mut gba, 0, 0, 0, OBJ_SEMI_TRANSPARENT as u16);
+        gba.gpu.render_obj(&mut gba.memory_bus.mem_map);
+
+        // bg0 in blue behind it, only selected as the second target and no effect in BLDCNT
+        gba.gpu.display_control.set_register(0x1100);
+        gba.gpu.backgrounds[0].scan_line.iter_mut().for_each(|pixel| *pixel = Rgb15::new(0x7C00));
+        gba.gpu.color_special_effects_selection.set_register(0x0100);
+        gba.gpu.alpha_blending_coefficients.set_register(0x0808);
+        gba.gpu.composite_background(&mut gba.memory_bus.mem_map);
+
+        let line = 2 * DISPLAY_WIDTH as usize;
+        assert_eq!(gba.gpu.frame_buffer[line], Rgb15::new(0x3C0F).to_0rgb());
+        assert_eq!(gba.gpu.frame_buffer[line + 8], Rgb15::new(0x7C00).to_0rgb());
+
+        // brightness effects still need the sprite to be picked as a first target
+        gba.gpu.color_special_effects_selection.set_register(0x0080);
+        gba.gpu.brightness_coefficient.set_register(16);
+        gba.gpu.composite_background(&mut gba.memory_bus.mem_map);
+        assert_eq!(gba.gpu.frame_buffer[line], Rgb15::new(0x001F).to_0rgb());
+        gba.gpu.color_special_effects_selection.set_register(0x0090);
+        gba.gpu.composite_background(&mut gba.memory_bus.mem_map);
+        assert_eq!(gba.gpu.frame_buffer[line], Rgb15::new(0x7FFF).to_0rgb());
+    }
+
+    // 64x64 sprites use 64 cycles each
+    fn wide_sprites(hblank_interval_free: bool, count: u32) -> GBA {
+        let mut gba = sprite_gba();
+        for index in 0..count {
+            let x = if index == count - 1 { 100 } else { 0 };
+            solid_sprite(&mut gba, index, 0xC000 | x, 0, 0);
+        }
+        gba.gpu.display_control.set_hblank_interval_free(hblank_interval_free as u8);
+        gba.gpu.render_obj(&mut gba.memory_bus.mem_map);
+        gba
+    }
+
+    #[test]
+    fn sprites_past_the_line_budget_are_dropped() {
+        let line = 2 * DISPLAY_WIDTH as usize;
+        assert!(!wide_sprites(false, 18).gpu.obj_buffer[line + 100].0.is_transparent());
+        assert!(wide_sprites(false, 19).gpu.obj_buffer[line + 100].0.is_transparent());
+        assert!(!wide_sprites(true, 14).gpu.obj_buffer[line + 100].0.is_transparent());
+        assert!(wide_sprites(true, 15).gpu.obj_buffer[line + 100].0.is_transparent());
+    }
+
+    #[test]
+    fn affine_sprites_cost_more() {
+        let mut gba = sprite_gba();
+        solid_sprite(&mut gba, 0, 0, 0, 0);
+        assert_eq!(gba.gpu.objects[0].render_cycles(), 8);
+        // affine, then double size
+        gba.memory_bus.mem_map.write_u16(0x0700_0000, 0x0100);
+        assert_eq!(gba.gpu.objects[0].render_cycles(), 10 + 2 * 8);
+        gba.memory_bus.mem_map.write_u16(0x0700_0000, 0x0300);
+        assert_eq!(gba.gpu.objects[0].render_cycles(), 10 + 2 * 16);
+    }
+}
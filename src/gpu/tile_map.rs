@@ -3,7 +3,6 @@ use crate::{
         memory_map::{MemoryMap},
         lcd_io_registers::PixelFormat,
     },
-    operations::bitutils
 };
 use super::{
     gpu::{GPU, DISPLAY_WIDTH}, 
@@ -39,7 +38,8 @@ impl GPU {
         let pixel_format = self.backgrounds[bg_number].control.get_pixel_format();
         let tile_size = self.backgrounds[bg_number].control.get_tilesize();
 
-        let current_scanline = self.vertical_count.get_current_scanline() as u32;
+        // a vertical mosaic repeats an earlier line
+        let current_scanline = self.bg_line(bg_number);
         let mut x = 0;
 
         let background_x = (x + horizontal_offset) % background_width;
@@ -118,8 +118,7 @@ impl GPU {
     pub fn render_aff_bg(&mut self, mem_map: &mut MemoryMap, bg_number: usize) {
         let texture_size = 128 << self.backgrounds[bg_number].control.get_screen_size();
 
-        let ref_point_x = bitutils::sign_extend_u32(self.bg_affine_components[bg_number - 2].refrence_point_x_internal, 27) as i32;
-        let ref_point_y =  bitutils::sign_extend_u32(self.bg_affine_components[bg_number - 2].refrence_point_y_internal, 27) as i32;
+        let (ref_point_x, ref_point_y) = self.affine_reference_point(bg_number);
 
         let pa = i32::from(&self.bg_affine_components[bg_number - 2].rotation_scaling_param_a);
         let pc = i32::from(&self.bg_affine_components[bg_number - 2].rotation_scaling_param_c);
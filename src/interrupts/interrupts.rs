@@ -2,11 +2,14 @@ use crate::memory::interrupt_registers::*;
 use crate::memory::memory_bus::MemoryBus;
 use crate::memory::memory_map::HaltState;
 use crate::cpu::cpu;
-use crate::cpu::cpu::{OperatingMode, InstructionSet, ARM_LR, ARM_PC};
+use crate::cpu::cpu::Exception;
 use serde::{Serialize, Deserialize};
 
 //use crate::cpu::InstructionSet;
 
+// serial, keypad and game pak
+const STOP_WAKE_INTERRUPTS: u16 = (1 << 7) | (1 << 12) | (1 << 13);
+
 #[derive(Serialize, Deserialize)]
 pub struct Interrupts {
     pub ime_interrupt: InterruptMasterEnableRegister,
@@ -30,8 +33,18 @@ impl Interrupts {
         return (self.ie_interrupt.get_register() & self.if_interrupt.get_register()) != 0;
     }
 
+    // only the serial, keypad and cartridge interrupts are raised while the clocks are stopped
+    pub fn should_wake_from_stop(&self) -> bool {
+        (self.ie_interrupt.get_register() & self.if_interrupt.get_register() & STOP_WAKE_INTERRUPTS) != 0
+    }
+
     pub fn service(&mut self, cpu: &mut cpu::CPU, mem_bus: &mut MemoryBus){
-        if self.should_service() && mem_bus.mem_map.halt_state == HaltState::Halt {
+        let wake = match mem_bus.mem_map.halt_state {
+            HaltState::Running => false,
+            HaltState::Halt => self.should_service(),
+            HaltState::Stop => self.should_wake_from_stop()
+        };
+        if wake {
             mem_bus.mem_map.halt_state = HaltState::Running;
             // log::info!("Setting state to running");
         }
@@ -39,13 +52,7 @@ impl Interrupts {
         if self.enabled() && self.should_service() {
             if !cpu.cpsr.control_bits.irq_disable {
                 // log::info!("Handling an interrupt: IE {:b}, IF {:b}", self.ie_interrupt.get_register(), self.if_interrupt.get_register());
-                let old_cpsr = cpu.cpsr;
-                cpu.set_operating_mode(OperatingMode::Interrupt);
-                cpu.set_instruction_set(InstructionSet::Arm);
-                cpu.set_register(ARM_LR, cpu.get_register(ARM_PC) + 4);
-                cpu.set_spsr(old_cpsr);
-                cpu.cpsr.control_bits.irq_disable = true;
-                cpu.set_register(15, 0x18);
+                cpu.take_exception(Exception::Irq);
             }
         }
     }
@@ -0,0 +1,204 @@
+use crate::memory::key_input_registers::{KeyStatus, KeyInterruptControl};
+use serde::{Serialize, Deserialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Button {
+    A = 0,
+    B = 1,
+    Select = 2,
+    Start = 3,
+    Right = 4,
+    Left = 5,
+    Up = 6,
+    Down = 7,
+    R = 8,
+    L = 9
+}
+
+impl Button {
+    pub const ALL: [Button; 10] = [
+        Button::A, Button::B, Button::Select, Button::Start, Button::Right,
+        Button::Left, Button::Up, Button::Down, Button::R, Button::L
+    ];
+
+    pub fn mask(self) -> u16 {
+        1 << (self as u16)
+    }
+}
+
+// Set of held buttons, a set bit means the button is down. KEYINPUT itself is active low.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Buttons(pub u16);
+
+pub const KEY_MASK: u16 = 0x3FF;
+
+impl Buttons {
+    pub const NONE: Buttons = Buttons(0);
+
+    pub fn contains(self, button: Button) -> bool {
+        self.0 & button.mask() != 0
+    }
+
+    pub fn insert(&mut self, button: Button) {
+        self.0 |= button.mask();
+    }
+
+    pub fn remove(&mut self, button: Button) {
+        self.0 &= !button.mask();
+    }
+
+    pub fn from_key_status(key_status: u16) -> Buttons {
+        Buttons(!key_status & KEY_MASK)
+    }
+
+    pub fn to_key_status(self) -> u16 {
+        !self.0 & KEY_MASK
+    }
+}
+
+impl From<Button> for Buttons {
+    fn from(button: Button) -> Buttons {
+        Buttons(button.mask())
+    }
+}
+
+impl From<&[Button]> for Buttons {
+    fn from(buttons: &[Button]) -> Buttons {
+        Buttons(buttons.iter().fold(0, |mask, button| mask | button.mask()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InputEvent {
+    Press(Button),
+    Release(Button),
+    Set(Buttons)
+}
+
+impl InputEvent {
+    pub fn apply(self, buttons: Buttons) -> Buttons {
+        let mut buttons = buttons;
+        match self {
+            InputEvent::Press(button) => buttons.insert(button),
+            InputEvent::Release(button) => buttons.remove(button),
+            InputEvent::Set(new_buttons) => buttons = new_buttons
+        }
+        buttons
+    }
+}
+
+// Frame counter and input events waiting for their frame, used for replays and scripted input.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Keypad {
+    pub frame: u64,
+    queue: Vec<(u64, InputEvent)>
+}
+
+impl Keypad {
+    pub fn new() -> Keypad {
+        Keypad {
+            frame: 0,
+            queue: Vec::new()
+        }
+    }
+
+    // events on the same frame are applied in the order they were queued
+    pub fn queue(&mut self, frame: u64, event: InputEvent) {
+        let index = self.queue.iter().position(|(queued_frame, _)| *queued_frame > frame).unwrap_or(self.queue.len());
+        self.queue.insert(index, (frame, event));
+    }
+
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn clear_queue(&mut self) {
+        self.queue.clear();
+    }
+
+    // removes and returns everything due on or before the current frame
+    pub fn take_due(&mut self) -> Vec<InputEvent> {
+        let due = self.queue.iter().take_while(|(frame, _)| *frame <= self.frame).count();
+        self.queue.drain(..due).map(|(_, event)| event).collect()
+    }
+
+    // KEYCNT either wants any of its selected keys (OR) or all of them (AND)
+    pub fn interrupt_requested(key_status: &KeyStatus, control: &KeyInterruptControl) -> bool {
+        if control.get_irq_enable_flag() == 0 {
+            return false;
+        }
+
+        let selected = control.get_register() & KEY_MASK;
+        let pressed = Buttons::from_key_status(key_status.get_register()).0 & selected;
+        if control.get_irq_condition() == 1 {
+            selected != 0 && pressed == selected
+        } else {
+            pressed != 0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gba::GBA;
+    use crate::memory::memory_map::HaltState;
+
+    #[test]
+    fn buttons_are_active_low() {
+        let mut gba: GBA = GBA::default();
+        gba.press(Button::A);
+        gba.press(Button::Up);
+        assert_eq!(gba.key_status.get_register(), 0x3FF & !0x41);
+        gba.release(Button::A);
+        assert_eq!(gba.buttons(), Buttons::from(Button::Up));
+        gba.set_buttons(Buttons::NONE);
+        assert_eq!(gba.key_status.get_register(), 0x3FF);
+    }
+
+    #[test]
+    fn keycnt_or_and_modes() {
+        let mut gba: GBA = GBA::default();
+        // A or B
+        gba.ket_interrupt_control.set_register(0x4003);
+        gba.press(Button::B);
+        assert_eq!(gba.interrupt_handler.if_interrupt.get_keypad(), 1);
+
+        gba.interrupt_handler.if_interrupt.set_register(0);
+        gba.release(Button::B);
+        // A and B
+        gba.ket_interrupt_control.set_register(0xC003);
+        gba.press(Button::A);
+        assert_eq!(gba.interrupt_handler.if_interrupt.get_keypad(), 0);
+        gba.press(Button::B);
+        assert_eq!(gba.interrupt_handler.if_interrupt.get_keypad(), 1);
+    }
+
+    #[test]
+    fn keypad_irq_wakes_from_stop() {
+        let mut gba: GBA = GBA::default();
+        gba.ket_interrupt_control.set_register(0x4001);
+        gba.interrupt_handler.ie_interrupt.set_keypad(1);
+        gba.memory_bus.mem_map.halt_state = HaltState::Stop;
+
+        gba.single_step();
+        assert_eq!(gba.memory_bus.mem_map.halt_state, HaltState::Stop);
+        gba.press(Button::A);
+        assert_eq!(gba.memory_bus.mem_map.halt_state, HaltState::Running);
+    }
+
+    #[test]
+    fn queued_input_applies_on_its_frame() {
+        let mut keypad = Keypad::new();
+        keypad.queue(2, InputEvent::Release(Button::A));
+        keypad.queue(1, InputEvent::Press(Button::A));
+        keypad.queue(1, InputEvent::Press(Button::B));
+
+        assert!(keypad.take_due().is_empty());
+        keypad.frame = 1;
+        assert_eq!(keypad.take_due(), vec![InputEvent::Press(Button::A), InputEvent::Press(Button::B)]);
+        keypad.frame = 2;
+        assert_eq!(keypad.take_due(), vec![InputEvent::Release(Button::A)]);
+        assert_eq!(keypad.pending(), 0);
+    }
+}
@@ -0,0 +1 @@
+pub mod input;
\ No newline at end of file
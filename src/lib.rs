@@ -10,6 +10,10 @@ pub mod interrupts;
 pub mod timers;
 pub mod dma;
 pub mod gamepak;
+pub mod apu;
+pub mod bios;
+pub mod keypad;
+pub mod debugger;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
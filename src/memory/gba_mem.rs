@@ -0,0 +1,230 @@
+use std::ops::{Index, IndexMut};
+use serde::{Serialize, Deserialize};
+use super::memory_map::*;
+
+pub const BIOS_SIZE: u32 = 0x3FFF;
+pub const IO_START: u32 = 0x04000000;
+pub const IO_SIZE: u32 = 0x3FF;
+pub const BACKUP_START: u32 = 0x0E000000;
+// flash 128K is two 64K banks, the bank offset is added on top of the address
+pub const BACKUP_SIZE: u32 = 0x1FFFF;
+
+// code pages are what the decode cache groups instructions by, mirrors share a page
+pub const CODE_PAGE_SHIFT: u32 = 10;
+const BIOS_PAGES: usize = ((BIOS_SIZE + 1) >> CODE_PAGE_SHIFT) as usize;
+const EWRAM_PAGES: usize = ((ON_BOARD_WRAM_SIZE + 1) >> CODE_PAGE_SHIFT) as usize;
+const IWRAM_PAGES: usize = ((ON_CHIP_WRAM_SIZE + 1) >> CODE_PAGE_SHIFT) as usize;
+
+// Backing store for the address space, one buffer per region with the hardware mirroring applied.
+// IO registers index into this with their absolute address.
+#[derive(Serialize, Deserialize)]
+pub struct GbaMem {
+    // the bios and rom aren't part of save states, they come from the files that were loaded
+    #[serde(skip)]
+    pub bios: Vec<u8>,
+    #[serde(skip)]
+    pub rom: Vec<u8>,
+    pub ewram: Vec<u8>,
+    pub iwram: Vec<u8>,
+    pub io: Vec<u8>,
+    pub palette: Vec<u8>,
+    pub vram: Vec<u8>,
+    pub oam: Vec<u8>,
+    pub backup: Vec<u8>,
+    // writes to the timer data registers set the reload value, reads give the counter
+    pub timer_reloads: [u16; 4],
+    // unmapped writes land here
+    #[serde(skip)]
+    unmapped: u8
+}
+
+static UNMAPPED: u8 = 0;
+
+impl Default for GbaMem {
+    fn default() -> Self {
+        GbaMem::new()
+    }
+}
+
+impl GbaMem {
+    pub fn new() -> GbaMem {
+        GbaMem {
+            bios: vec![0; (BIOS_SIZE + 1) as usize],
+            rom: Vec::new(),
+            ewram: vec![0; (ON_BOARD_WRAM_SIZE + 1) as usize],
+            iwram: vec![0; (ON_CHIP_WRAM_SIZE + 1) as usize],
+            io: vec![0; (IO_SIZE + 1) as usize],
+            palette: vec![0; (PALETTE_RAM_SIZE + 1) as usize],
+            vram: vec![0; (VIDEO_RAM_SIZE + 1) as usize],
+            oam: vec![0; (OBJECT_ATTRIBUTES_SIZE + 1) as usize],
+            backup: vec![0; (BACKUP_SIZE + 1) as usize],
+            timer_reloads: [0; 4],
+            unmapped: 0
+        }
+    }
+
+    // the bios, both work rams and the rom are the places code gets run from
+    pub fn code_page(address: u32) -> Option<usize> {
+        let page = |offset: u32| (offset >> CODE_PAGE_SHIFT) as usize;
+        match address >> 24 {
+            0x00 if address <= BIOS_SIZE => Some(page(address)),
+            0x02 => Some(BIOS_PAGES + page(address & ON_BOARD_WRAM_SIZE)),
+            0x03 => Some(BIOS_PAGES + EWRAM_PAGES + page(address & ON_CHIP_WRAM_SIZE)),
+            0x08..=0x0D => Some(BIOS_PAGES + EWRAM_PAGES + IWRAM_PAGES + page(address & ROM_SIZE)),
+            _ => None
+        }
+    }
+
+    // vram is 96K mirrored in 128K steps, the last 32K mirrors the 32K before it
+    pub fn vram_offset(address: u32) -> usize {
+        let offset = address & 0x1FFFF;
+        if offset > VIDEO_RAM_SIZE { (offset - 0x8000) as usize } else { offset as usize }
+    }
+
+    pub fn is_mapped(address: u32) -> bool {
+        match address >> 24 {
+            0x00 => address <= BIOS_SIZE,
+            0x04 => address - IO_START <= IO_SIZE,
+            0x02 | 0x03 | 0x05..=0x0F => true,
+            _ => false
+        }
+    }
+
+    fn slot(&self, address: u32) -> Option<&u8> {
+        match address >> 24 {
+            0x00 if address <= BIOS_SIZE => self.bios.get(address as usize),
+            0x02 => self.ewram.get((address & ON_BOARD_WRAM_SIZE) as usize),
+            0x03 => self.iwram.get((address & ON_CHIP_WRAM_SIZE) as usize),
+            0x04 if address - IO_START <= IO_SIZE => self.io.get((address - IO_START) as usize),
+            0x05 => self.palette.get((address & PALETTE_RAM_SIZE) as usize),
+            0x06 => self.vram.get(GbaMem::vram_offset(address)),
+            0x07 => self.oam.get((address & OBJECT_ATTRIBUTES_SIZE) as usize),
+            0x08..=0x0D => self.rom.get((address & ROM_SIZE) as usize),
+            0x0E..=0x0F => self.backup.get(((address - BACKUP_START) & BACKUP_SIZE) as usize),
+            _ => None
+        }
+    }
+
+    fn slot_mut(&mut self, address: u32) -> Option<&mut u8> {
+        match address >> 24 {
+            0x00 if address <= BIOS_SIZE => self.bios.get_mut(address as usize),
+            0x02 => self.ewram.get_mut((address & ON_BOARD_WRAM_SIZE) as usize),
+            0x03 => self.iwram.get_mut((address & ON_CHIP_WRAM_SIZE) as usize),
+            0x04 if address - IO_START <= IO_SIZE => self.io.get_mut((address - IO_START) as usize),
+            0x05 => self.palette.get_mut((address & PALETTE_RAM_SIZE) as usize),
+            0x06 => self.vram.get_mut(GbaMem::vram_offset(address)),
+            0x07 => self.oam.get_mut((address & OBJECT_ATTRIBUTES_SIZE) as usize),
+            // the rom is read only, it's filled through load
+            0x0E..=0x0F => self.backup.get_mut(((address - BACKUP_START) & BACKUP_SIZE) as usize),
+            _ => None
+        }
+    }
+
+    pub fn read(&self, address: u32) -> u8 {
+        *self.slot(address).unwrap_or(&UNMAPPED)
+    }
+
+    pub fn write(&mut self, address: u32, value: u8) {
+        if let Some(byte) = self.slot_mut(address) {
+            *byte = value;
+        }
+    }
+
+    // a write that can also fill the rom, for loading a cartridge or code into memory
+    pub fn load(&mut self, address: u32, value: u8) {
+        if !(0x08..=0x0D).contains(&(address >> 24)) {
+            self.write(address, value);
+            return;
+        }
+
+        // the rom only takes as much space as what was loaded into it
+        let offset = (address & ROM_SIZE) as usize;
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = value;
+    }
+}
+
+impl Index<usize> for GbaMem {
+    type Output = u8;
+
+    fn index(&self, address: usize) -> &u8 {
+        self.slot(address as u32).unwrap_or(&UNMAPPED)
+    }
+}
+
+impl IndexMut<usize> for GbaMem {
+    // the rom, unmapped addresses and a bios left empty by a deserialize all write into a sink
+    fn index_mut(&mut self, address: usize) -> &mut u8 {
+        if self.slot_mut(address as u32).is_none() {
+            self.unmapped = 0;
+            return &mut self.unmapped;
+        }
+        self.slot_mut(address as u32).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vram_upper_32k_mirrors() {
+        let mut mem = GbaMem::new();
+        mem.write(0x0601_0000, 0x12);
+        assert_eq!(mem.read(0x0601_8000), 0x12);
+        assert_eq!(mem.read(0x0602_0000 + 0x1_0000), 0x12);
+        mem.write(0x0601_FFFF, 0x34);
+        assert_eq!(mem.read(0x0601_7FFF), 0x34);
+    }
+
+    #[test]
+    fn regions_mirror() {
+        let mut mem = GbaMem::new();
+        mem.write(0x0200_0010, 1);
+        mem.write(0x0300_0010, 2);
+        mem.write(0x0500_0010, 3);
+        mem.write(0x0700_0010, 4);
+        assert_eq!(mem.read(0x0204_0010), 1);
+        assert_eq!(mem.read(0x0300_8010), 2);
+        assert_eq!(mem.read(0x0500_0410), 3);
+        assert_eq!(mem.read(0x0700_0410), 4);
+    }
+
+    #[test]
+    fn rom_wait_state_mirrors() {
+        let mut mem = GbaMem::new();
+        mem.load(0x0800_0002, 0xAA);
+        assert_eq!(mem.read(0x0A00_0002), 0xAA);
+        assert_eq!(mem.read(0x0C00_0002), 0xAA);
+        assert_eq!(mem.read(0x0800_1000), 0);
+    }
+
+    #[test]
+    fn unmapped_addresses_read_zero() {
+        let mut mem = GbaMem::new();
+        mem[0x0100_0000] = 0xFF;
+        mem[0x0400_0800] = 0xFF;
+        assert_eq!(mem[0x0100_0000], 0);
+        assert_eq!(mem.read(0x0400_0800), 0);
+        assert_eq!(mem.read(0x1000_0000), 0);
+    }
+
+    #[test]
+    fn rom_is_read_only() {
+        let mut mem = GbaMem::new();
+        mem.load(0x0800_0000, 0xAA);
+        mem.write(0x0800_0000, 0x55);
+        mem.write(0x09FF_FFF0, 1);
+        mem[0x0800_0000] = 0x55;
+        assert_eq!(mem.read(0x0800_0000), 0xAA);
+        assert_eq!(mem.rom.len(), 1);
+
+        // a deserialized state has no bios or rom until they're reloaded
+        let mut mem: GbaMem = bincode::deserialize(&bincode::serialize(&GbaMem::new()).unwrap()).unwrap();
+        mem[0x0000_0010] = 0xFF;
+        mem[0x0800_0010] = 0xFF;
+        assert_eq!(mem[0x0000_0010], 0);
+    }
+}
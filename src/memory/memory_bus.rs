@@ -1,12 +1,18 @@
 use crate::memory::memory_map::MemoryMap;
 use crate::operations::timing::{CycleClock, MemAccessSize};
 use crate::gamepak::BackupType;
+use crate::debugger::watchpoint::{AccessKind, Watchpoint, WatchHit};
 use serde::{Serialize, Deserialize};
 
 #[derive(Serialize, Deserialize)]
 pub struct MemoryBus {
     pub mem_map: MemoryMap,
     pub cycle_clock: CycleClock,
+    // set up by the debugger, only data accesses are checked, not opcode fetches
+    #[serde(skip)]
+    pub watchpoints: Vec<Watchpoint>,
+    #[serde(skip)]
+    pub watch_hit: Option<WatchHit>
 }
 
 impl MemoryBus {
@@ -14,6 +20,8 @@ impl MemoryBus {
         return MemoryBus {
             mem_map: MemoryMap::new(backup_type),
             cycle_clock: CycleClock::new(),
+            watchpoints: Vec::new(),
+            watch_hit: None
         };
     }
 
@@ -23,26 +31,68 @@ impl MemoryBus {
 
     pub fn read_u8(&mut self, address: u32) -> u8 {
         self.cycle_clock.update_cycles(address, MemAccessSize::Mem8);
-        self.mem_map.read_u8(address)
+        let value = if self.mem_map.is_eeprom_address(address) {
+            self.mem_map.read_eeprom() as u8
+        } else {
+            self.mem_map.read_u8(address)
+        };
+        self.watch(address, 1, AccessKind::Read, value as u32);
+        value
     }
 
     pub fn read_u16(&mut self, address: u32) -> u16 {
         self.cycle_clock.update_cycles(address, MemAccessSize::Mem16);
-        self.mem_map.read_u16(address)
+        let value = if self.is_backup_address(address) {
+            self.mem_map.read_u8(address) as u16 * 0x0101
+        } else {
+            self.load_u16(address)
+        };
+        self.watch(address, 2, AccessKind::Read, value as u32);
+        value
     }
 
     pub fn read_u32(&mut self, address: u32) -> u32 {
         self.cycle_clock.update_cycles(address, MemAccessSize::Mem32);
+        let value = if self.is_backup_address(address) {
+            self.mem_map.read_u8(address) as u32 * 0x0101_0101
+        } else {
+            self.mem_map.read_u32(address)
+        };
+        self.watch(address, 4, AccessKind::Read, value);
+        value
+    }
+
+    // opcode fetches, these don't trigger read watchpoints and can come out of the prefetch buffer
+    pub fn fetch_u16(&mut self, address: u32) -> u16 {
+        self.cycle_clock.update_fetch_cycles(address, MemAccessSize::Mem16);
+        self.load_u16(address)
+    }
+
+    pub fn fetch_u32(&mut self, address: u32) -> u32 {
+        self.cycle_clock.update_fetch_cycles(address, MemAccessSize::Mem32);
         self.mem_map.read_u32(address)
     }
 
+    fn load_u16(&mut self, address: u32) -> u16 {
+        if self.mem_map.is_eeprom_address(address) {
+            return self.mem_map.read_eeprom();
+        }
+        self.mem_map.read_u16(address)
+    }
+
     pub fn write_u8(&mut self, address: u32, value: u8) {
         self.cycle_clock.update_cycles(address, MemAccessSize::Mem8);
+        self.watch(address, 1, AccessKind::Write, value as u32);
         self.mem_map.write_u8(address, value);
     }
 
     pub fn write_u16(&mut self, address: u32, value: u16) {
         self.cycle_clock.update_cycles(address, MemAccessSize::Mem16);
+        self.watch(address, 2, AccessKind::Write, value as u32);
+        if self.is_backup_address(address) {
+            self.mem_map.write_u8(address, (value >> (8 * (address & 1))) as u8);
+            return;
+        }
 
         // if address < 0x00003FFF {
         //     // panic!("Writing to bios: {:X}", address);
@@ -54,6 +104,11 @@ impl MemoryBus {
 
     pub fn write_u32(&mut self, address: u32, value: u32) {
         self.cycle_clock.update_cycles(address, MemAccessSize::Mem32);
+        self.watch(address, 4, AccessKind::Write, value);
+        if self.is_backup_address(address) {
+            self.mem_map.write_u8(address, (value >> (8 * (address & 3))) as u8);
+            return;
+        }
 
         if address < 0x00003FFF {
             // panic!("Writing to bios");
@@ -63,4 +118,75 @@ impl MemoryBus {
 
         self.mem_map.write_u32(address, value);
     }
+
+    // sram and flash sit on an 8 bit bus, wider reads see the byte repeated and wider writes only
+    // store the byte lined up with the address
+    fn is_backup_address(&self, address: u32) -> bool {
+        let backup_bus = match self.mem_map.backup_type {
+            BackupType::Sram | BackupType::Flash64K | BackupType::Flash128K => true,
+            BackupType::Eeprom | BackupType::Error => false
+        };
+        backup_bus && matches!(address >> 24, 0x0E | 0x0F)
+    }
+
+    // keeps the first hit until the debugger takes it
+    fn watch(&mut self, address: u32, size: u32, kind: AccessKind, value: u32) {
+        if self.watchpoints.is_empty() || self.watch_hit.is_some() {
+            return;
+        }
+
+        if let Some(watchpoint) = self.watchpoints.iter().find(|watchpoint| watchpoint.matches(address, size, kind)) {
+            self.watch_hit = Some(WatchHit {
+                id: watchpoint.id,
+                address,
+                kind,
+                value
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup_bus(backup_type: BackupType) -> MemoryBus {
+        let mut bus = MemoryBus::new(backup_type);
+        bus.cycle_clock.register(&bus.mem_map.memory);
+        bus
+    }
+
+    #[test]
+    fn backup_bus_is_eight_bits_wide() {
+        for backup_type in [BackupType::Sram, BackupType::Flash64K, BackupType::Flash128K] {
+            let mut bus = backup_bus(backup_type);
+            bus.mem_map.memory.borrow_mut().write(0x0E00_0000, 0x5A);
+            let byte = bus.read_u8(0x0E00_0000);
+            assert_eq!(bus.read_u16(0x0E00_0000), byte as u16 * 0x0101);
+            assert_eq!(bus.read_u32(0x0E00_0000), byte as u32 * 0x0101_0101);
+        }
+
+        let mut bus = backup_bus(BackupType::Sram);
+        bus.write_u16(0x0E00_0011, 0xAABB);
+        bus.write_u32(0x0E00_0022, 0x1122_3344);
+        assert_eq!(bus.read_u8(0x0E00_0010), 0);
+        assert_eq!(bus.read_u8(0x0E00_0011), 0xAA);
+        assert_eq!(bus.read_u8(0x0E00_0012), 0);
+        assert_eq!(bus.read_u8(0x0E00_0022), 0x22);
+        assert_eq!(bus.read_u8(0x0E00_0023), 0);
+        // the upper mirror
+        assert_eq!(bus.read_u8(0x0F00_0011), 0xAA);
+    }
+
+    #[test]
+    fn sram_wait_states() {
+        let mut bus = backup_bus(BackupType::Sram);
+        bus.read_u32(0x0E00_0000);
+        assert_eq!(bus.cycle_clock.get_cycles(), 5);
+
+        bus.mem_map.write_u16(0x0400_0204, 0b10);
+        bus.write_u16(0x0E00_0000, 0);
+        bus.read_u8(0x0E00_0001);
+        assert_eq!(bus.cycle_clock.get_cycles(), 3 + 3);
+    }
 }
@@ -2,6 +2,11 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use crate::gamepak::BackupType;
 use crate::gamepak::flash::Flash;
+use crate::gamepak::eeprom::Eeprom;
+use crate::gamepak::rtc::Rtc;
+use crate::apu::direct_sound::SoundFifo;
+use super::GbaMem;
+use super::open_bus::OpenBus;
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
 use serde::ser::SerializeStruct;
 use serde::de::{self, Visitor, MapAccess, SeqAccess};
@@ -32,22 +37,30 @@ pub enum HaltState {
 }
 
 pub struct MemoryMap {
-    pub memory: Rc<RefCell<Vec<u8>>>,
+    pub memory: Rc<RefCell<GbaMem>>,
     pub halt_state: HaltState,
     pub backup_type: BackupType,
     pub backed_up: bool,
-    pub flash: Flash
+    pub flash: Flash,
+    pub eeprom: Eeprom,
+    pub rtc: Rtc,
+    pub sound_fifos: [SoundFifo; 2],
+    pub open_bus: OpenBus
 }
 
 impl MemoryMap {
 
     pub fn new(backup_type: BackupType) -> MemoryMap {
         return MemoryMap {
-            memory: Rc::new(RefCell::new(vec![0; 0x1000_00F0])),
+            memory: Rc::new(RefCell::new(GbaMem::new())),
             halt_state: HaltState::Running,
             backup_type: backup_type,
             backed_up: false,
-            flash: Flash::new()
+            flash: Flash::new(),
+            eeprom: Eeprom::new(),
+            rtc: Rtc::new(),
+            sound_fifos: [SoundFifo::new(), SoundFifo::new()],
+            open_bus: OpenBus::new()
         }
     }
 
@@ -55,18 +68,19 @@ impl MemoryMap {
         let upper_byte = address >> 24;
 
         match upper_byte {
-            0x02 => self.memory.borrow_mut()[((address & ON_BOARD_WRAM_SIZE) + ON_BOARD_WRAM_START) as usize] = value,
-            0x03 => self.memory.borrow_mut()[((address & ON_CHIP_WRAM_SIZE) + ON_CHIP_WRAM_START) as usize] = value,
+            0x02 | 0x03 | 0x05..=0x07 => self.memory.borrow_mut().write(address, value),
             0x04 => {
                 if address == 0x4000202 || address == 0x4000203 {
                     let new_val = self.read_u8(address) & !value;
-                    self.memory.borrow_mut()[address as usize] = new_val;
+                    self.memory.borrow_mut().write(address, new_val);
                 }else if address == 0x4000100 || address == 0x4000101 ||
                    address == 0x4000104 || address == 0x4000105 ||
                    address == 0x4000108 || address == 0x4000109 ||
                    address == 0x400010C || address == 0x400010D {
-                    let index: usize = (address & 0xF) as usize;
-                    self.memory.borrow_mut()[0x1000_0000usize + index] = value;
+                    let shift = (address & 1) * 8;
+                    let mut mem = self.memory.borrow_mut();
+                    let reload = &mut mem.timer_reloads[((address & 0xF) >> 2) as usize];
+                    *reload = (*reload & !(0xFF << shift)) | ((value as u16) << shift);
                 } else if address == 0x4000301{
                     let bit = (value & 0x80) >> 7;
                     if bit == 0 {
@@ -78,36 +92,46 @@ impl MemoryMap {
                     }
                 }else if address == 0x4000130 ||  address == 0x4000131  {
                     // read only
+                }else if MemoryMap::is_fifo_address(address) {
+                    self.write_fifo(address, &[value]);
                 }else {
-                    self.memory.borrow_mut()[address as usize] = value;
+                    self.memory.borrow_mut().write(address, value);
                 }
 
             },
-            0x05 => self.memory.borrow_mut()[((address & PALETTE_RAM_SIZE) + PALETTE_RAM_START) as usize] = value,
-            0x06 => self.memory.borrow_mut()[address as usize] = value,
-            0x07 => self.memory.borrow_mut()[((address & OBJECT_ATTRIBUTES_SIZE) + OBJECT_ATTRIBUTES_START) as usize] = value,
             0x08..=0x0F => {
+                if MemoryMap::is_gpio_address(address) {
+                    self.write_gpio(address, value);
+                    return;
+                }
+
                 match self.backup_type {
                     BackupType::Sram => {
-                        /* don't need to do anything here */
-                        self.memory.borrow_mut()[address as usize] = value;
+                        if upper_byte == 0x0E || upper_byte == 0x0F {
+                            self.memory.borrow_mut().write((address & SRAM_SIZE) + SRAM_START, value);
+                        } else {
+                            self.memory.borrow_mut().write(address, value);
+                        }
                     },
                     BackupType::Eeprom => {
-                        // TODO implement EEPROM
-                        self.memory.borrow_mut()[address as usize] = value;
+                        if self.is_eeprom_address(address) {
+                            self.write_eeprom(address, value);
+                        } else {
+                            self.memory.borrow_mut().write(address, value);
+                        }
                     },
                     BackupType::Flash64K | BackupType::Flash128K => {
                         if upper_byte == 0x0E || upper_byte == 0x0F {
                             self.write_flash(address, value);
                         } else {
-                            self.memory.borrow_mut()[address as usize] = value;
+                            self.memory.borrow_mut().write(address, value);
                         }
                     },
                     // BackupType::Flash128K => {
                     //     self.memory.borrow_mut()[address as usize] = value;
                     // },
                     BackupType::Error => {
-                        self.memory.borrow_mut()[address as usize] = value;
+                        self.memory.borrow_mut().write(address, value);
                     },
                 }
             },
@@ -118,11 +142,21 @@ impl MemoryMap {
     }
 
     pub fn write_u16(&mut self, address: u32, value: u16) {
+        if MemoryMap::is_fifo_address(address) {
+            self.write_fifo(address, &value.to_le_bytes());
+            return;
+        }
+
         self.write_u8(address + 1, ((value & 0xFF00) >> 8) as u8);
         self.write_u8(address, (value & 0xFF) as u8);
     }
 
     pub fn write_u32(&mut self, address: u32, value: u32) {
+        if MemoryMap::is_fifo_address(address) {
+            self.write_fifo(address, &value.to_le_bytes());
+            return;
+        }
+
         self.write_u8(address + 3, ((value & 0xFF000000) >> 24) as u8);
         self.write_u8(address + 2, ((value & 0xFF0000) >> 16) as u8);
         self.write_u8(address + 1, ((value & 0xFF00) >> 8) as u8);
@@ -130,12 +164,10 @@ impl MemoryMap {
     }
 
     pub fn write_block(&mut self, address: u32, block: &Vec<u8>) {
-        let mut offset: u32 = 0;
         let mut mem = self.memory.borrow_mut();
 
-        for byte in block {
-            mem[(address + offset) as usize] = *byte;
-            offset += 1;
+        for (offset, byte) in block.iter().enumerate() {
+            mem.load(address + offset as u32, *byte);
         }
     }
 
@@ -150,7 +182,7 @@ impl MemoryMap {
     pub fn read_block_raw(&self, address: u32, bytes: u32) -> Vec<u8> {
         let mut temp: Vec<u8> = vec![];
         for i in address..(address + bytes) {
-            temp.push(self.memory.borrow()[i as usize]);
+            temp.push(self.memory.borrow().read(i));
         }
         return temp;
     }
@@ -172,31 +204,36 @@ impl MemoryMap {
         let upper_byte = address >> 24;
 
         match upper_byte {
-            0x02 => return self.memory.borrow()[((address & ON_BOARD_WRAM_SIZE) + ON_BOARD_WRAM_START) as usize],
-            0x03 => return self.memory.borrow()[((address & ON_CHIP_WRAM_SIZE) + ON_CHIP_WRAM_START) as usize],
-            0x04 => return self.memory.borrow()[address as usize],
-            0x05 => return self.memory.borrow()[((address & PALETTE_RAM_SIZE) + PALETTE_RAM_START) as usize],
-            0x06 => return self.memory.borrow()[address as usize],
-            0x07 => return self.memory.borrow()[((address & OBJECT_ATTRIBUTES_SIZE) + OBJECT_ATTRIBUTES_START) as usize],
+            0x00 => return self.read_bios(address),
+            0x02..=0x07 => return self.read_mapped(address),
             0x08..=0x0F => {
+                if MemoryMap::is_gpio_address(address) {
+                    if let Some(value) = self.read_gpio(address) {
+                        return value;
+                    }
+                }
+
                 match self.backup_type {
                     BackupType::Sram => {
                         /* don't need to do anything here */
-                        if upper_byte == 0x0E {
-                            return self.memory.borrow()[((address & SRAM_SIZE) + SRAM_START) as usize]
+                        if upper_byte == 0x0E || upper_byte == 0x0F {
+                            return self.memory.borrow().read((address & SRAM_SIZE) + SRAM_START)
                         } else {
-                            return self.memory.borrow()[address as usize];
+                            return self.read_rom(address);
                         }
                     },
                     BackupType::Eeprom => {
-                        // TODO implement EEPROM
-                        return self.memory.borrow()[address as usize];
+                        if self.is_eeprom_address(address) {
+                            // reads that advance the serial stream go through MemoryBus, this just reports ready
+                            return if address & 1 == 0 { 1 } else { 0 };
+                        }
+                        return self.read_rom(address);
                     },
                     BackupType::Flash64K | BackupType::Flash128K => {
                         if upper_byte == 0x0E || upper_byte == 0x0F {
                             return self.read_flash(address);
                         } else {
-                            return self.memory.borrow()[address as usize];
+                            return self.read_rom(address);
                         }
                     },
                     // BackupType::Flash128K => {
@@ -209,17 +246,11 @@ impl MemoryMap {
 
                     // },
                     BackupType::Error => {
-                        return self.memory.borrow()[address as usize];
+                        return self.read_rom(address);
                     },
                 }
             }
-            _ => { 
-                if address > 0x0FFFFFFF {
-                    return 0;
-                }
-
-                return self.memory.borrow()[address as usize]; 
-            }
+            _ => return self.read_open_bus(address)
         }
     }
 }
@@ -231,9 +262,9 @@ impl Serialize for MemoryMap {
         S: Serializer,
     {
         // Determine how many fields we're serializing
-        let mut state = serializer.serialize_struct("MemoryMap", 5)?;
+        let mut state = serializer.serialize_struct("MemoryMap", 9)?;
         
-        // Serialize memory by borrowing the RefCell and using the underlying Vec<u8>
+        // the bios and rom are left out, they're reloaded from their files
         state.serialize_field("memory", &*self.memory.borrow())?;
         
         // Serialize the rest of the fields normally
@@ -241,6 +272,10 @@ impl Serialize for MemoryMap {
         state.serialize_field("backup_type", &self.backup_type)?;
         state.serialize_field("backed_up", &self.backed_up)?;
         state.serialize_field("flash", &self.flash)?;
+        state.serialize_field("eeprom", &self.eeprom)?;
+        state.serialize_field("rtc", &self.rtc)?;
+        state.serialize_field("sound_fifos", &self.sound_fifos)?;
+        state.serialize_field("open_bus", &self.open_bus)?;
         
         state.end()
     }
@@ -253,7 +288,7 @@ impl<'de> Deserialize<'de> for MemoryMap {
         D: Deserializer<'de>,
     {
         // Define the fields we expect
-        enum Field { Memory, HaltState, BackupType, BackedUp, Flash }
+        enum Field { Memory, HaltState, BackupType, BackedUp, Flash, Eeprom, Rtc, SoundFifos, OpenBus }
         
         // Implement a deserializer for the field names
         impl<'de> Deserialize<'de> for Field {
@@ -267,7 +302,7 @@ impl<'de> Deserialize<'de> for MemoryMap {
                     type Value = Field;
 
                     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                        formatter.write_str("`memory`, `halt_state`, `backup_type`, `backed_up`, or `flash`")
+                        formatter.write_str("`memory`, `halt_state`, `backup_type`, `backed_up`, `flash`, `eeprom`, `rtc`, `sound_fifos`, or `open_bus`")
                     }
 
                     fn visit_str<E>(self, value: &str) -> Result<Field, E>
@@ -280,7 +315,11 @@ impl<'de> Deserialize<'de> for MemoryMap {
                             "backup_type" => Ok(Field::BackupType),
                             "backed_up" => Ok(Field::BackedUp),
                             "flash" => Ok(Field::Flash),
-                            _ => Err(de::Error::unknown_field(value, &["memory", "halt_state", "backup_type", "backed_up", "flash"])),
+                            "eeprom" => Ok(Field::Eeprom),
+                            "rtc" => Ok(Field::Rtc),
+                            "sound_fifos" => Ok(Field::SoundFifos),
+                            "open_bus" => Ok(Field::OpenBus),
+                            _ => Err(de::Error::unknown_field(value, &["memory", "halt_state", "backup_type", "backed_up", "flash", "eeprom", "rtc", "sound_fifos", "open_bus"])),
                         }
                     }
                 }
@@ -302,19 +341,27 @@ impl<'de> Deserialize<'de> for MemoryMap {
             fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
                 where
                     A: SeqAccess<'de>, {
-                let mem_vec = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let mem: GbaMem = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
                 let halt_state = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
                 let backup_type = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
                 let backed_up = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
                 let flash = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let eeprom = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let rtc = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let sound_fifos = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let open_bus = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
 
-                let memory = Rc::new(RefCell::new(mem_vec));
+                let memory = Rc::new(RefCell::new(mem));
                 Ok(MemoryMap {
                     memory,
                     halt_state,
                     backup_type,
                     backed_up,
                     flash,
+                    eeprom,
+                    rtc,
+                    sound_fifos,
+                    open_bus,
                 })
             }
 
@@ -327,6 +374,10 @@ impl<'de> Deserialize<'de> for MemoryMap {
                 let mut backup_type = None;
                 let mut backed_up = None;
                 let mut flash = None;
+                let mut eeprom = None;
+                let mut rtc = None;
+                let mut sound_fifos = None;
+                let mut open_bus = None;
 
                 // Extract each field from the map
                 while let Some(key) = map.next_key()? {
@@ -335,9 +386,8 @@ impl<'de> Deserialize<'de> for MemoryMap {
                             if memory.is_some() {
                                 return Err(de::Error::duplicate_field("memory"));
                             }
-                            // Deserialize directly into a Vec<u8>
-                            let mem_vec: Vec<u8> = map.next_value()?;
-                            memory = Some(Rc::new(RefCell::new(mem_vec)));
+                            let mem: GbaMem = map.next_value()?;
+                            memory = Some(Rc::new(RefCell::new(mem)));
                         }
                         Field::HaltState => {
                             if halt_state.is_some() {
@@ -363,6 +413,30 @@ impl<'de> Deserialize<'de> for MemoryMap {
                             }
                             flash = Some(map.next_value()?);
                         }
+                        Field::Eeprom => {
+                            if eeprom.is_some() {
+                                return Err(de::Error::duplicate_field("eeprom"));
+                            }
+                            eeprom = Some(map.next_value()?);
+                        }
+                        Field::Rtc => {
+                            if rtc.is_some() {
+                                return Err(de::Error::duplicate_field("rtc"));
+                            }
+                            rtc = Some(map.next_value()?);
+                        }
+                        Field::SoundFifos => {
+                            if sound_fifos.is_some() {
+                                return Err(de::Error::duplicate_field("sound_fifos"));
+                            }
+                            sound_fifos = Some(map.next_value()?);
+                        }
+                        Field::OpenBus => {
+                            if open_bus.is_some() {
+                                return Err(de::Error::duplicate_field("open_bus"));
+                            }
+                            open_bus = Some(map.next_value()?);
+                        }
                     }
                 }
 
@@ -372,6 +446,10 @@ impl<'de> Deserialize<'de> for MemoryMap {
                 let backup_type = backup_type.ok_or_else(|| de::Error::missing_field("backup_type"))?;
                 let backed_up = backed_up.ok_or_else(|| de::Error::missing_field("backed_up"))?;
                 let flash = flash.ok_or_else(|| de::Error::missing_field("flash"))?;
+                let eeprom = eeprom.ok_or_else(|| de::Error::missing_field("eeprom"))?;
+                let rtc = rtc.ok_or_else(|| de::Error::missing_field("rtc"))?;
+                let sound_fifos = sound_fifos.ok_or_else(|| de::Error::missing_field("sound_fifos"))?;
+                let open_bus = open_bus.ok_or_else(|| de::Error::missing_field("open_bus"))?;
 
                 // Return the constructed struct
                 Ok(MemoryMap {
@@ -380,6 +458,10 @@ impl<'de> Deserialize<'de> for MemoryMap {
                     backup_type,
                     backed_up,
                     flash,
+                    eeprom,
+                    rtc,
+                    sound_fifos,
+                    open_bus,
                 })
             }
         }
@@ -387,7 +469,7 @@ impl<'de> Deserialize<'de> for MemoryMap {
         // Start the deserialization process
         deserializer.deserialize_struct(
             "MemoryMap",
-            &["memory", "halt_state", "backup_type", "backed_up", "flash"],
+            &["memory", "halt_state", "backup_type", "backed_up", "flash", "eeprom", "rtc", "sound_fifos", "open_bus"],
             MemoryMapVisitor
         )
     }
@@ -7,5 +7,7 @@ pub mod memory_bus;
 pub mod dma_registers;
 pub mod timer_registers;
 pub mod sound_registers;
+pub mod gba_mem;
+pub mod open_bus;
 
-pub type GbaMem = Vec<u8>;
+pub use gba_mem::GbaMem;
@@ -0,0 +1,162 @@
+use super::memory_map::MemoryMap;
+use super::gba_mem::{GbaMem, BIOS_SIZE};
+use serde::{Serialize, Deserialize};
+
+// what the bios leaves on the bus once it has jumped to the cartridge
+pub const BIOS_BOOT_OPCODE: u32 = 0xE129F000;
+
+// Values the bus holds onto between accesses. Reads from unmapped memory see the last
+// prefetched opcode, reads from the bios while running outside of it see the last bios opcode.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct OpenBus {
+    pub pc: u32,
+    pub value: u32,
+    pub bios_value: u32
+}
+
+impl OpenBus {
+    pub fn new() -> OpenBus {
+        OpenBus {
+            pc: 0,
+            value: 0,
+            bios_value: BIOS_BOOT_OPCODE
+        }
+    }
+}
+
+impl Default for OpenBus {
+    fn default() -> Self {
+        OpenBus::new()
+    }
+}
+
+impl MemoryMap {
+    // called before every opcode fetch with the address being fetched
+    pub fn update_open_bus(&mut self, pc: u32, thumb: bool) {
+        let value = {
+            let mem = self.memory.borrow();
+            let read_u16 = |address: u32| mem.read(address) as u32 | ((mem.read(address.wrapping_add(1)) as u32) << 8);
+
+            if !thumb {
+                read_u16(pc.wrapping_add(8)) | (read_u16(pc.wrapping_add(10)) << 16)
+            } else {
+                // thumb only prefetches a halfword, what ends up in the other half depends on the bus width
+                match pc >> 24 {
+                    0x00 | 0x07 => if pc.wrapping_add(4) & 2 == 0 {
+                        read_u16(pc.wrapping_add(4)) | (read_u16(pc.wrapping_add(6)) << 16)
+                    } else {
+                        read_u16(pc.wrapping_add(2)) | (read_u16(pc.wrapping_add(4)) << 16)
+                    },
+                    0x03 => if pc.wrapping_add(4) & 2 == 0 {
+                        read_u16(pc.wrapping_add(4)) | (read_u16(pc.wrapping_add(2)) << 16)
+                    } else {
+                        read_u16(pc.wrapping_add(2)) | (read_u16(pc.wrapping_add(4)) << 16)
+                    },
+                    _ => read_u16(pc.wrapping_add(4)) * 0x0001_0001
+                }
+            }
+        };
+
+        self.open_bus.pc = pc;
+        self.open_bus.value = value;
+        if pc <= BIOS_SIZE {
+            self.open_bus.bios_value = value;
+        }
+    }
+
+    pub fn read_open_bus(&self, address: u32) -> u8 {
+        (self.open_bus.value >> ((address & 3) * 8)) as u8
+    }
+
+    // the bios can only be read by code running from the bios
+    pub fn read_bios(&self, address: u32) -> u8 {
+        if address > BIOS_SIZE {
+            self.read_open_bus(address)
+        } else if self.open_bus.pc > BIOS_SIZE {
+            (self.open_bus.bios_value >> ((address & 3) * 8)) as u8
+        } else {
+            self.memory.borrow().read(address)
+        }
+    }
+
+    // past the end of the rom the cartridge bus returns the halfword address
+    pub fn read_rom(&self, address: u32) -> u8 {
+        let mem = self.memory.borrow();
+        if (0x08..=0x0D).contains(&(address >> 24)) && !mem.rom.is_empty() && (address & 0x1FF_FFFF) as usize >= mem.rom.len() {
+            ((address >> 1) >> ((address & 1) * 8)) as u8
+        } else {
+            mem.read(address)
+        }
+    }
+
+    pub fn read_mapped(&self, address: u32) -> u8 {
+        if GbaMem::is_mapped(address) {
+            self.memory.borrow().read(address)
+        } else {
+            self.read_open_bus(address)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gba::GBA;
+    use crate::cpu::cpu::ARM_PC;
+
+    fn rom_gba() -> GBA {
+        let mut gba: GBA = GBA::default();
+        // mov r0, r0 over and over
+        let rom: Vec<u8> = [0xE1A0_0000u32; 8].iter().flat_map(|word| word.to_le_bytes().to_vec()).collect();
+        gba.load_rom(&rom);
+        gba.load_bios(&vec![0x11; 0x4000]);
+        gba
+    }
+
+    #[test]
+    fn unmapped_reads_return_prefetch() {
+        let mut gba = rom_gba();
+        gba.cpu.set_register(ARM_PC, 0x0800_0000);
+        gba.single_step();
+
+        assert_eq!(gba.memory_bus.mem_map.read_u32(0x0100_0000), 0xE1A0_0000);
+        assert_eq!(gba.memory_bus.mem_map.read_u32(0x1000_0000), 0xE1A0_0000);
+        assert_eq!(gba.memory_bus.mem_map.read_u16(0x0400_0802), 0xE1A0);
+    }
+
+    #[test]
+    fn bios_is_protected_outside_bios() {
+        let mut gba = rom_gba();
+        gba.cpu.set_register(ARM_PC, 0x0800_0000);
+        gba.single_step();
+        assert_eq!(gba.memory_bus.mem_map.read_u32(0x0000_0100), super::BIOS_BOOT_OPCODE);
+
+        gba.memory_bus.mem_map.update_open_bus(0x0000_0100, false);
+        assert_eq!(gba.memory_bus.mem_map.read_u32(0x0000_0100), 0x1111_1111);
+        assert_eq!(gba.memory_bus.mem_map.open_bus.bios_value, 0x1111_1111);
+    }
+
+    #[test]
+    fn prefetch_wraps_at_the_top_of_the_address_space() {
+        let mut gba = rom_gba();
+        gba.memory_bus.mem_map.update_open_bus(0xFFFF_FFFC, false);
+        gba.memory_bus.mem_map.update_open_bus(0xFFFF_FFFE, true);
+        assert_eq!(gba.memory_bus.mem_map.open_bus.pc, 0xFFFF_FFFE);
+    }
+
+    #[test]
+    fn rom_reads_past_the_end_return_address() {
+        let gba = rom_gba();
+        assert_eq!(gba.memory_bus.mem_map.read_u16(0x0800_1000), 0x0800);
+        assert_eq!(gba.memory_bus.mem_map.read_u16(0x0812_3456), 0x1A2B);
+    }
+
+    #[test]
+    fn rom_writes_are_dropped() {
+        let mut gba = rom_gba();
+        gba.memory_bus.write_u8(0x09FF_FFF0, 1);
+        gba.memory_bus.write_u32(0x0800_0000, 0);
+        assert_eq!(gba.memory_bus.mem_map.memory.borrow().rom.len(), 0x20);
+        assert_eq!(gba.memory_bus.mem_map.read_u8(0x09FF_FFF0), 0xF8);
+        assert_eq!(gba.memory_bus.mem_map.read_u32(0x0800_0000), 0xE1A0_0000);
+    }
+}
@@ -20,9 +20,7 @@ io_register! (
 impl TimerDataRegister {
     pub fn get_reload(&self) -> u16 {
         if let Some(mem) = &self.memory {
-            let mem_ref = mem.borrow();
-            let address = 0x1000_0000 + (TimerDataRegister::SEGMENT_INDICIES[self.index] & 0xF); 
-            return (mem_ref[address] as u32 | ((mem_ref[address + 1] as u32) << 8)) as u16;
+            return mem.borrow().timer_reloads[self.index];
         } else {
             panic!("IO register was accessed without being registered");
         }
@@ -30,11 +28,7 @@ impl TimerDataRegister {
 
     pub fn write_reload(&mut self, value: u16) {
         if let Some(mem) = &self.memory {
-            let mut mem_ref = mem.borrow_mut();
-            let address = 0x1000_0000 + (self.index * 2); 
-
-            mem_ref[address] = (value & 0xFF) as u8;
-            mem_ref[address + 1] = ((value & 0xFF00) >> 8) as u8;
+            mem.borrow_mut().timer_reloads[self.index] = value;
         } else {
             panic!("IO register was accessed without being registered");
         }
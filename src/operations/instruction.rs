@@ -5,5 +5,7 @@ use crate::memory::memory_bus::MemoryBus;
 pub trait Instruction {
     fn execute(&self, cpu: &mut CPU, mem_bus: &mut MemoryBus) -> u32;
     fn asm(&self) -> String;
-    fn cycles(&self) -> u32;
+    // internal (I) cycles, asked for before execute since multiplies depend on the operands. The S and
+    // N cycles come from the bus accesses, opcode fetches included
+    fn cycles(&self, cpu: &CPU) -> u32;
 }
\ No newline at end of file
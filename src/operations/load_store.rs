@@ -129,8 +129,9 @@ pub fn data_transfer_execute(transfer_info: DataTransfer, base_address: u32, add
 
     } else {
         let mut value_to_store = cpu.get_register(transfer_info.destination);
+        // stores of the pc are an instruction further ahead than reads
         if transfer_info.destination == 15 {
-            value_to_store += 8;
+            value_to_store += 4;
         }
 
         store(transfer_info.data_type, value_to_store, address, mem_bus);
@@ -191,7 +192,7 @@ mod tests {
 
     #[test]
     fn test_store_halfword() {
-        let memory_address = 0x08000000;
+        let memory_address = 0x02000000;
         let value_to_store = 0x8080;
         let mut gba = GBA::default();
 
@@ -202,7 +203,7 @@ mod tests {
 
     #[test]
     fn test_store_byte() {
-        let memory_address = 0x08000000;
+        let memory_address = 0x02000000;
         let value_to_store = 0x80;
         let mut gba = GBA::default();
 
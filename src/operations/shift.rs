@@ -69,10 +69,12 @@ pub fn apply_shift(base_value: u32, shift: &Shift, cpu: &mut CPU) -> (u32, Optio
     if shift.immediate {
         return apply_shift_imm(base_value, &shift.shift_type, shift.shift_amount as u32, cpu)
     } else {
+        // like rm and rn, the pc has moved on to pc + 12 by the time the register shift reads it
+        let mut shift_register = cpu.get_register(shift.shift_register);
         if shift.shift_register == 15 {
-            panic!("Can't have RS of 15");
+            shift_register += 4;
         }
-        shift_amount = cpu.get_register(shift.shift_register) & 0xFF;
+        shift_amount = shift_register & 0xFF;
         return apply_shift_reg(base_value, &shift.shift_type, shift_amount as u32, cpu);
     }
 }
@@ -1,4 +1,4 @@
-use crate::memory::system_control::WaitStateControl;
+use crate::memory::{system_control::WaitStateControl, GbaMem};
 use std::cell::RefCell;
 use std::rc::Rc;
 use serde::{Serialize, Deserialize};
@@ -7,10 +7,35 @@ use serde::{Serialize, Deserialize};
 pub struct CycleClock {
     pub prev_address: u32,
     pub cycles: u32,
+    pub prefetch: PrefetchBuffer,
     #[serde(skip)]
     pub wait_state_control: WaitStateControl,
 }
 
+// Halfwords read ahead from the cart while the gamepak bus would otherwise be idle, enabled by
+// WAITCNT bit 14. An opcode fetch that hits the buffer takes a single cycle.
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, Copy, Clone)]
+pub struct PrefetchBuffer {
+    pub active: bool,
+    // address of the oldest buffered halfword, the one being fetched is `count` halfwords on
+    pub head: u32,
+    pub count: u32,
+    // cycles spent so far on the halfword being fetched
+    pub progress: u32
+}
+
+pub const PREFETCH_CAPACITY: u32 = 8;
+
+impl PrefetchBuffer {
+    pub fn flush(&mut self) {
+        *self = PrefetchBuffer::default();
+    }
+
+    fn restart(&mut self, head: u32) {
+        *self = PrefetchBuffer { active: true, head, count: 0, progress: 0 };
+    }
+}
+
 pub const BIOS_START: u32 = 0x0000_0000;
 pub const EWRAM_START: u32 = 0x0200_0000;
 pub const IWRAM_START: u32 = 0x0300_0000;
@@ -24,6 +49,13 @@ pub const GAMEPAK_WS1_START: u32 = 0x0A00_0000;
 pub const GAMEPAK_WS1_HI: u32 = 0x0B00_0000;
 pub const GAMEPAK_WS2_START: u32 = 0x0C00_0000;
 pub const GAMEPAK_WS2_HI: u32 = 0x0D00_0000;
+pub const SRAM_START: u32 = 0x0E00_0000;
+pub const SRAM_HI: u32 = 0x0F00_0000;
+
+const NONSEQ_WAITS: [u32; 4] = [4, 3, 2, 8];
+const WS0_SEQ_WAITS: [u32; 2] = [2, 1];
+const WS1_SEQ_WAITS: [u32; 2] = [4, 1];
+const WS2_SEQ_WAITS: [u32; 2] = [8, 1];
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Copy, Clone)]
 pub enum MemAccessSize {
@@ -43,90 +75,143 @@ impl CycleClock {
         return CycleClock {
             prev_address: 0,
             cycles: 0,
+            prefetch: PrefetchBuffer::default(),
             wait_state_control: WaitStateControl::new(),
         };
     }
 
-    pub fn register(&mut self, mem: &Rc<RefCell<Vec<u8>>>) {
+    pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>) {
         self.wait_state_control.register(mem);
     }
 
     pub fn update_cycles(&mut self, address: u32, access_size: MemAccessSize) {
-        let nonseq_cycles = [4, 3, 2, 8];
-        let ws0_seq_cycles = [2, 1];
-        let ws1_seq_cycles = [4, 1];
-        let ws2_seq_cycles = [8, 1];
+        let cycles = self.access_cycles(address, access_size);
+        self.cycles += cycles;
+        if is_gamepak(address) {
+            // data from the cart takes the bus away from the prefetcher
+            self.prefetch.flush();
+        } else {
+            self.run_prefetch(cycles);
+        }
+    }
+
+    // opcode fetches are the only accesses the prefetch buffer can serve
+    pub fn update_fetch_cycles(&mut self, address: u32, access_size: MemAccessSize) {
+        if !is_gamepak(address) || self.wait_state_control.get_gamepak_prefetch_buffer() == 0 {
+            self.update_cycles(address, access_size);
+            return;
+        }
+
+        let halfwords = if access_size == MemAccessSize::Mem32 { 2 } else { 1 };
+        if !self.prefetch.active || self.prefetch.head != address {
+            // a non sequential fetch or a branch, start over from after it
+            self.cycles += self.access_cycles(address, access_size);
+            self.prefetch.restart(address.wrapping_add(2 * halfwords));
+            return;
+        }
+
+        self.prev_address = address;
+        let sequential = 1 + self.gamepak_waits(address).1;
+        for _ in 0..halfwords {
+            if self.prefetch.count > 0 {
+                self.prefetch.count -= 1;
+                self.cycles += 1;
+                self.run_prefetch(1);
+            } else {
+                // wait out the halfword that's on its way
+                self.cycles += sequential.saturating_sub(self.prefetch.progress).max(1);
+                self.prefetch.progress = 0;
+            }
+            self.prefetch.head = self.prefetch.head.wrapping_add(2);
+        }
+    }
+
+    // internal cycles leave the bus free for the prefetcher
+    pub fn internal(&mut self, cycles: u32) {
+        self.cycles += cycles;
+        self.run_prefetch(cycles);
+    }
+
+    fn run_prefetch(&mut self, cycles: u32) {
+        if !self.prefetch.active || self.prefetch.count >= PREFETCH_CAPACITY || self.wait_state_control.get_gamepak_prefetch_buffer() == 0 {
+            return;
+        }
+
+        let sequential = 1 + self.gamepak_waits(self.prefetch.head).1;
+        self.prefetch.progress += cycles;
+        while self.prefetch.progress >= sequential && self.prefetch.count < PREFETCH_CAPACITY {
+            self.prefetch.progress -= sequential;
+            self.prefetch.count += 1;
+        }
+        if self.prefetch.count == PREFETCH_CAPACITY {
+            self.prefetch.progress = 0;
+        }
+    }
+
+    // first and second access wait states for the gamepak region `address` is in
+    fn gamepak_waits(&self, address: u32) -> (u32, u32) {
+        match address & 0xFF00_0000 {
+            GAMEPAK_WS0_START | GAMEPAK_WS0_HI => (
+                NONSEQ_WAITS[self.wait_state_control.get_wait_state_zero_first_access() as usize],
+                WS0_SEQ_WAITS[self.wait_state_control.get_wait_state_zero_second_access() as usize]
+            ),
+            GAMEPAK_WS1_START | GAMEPAK_WS1_HI => (
+                NONSEQ_WAITS[self.wait_state_control.get_wait_state_one_first_access() as usize],
+                WS1_SEQ_WAITS[self.wait_state_control.get_wait_state_one_second_access() as usize]
+            ),
+            _ => (
+                NONSEQ_WAITS[self.wait_state_control.get_wait_state_two_first_access() as usize],
+                WS2_SEQ_WAITS[self.wait_state_control.get_wait_state_two_second_access() as usize]
+            )
+        }
+    }
+
+    fn access_cycles(&mut self, address: u32, access_size: MemAccessSize) -> u32 {
         let access_type = self.is_sequential(address, access_size);
         self.prev_address = address;
         match address & 0xFF00_0000 {
-            BIOS_START | IWRAM_START | IOMEM_START => self.cycles += 1,
+            BIOS_START | IWRAM_START | IOMEM_START => 1,
             EWRAM_START => {
                 // Might need to revisit this in relation to wait states
                 match access_size {
-                    MemAccessSize::Mem8 | MemAccessSize::Mem16 => self.cycles += 3,
-                    MemAccessSize::Mem32 => self.cycles += 6
+                    MemAccessSize::Mem8 | MemAccessSize::Mem16 => 3,
+                    MemAccessSize::Mem32 => 6
                 }
             }
             PALRAM_START | VRAM_START => {
                 // TODO Plus 1 cycle if GBA accesses video memory at the same time.
                 match access_size {
-                    MemAccessSize::Mem8 | MemAccessSize::Mem16 => self.cycles += 1,
-                    MemAccessSize::Mem32 => self.cycles += 2
+                    MemAccessSize::Mem8 | MemAccessSize::Mem16 => 1,
+                    MemAccessSize::Mem32 => 2
                 }
             }
             OAM_START => {
                 // TODO Plus 1 cycle if GBA accesses video memory at the same time.
-                self.cycles += 1;
+                1
             }
-            GAMEPAK_WS0_START | GAMEPAK_WS0_HI => {
-                match access_type {
-                    CycleType::N => {
-                        self.cycles += nonseq_cycles[self.wait_state_control.get_wait_state_zero_first_access() as usize];
-                        if access_size == MemAccessSize::Mem32 {
-                            self.cycles += ws0_seq_cycles[self.wait_state_control.get_wait_state_zero_second_access() as usize];
-                        }
-                    }
-                    CycleType::S => {
-                        self.cycles += ws0_seq_cycles[self.wait_state_control.get_wait_state_zero_second_access() as usize];
-                        if access_size == MemAccessSize::Mem32 {
-                            self.cycles += ws0_seq_cycles[self.wait_state_control.get_wait_state_zero_second_access() as usize];
-                        }
-                    }
-                }
-            }
-            GAMEPAK_WS1_START | GAMEPAK_WS1_HI => {
-                match access_type {
-                    CycleType::N => {
-                        self.cycles += nonseq_cycles[self.wait_state_control.get_wait_state_one_first_access() as usize];
-                        if access_size == MemAccessSize::Mem32 {
-                            self.cycles += ws1_seq_cycles[self.wait_state_control.get_wait_state_one_second_access() as usize];
-                        }
-                    }
-                    CycleType::S => {
-                        self.cycles += ws1_seq_cycles[self.wait_state_control.get_wait_state_one_second_access() as usize];
-                        if access_size == MemAccessSize::Mem32 {
-                            self.cycles += ws1_seq_cycles[self.wait_state_control.get_wait_state_one_second_access() as usize];
-                        }
-                    }
-                }
+            GAMEPAK_WS0_START | GAMEPAK_WS0_HI | GAMEPAK_WS1_START | GAMEPAK_WS1_HI | GAMEPAK_WS2_START | GAMEPAK_WS2_HI => {
+                let (first, second) = self.gamepak_waits(address);
+                CycleClock::gamepak_cycles(access_type, access_size, first, second)
             }
-            GAMEPAK_WS2_START | GAMEPAK_WS2_HI => {
-                match access_type {
-                    CycleType::N => {
-                        self.cycles += nonseq_cycles[self.wait_state_control.get_wait_state_two_first_access() as usize];
-                        if access_size == MemAccessSize::Mem32 {
-                            self.cycles += ws2_seq_cycles[self.wait_state_control.get_wait_state_two_second_access() as usize];
-                        }
-                    }
-                    CycleType::S => {
-                        self.cycles += ws2_seq_cycles[self.wait_state_control.get_wait_state_two_second_access() as usize];
-                        if access_size == MemAccessSize::Mem32 {
-                            self.cycles += ws2_seq_cycles[self.wait_state_control.get_wait_state_two_second_access() as usize];
-                        }
-                    }
-                }
+            SRAM_START | SRAM_HI => {
+                // an 8 bit bus, every access is a single byte whatever its width
+                1 + NONSEQ_WAITS[self.wait_state_control.get_sram_wait_control() as usize]
             }
-            _ => { }//log::error!("Trying to read unknown address: {:X}", address) }
+            _ => 0 //log::error!("Trying to read unknown address: {:X}", address) }
+        }
+    }
+
+    // the wait states are on top of the access itself, and the bus is 16 bits wide so a word is
+    // two accesses with the second always sequential
+    fn gamepak_cycles(access_type: CycleType, access_size: MemAccessSize, first: u32, second: u32) -> u32 {
+        let access = match access_type {
+            CycleType::N => 1 + first,
+            CycleType::S => 1 + second
+        };
+        if access_size == MemAccessSize::Mem32 {
+            access + 1 + second
+        } else {
+            access
         }
     }
 
@@ -150,11 +235,31 @@ impl CycleClock {
     }
 }
 
+// m for a multiply, how many bytes of the multiplier have to be looked at before the rest are all
+// zeroes, or all ones for a signed multiply
+pub fn multiply_cycles(multiplier: u32, signed: bool) -> u32 {
+    let done = |mask: u32| multiplier & mask == 0 || (signed && multiplier & mask == mask);
+    if done(0xFFFF_FF00) {
+        1
+    } else if done(0xFFFF_0000) {
+        2
+    } else if done(0xFF00_0000) {
+        3
+    } else {
+        4
+    }
+}
+
+fn is_gamepak(address: u32) -> bool {
+    (GAMEPAK_WS0_START..GAMEPAK_WS2_HI + 0x0100_0000).contains(&address)
+}
+
 impl Default for CycleClock {
     fn default() -> Self {
         CycleClock {
             prev_address: 0,
             cycles: 0,
+            prefetch: PrefetchBuffer::default(),
             wait_state_control: WaitStateControl::new(),
         }
     }
@@ -162,7 +267,204 @@ impl Default for CycleClock {
 
 #[cfg(test)]
 mod tests {
+    use super::multiply_cycles;
     use crate::gba::GBA;
+    use crate::cpu::cpu::{InstructionSet, ARM_PC};
+
+    const DATA: u32 = 0x0300_0100;
+
+    // cycles for one instruction at `address`, run after a nop so the pipeline is already full
+    fn step_cycles(address: u32, set: InstructionSet, instruction: u32, registers: &[(u8, u32)]) -> usize {
+        let mut gba: GBA = GBA::default();
+        gba.memory_bus.mem_map.write_u32(DATA, 0x0300_0200);
+        match set {
+            InstructionSet::Arm => {
+                let code: Vec<u8> = [0xE1A0_0000, instruction, 0xE1A0_0000].iter().flat_map(|word| word.to_le_bytes().to_vec()).collect();
+                gba.memory_bus.mem_map.write_block(address - 4, &code);
+            },
+            InstructionSet::Thumb => {
+                let code: Vec<u8> = [0x46C0, instruction as u16, 0x46C0].iter().flat_map(|half| half.to_le_bytes().to_vec()).collect();
+                gba.memory_bus.mem_map.write_block(address - 2, &code);
+            }
+        }
+        gba.cpu.set_instruction_set(set);
+        for (register, value) in registers {
+            gba.cpu.set_register_unsafe(*register, *value);
+        }
+        gba.cpu.set_register_unsafe(ARM_PC, address - if set == InstructionSet::Arm { 4 } else { 2 });
+        gba.cpu.fetch(&mut gba.memory_bus);
+        gba.cpu.fetch(&mut gba.memory_bus)
+    }
+
+    #[test]
+    fn arm_cycles_match_the_datasheet() {
+        // iwram, where S and N are both a single cycle
+        let table: &[(&str, u32, &[(u8, u32)], usize)] = &[
+            ("mov r0, r1", 0xE1A0_0001, &[], 1),                                 // 1S
+            ("moveq r0, r1 (not taken)", 0x01A0_0001, &[], 1),                   // 1S
+            ("add r0, r1, r2, lsl r3", 0xE081_0312, &[], 2),                     // 1S + 1I
+            ("mov pc, r1", 0xE1A0_F001, &[(1, DATA)], 3),                        // 2S + 1N
+            ("mrs r0, cpsr", 0xE10F_0000, &[], 1),                               // 1S
+            ("ldr r0, [r1]", 0xE591_0000, &[(1, DATA)], 3),                      // 1S + 1N + 1I
+            ("ldr pc, [r1]", 0xE591_F000, &[(1, DATA)], 5),                      // 2S + 2N + 1I
+            ("str r0, [r1]", 0xE581_0000, &[(1, DATA)], 2),                      // 2N
+            ("ldrh r0, [r1]", 0xE1D1_00B0, &[(1, DATA)], 3),                     // 1S + 1N + 1I
+            ("strh r0, [r1]", 0xE1C1_00B0, &[(1, DATA)], 2),                     // 2N
+            ("ldmia r1, {r2-r5}", 0xE891_003C, &[(1, DATA)], 6),                 // 4S + 1N + 1I
+            ("stmia r1, {r2-r5}", 0xE881_003C, &[(1, DATA)], 5),                 // 3S + 2N
+            ("swp r0, r1, [r2]", 0xE102_0091, &[(2, DATA)], 4),                  // 1S + 2N + 1I
+            ("mul r0, r1, r2 (m = 1)", 0xE000_0291, &[(2, 0xFF)], 2),            // 1S + 1I
+            ("mul r0, r1, r2 (m = 1, negative)", 0xE000_0291, &[(2, 0xFFFF_FF80)], 2),
+            ("mul r0, r1, r2 (m = 4)", 0xE000_0291, &[(2, 0x1234_5678)], 5),     // 1S + 4I
+            ("mla r0, r1, r2, r3 (m = 2)", 0xE020_3291, &[(2, 0x100)], 4),       // 1S + 3I
+            ("umull r0, r1, r2, r3 (m = 4)", 0xE081_0392, &[(3, 0xFFFF_FFFF)], 6), // 1S + 5I
+            ("smull r0, r1, r2, r3 (m = 1)", 0xE0C1_0392, &[(3, 0xFFFF_FFFF)], 3), // 1S + 2I
+            ("smlal r0, r1, r2, r3 (m = 1)", 0xE0E1_0392, &[(3, 1)], 4),         // 1S + 3I
+            ("b", 0xEA00_0000, &[], 3),                                          // 2S + 1N
+            ("bl", 0xEB00_0000, &[], 3),                                         // 2S + 1N
+            ("bx r1", 0xE12F_FF11, &[(1, DATA)], 3),                             // 2S + 1N
+            ("swi 0", 0xEF00_0000, &[], 3),                                      // 2S + 1N
+            ("undefined", 0xE600_0010, &[], 4)                                   // 2S + 1N + 1I
+        ];
+        for (name, instruction, registers, cycles) in table {
+            assert_eq!(step_cycles(0x0300_0004, InstructionSet::Arm, *instruction, registers), *cycles, "{}", name);
+        }
+    }
+
+    #[test]
+    fn thumb_cycles_match_the_datasheet() {
+        let table: &[(&str, u32, &[(u8, u32)], usize)] = &[
+            ("lsl r0, r1, #2", 0x0088, &[], 1),                                  // 1S
+            ("lsl r0, r1", 0x4088, &[], 2),                                      // 1S + 1I
+            ("mul r0, r1 (m = 1)", 0x4348, &[(0, 0x10)], 2),                     // 1S + 1I
+            ("mul r0, r1 (m = 3)", 0x4348, &[(0, 0x10_0000)], 4),                // 1S + 3I
+            ("add r0, pc, #0", 0xA000, &[], 1),                                  // 1S
+            ("mov pc, r1", 0x468F, &[(1, DATA)], 3),                             // 2S + 1N
+            ("ldr r0, [pc, #0]", 0x4800, &[], 3),                                // 1S + 1N + 1I
+            ("ldr r0, [r1, #0]", 0x6808, &[(1, DATA)], 3),                       // 1S + 1N + 1I
+            ("str r0, [r1, #0]", 0x6008, &[(1, DATA)], 2),                       // 2N
+            ("ldrsh r0, [r1, r2]", 0x5E88, &[(1, DATA)], 3),                     // 1S + 1N + 1I
+            ("push {r0, r1}", 0xB403, &[(13, 0x0300_7F00)], 3),                  // 1S + 2N
+            ("pop {r0, pc}", 0xBD01, &[(13, DATA)], 6),                          // 2S + 2N + 1I + 1S
+            ("stmia r1!, {r2, r3}", 0xC10C, &[(1, DATA)], 3),                    // 1S + 2N
+            ("beq (not taken)", 0xD000, &[], 1),                                 // 1S
+            ("b", 0xE000, &[], 3),                                               // 2S + 1N
+            ("bl, first half", 0xF000, &[], 1),                                  // 1S
+            ("bl, second half", 0xF800, &[], 3),                                 // 2S + 1N
+            ("bx r1", 0x4708, &[(1, DATA)], 3),                                  // 2S + 1N
+            ("swi 0", 0xDF00, &[], 3)                                            // 2S + 1N
+        ];
+        for (name, instruction, registers, cycles) in table {
+            assert_eq!(step_cycles(0x0300_0002, InstructionSet::Thumb, *instruction, registers), *cycles, "{}", name);
+        }
+    }
+
+    #[test]
+    fn gamepak_wait_states() {
+        // waitcnt is 0 after reset, 4 wait states for a non sequential access and 2 for sequential
+        // ones, opcode fetches are two halfword accesses
+        assert_eq!(step_cycles(0x0800_0004, InstructionSet::Arm, 0xE1A0_0001, &[]), 6);
+        assert_eq!(step_cycles(0x0800_0004, InstructionSet::Arm, 0xEA00_0000, &[]), 6 + 8 + 6);
+        assert_eq!(step_cycles(0x0800_0002, InstructionSet::Thumb, 0x0088, &[]), 3);
+
+        let mut gba: GBA = GBA::default();
+        // 3,1 for ws0
+        gba.memory_bus.write_u16(0x0400_0204, 0b1_01 << 2);
+        gba.memory_bus.cycle_clock.get_cycles();
+        gba.memory_bus.read_u32(0x0800_0100);
+        gba.memory_bus.read_u32(0x0800_0104);
+        assert_eq!(gba.memory_bus.cycle_clock.get_cycles(), (1 + 3) + (1 + 1) + (1 + 1) + (1 + 1));
+    }
+
+    fn prefetch_gba() -> GBA {
+        let mut gba: GBA = GBA::default();
+        // prefetch on, ws0 at 4 and 2 so a halfword is 5 cycles non sequential and 3 sequential
+        gba.memory_bus.write_u16(0x0400_0204, 0x4000);
+        gba.memory_bus.cycle_clock.get_cycles();
+        gba
+    }
+
+    #[test]
+    fn prefetch_buffer_serves_sequential_fetches() {
+        let mut gba = prefetch_gba();
+        let bus = &mut gba.memory_bus;
+        bus.fetch_u16(0x0800_0000);
+        bus.cycle_clock.internal(6);
+        assert_eq!(bus.cycle_clock.get_cycles(), 5 + 6);
+        assert_eq!(bus.cycle_clock.prefetch.count, 2);
+
+        bus.fetch_u16(0x0800_0002);
+        bus.fetch_u16(0x0800_0004);
+        assert_eq!(bus.cycle_clock.get_cycles(), 2);
+
+        // part of the way through the next halfword
+        bus.fetch_u16(0x0800_0006);
+        assert_eq!(bus.cycle_clock.get_cycles(), 1);
+
+        // arm fetches take two halfwords out
+        bus.cycle_clock.internal(12);
+        bus.cycle_clock.get_cycles();
+        bus.fetch_u32(0x0800_0008);
+        assert_eq!(bus.cycle_clock.get_cycles(), 2);
+
+        bus.cycle_clock.internal(100);
+        assert_eq!(bus.cycle_clock.prefetch.count, 8);
+    }
+
+    #[test]
+    fn prefetch_buffer_flushes() {
+        let mut gba = prefetch_gba();
+        let bus = &mut gba.memory_bus;
+
+        // a branch
+        bus.fetch_u16(0x0800_0000);
+        bus.cycle_clock.internal(6);
+        bus.cycle_clock.get_cycles();
+        bus.fetch_u16(0x0800_0100);
+        assert_eq!(bus.cycle_clock.get_cycles(), 5);
+
+        // data from the cart
+        bus.cycle_clock.internal(6);
+        bus.read_u16(0x0800_0200);
+        bus.cycle_clock.get_cycles();
+        assert!(!bus.cycle_clock.prefetch.active);
+        bus.fetch_u16(0x0800_0102);
+        assert_eq!(bus.cycle_clock.get_cycles(), 5);
+
+        // iwram accesses leave the gamepak bus to the prefetcher
+        bus.read_u32(0x0300_0000);
+        bus.read_u32(0x0300_0000);
+        bus.read_u32(0x0300_0000);
+        assert_eq!(bus.cycle_clock.prefetch.count, 1);
+    }
+
+    #[test]
+    fn prefetch_speeds_up_rom_code() {
+        // nop ; mul r0, r1 ; nop ; nop
+        let rom: Vec<u8> = vec![0xC0, 0x46, 0x48, 0x43, 0xC0, 0x46, 0xC0, 0x46];
+        let run = |prefetch: bool| -> Vec<usize> {
+            let mut gba: GBA = GBA::default();
+            gba.memory_bus.mem_map.write_block(0x0800_0000, &rom);
+            gba.memory_bus.write_u16(0x0400_0204, if prefetch { 0x4000 } else { 0 });
+            gba.cpu.set_instruction_set(InstructionSet::Thumb);
+            gba.cpu.set_register_unsafe(0, 0x1234_5678);
+            gba.cpu.set_register_unsafe(ARM_PC, 0x0800_0000);
+            (0..4).map(|_| gba.cpu.fetch(&mut gba.memory_bus)).collect()
+        };
+        // the multiply's internal cycles buffer the opcode two ahead of the next one
+        assert_eq!(run(false)[1..], [7, 3, 3]);
+        assert_eq!(run(true)[1..], [7, 1, 1]);
+    }
+
+    #[test]
+    fn multiplier_cycles() {
+        assert_eq!(multiply_cycles(0, false), 1);
+        assert_eq!(multiply_cycles(0xFFFF_FFFF, true), 1);
+        assert_eq!(multiply_cycles(0xFFFF_FFFF, false), 4);
+        assert_eq!(multiply_cycles(0x0000_FF00, false), 2);
+        assert_eq!(multiply_cycles(0xFF80_0000, true), 3);
+        assert_eq!(multiply_cycles(0x0100_0000, true), 4);
+    }
 
     #[test]
     fn test_placeholder() {
@@ -2,6 +2,7 @@ use crate::cpu::cpu::CPU;
 use crate::operations::instruction::Instruction;
 use crate::operations::{arm_arithmetic, logical};
 use crate::operations::shift::{Shift, ShiftType, apply_shift};
+use crate::operations::timing::multiply_cycles;
 use std::fmt;
 use crate::memory::memory_bus::MemoryBus;
 
@@ -237,7 +238,13 @@ impl Instruction for ALU {
     fn asm(&self) -> String{
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 1;} // 1s
+    fn cycles(&self, cpu: &CPU) -> u32 {
+        match self.opcode {
+            OpCodes::LSL | OpCodes::LSR | OpCodes::ASR | OpCodes::ROR => 1, // 1S + 1I
+            OpCodes::MUL => multiply_cycles(cpu.get_register(self.rd), true), // 1S + mI, m from the incoming rd
+            _ => 0 // 1S
+        }
+    }
 
 }
 //Unit Tests
@@ -2,7 +2,6 @@ use crate::operations::instruction::Instruction;
 use crate::operations::{arm_arithmetic};
 use crate::cpu::{cpu::CPU, cpu::InstructionSet, cpu::ARM_PC, cpu::THUMB_PC};
 use std::fmt;
-use log::{error};
 use crate::memory::memory_bus::MemoryBus;
 
 #[repr(u8)]
@@ -68,16 +67,7 @@ impl Instruction for HiRegisterOp {
     fn asm(&self) -> String{
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {
-        match self.op {
-            OpCodes::BX => {
-                return 3; //2s + 1n
-            }
-            _ => {
-                return 1; //1s
-            }
-        }
-    } // 1s or 2s + 1n
+    fn cycles(&self, _cpu: &CPU) -> u32 { 0 } // 1S, 2S + 1N for BX or when the pc is written
 
 }
 
@@ -86,29 +76,21 @@ impl HiRegisterOp {
     /// Gets the destination and source register values
     /// returns `(destination, source)`
     fn get_register_vals(&self, cpu: &CPU) -> (u32, u32) {
-        let mut destination: u32;
-        if self.hi_flag_1 {
+        let destination = if self.hi_flag_1 {
             // r8-r15
-            destination = cpu.get_register_unsafe(self.destination_register + 8);
-            if self.destination_register == 7 { // R15 special case
-                destination = destination + 2;  // Fetch adds the other +2
-            }
+            cpu.get_register_unsafe(self.destination_register + 8)
         } else {
             // r0-r7
-            destination = cpu.get_register(self.destination_register);
-        }
+            cpu.get_register(self.destination_register)
+        };
 
-        let mut source: u32;
-        if self.hi_flag_2 {
+        let source = if self.hi_flag_2 {
             // r8-r15
-            source = cpu.get_register_unsafe(self.source_register + 8);
-            if self.source_register == 7 && (self.destination_register != 7 && !self.hi_flag_1) {  // R15 Special case (and nop case)
-                source = source + 2;        // Fetch adds the other +2
-            }
+            cpu.get_register_unsafe(self.source_register + 8)
         } else {
             // r0-r7
-            source = cpu.get_register(self.source_register);
-        }
+            cpu.get_register(self.source_register)
+        };
 
         return (destination, source);
     }
@@ -144,12 +126,8 @@ impl HiRegisterOp {
         self.set_destniation_register(cpu, source);
     }
 
+    // h1 is documented as unpredictable for bx, the arm7tdmi ignores it
     fn bx(&self, cpu: &mut CPU) {
-        if self.hi_flag_1 {
-            error!("Invalid hi flag combination");
-            panic!("Invalid hi flag combination");
-        }
-
         let (_, source) = self.get_register_vals(cpu);
         let mode_bit = (source & 0x1) != 0;
         if mode_bit {
@@ -337,4 +315,18 @@ mod tests {
         assert_eq!(InstructionSet::Thumb, gba.cpu.get_instruction_set());
         assert_eq!(200, gba.cpu.get_register(THUMB_PC));
     }
+
+    #[test]
+    fn bx_ignores_hi_flag_1() {
+        let mut gba: GBA = GBA::default();
+        // bx r0 with h1 set
+        gba.memory_bus.mem_map.write_block(0x0800_0000, &0x4780u16.to_le_bytes().to_vec());
+        gba.cpu.set_instruction_set(InstructionSet::Thumb);
+        gba.cpu.set_register(THUMB_PC, 0x0800_0000);
+        gba.cpu.set_register(0, 0x0800_0101);
+
+        assert!(gba.try_single_step().is_ok());
+        assert_eq!(InstructionSet::Thumb, gba.cpu.get_instruction_set());
+        assert_eq!(gba.cpu.get_pc(), 0x0800_0100);
+    }
 }
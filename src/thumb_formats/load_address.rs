@@ -37,7 +37,7 @@ impl Instruction for LoadAddress {
             let (new, _) = arm_arithmetic::add(sp, self.word8 as u32);
             cpu.set_register(self.destination, new);
         } else {
-            let pc = (cpu.get_register(THUMB_PC) + 2) & !2;    // Fetch handles other + 2
+            let pc = cpu.get_register(THUMB_PC) & !2;
             let (new, _) = arm_arithmetic::add(pc, self.word8 as u32);
             cpu.set_register(self.destination, new);  
         }
@@ -46,7 +46,7 @@ impl Instruction for LoadAddress {
     fn asm(&self) -> String {
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 3;} // 2s + 1n... PC being written
+    fn cycles(&self, _cpu: &CPU) -> u32 { 0 } // 1S
 
 }
 
@@ -25,10 +25,9 @@ impl Instruction for BL {
             // Bottom half of the 23 bit offset (bits 11-1)
             let offset: u32 = self.offset << 1;
             let pc: u32 = cpu.get_register(THUMB_PC);
-            let (lr, _) = arm_arithmetic::add(cpu.get_register(THUMB_LR), offset);
-            let (final_lr, _) = arm_arithmetic::add(lr, 2);
+            let (final_lr, _) = arm_arithmetic::add(cpu.get_register(THUMB_LR), offset);
             cpu.set_register(THUMB_PC, final_lr);
-            cpu.set_register(THUMB_LR, pc + 1); // need to set first bit
+            cpu.set_register(THUMB_LR, (pc - 2) | 1); // the instruction after this one, with the first bit set
         } else {
             // H = 0
             // Top half of the 23 bit offset (bits 23-12)
@@ -43,7 +42,7 @@ impl Instruction for BL {
     fn asm(&self) -> String{
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 4;} // for more info look at 10.3 in dwedit.org/files/arm7tdmi.pdf
+    fn cycles(&self, _cpu: &CPU) -> u32 { 0 } // 1S for the first half, 2S + 1N for the second
 }
 
 impl fmt::Debug for BL {
@@ -63,6 +62,8 @@ mod tests {
     fn branch_long_negative_offset() {
         let mut gba: GBA = GBA::default(); 
         gba.cpu.set_instruction_set(InstructionSet::Thumb);
+        // the pair sits at 0x08000000, the pc reads two instructions ahead of each half
+        gba.cpu.set_register(THUMB_PC, 0x08000004);
 
         // Offset: 11111111111 11111110110 0 = -20
         //          upper 11    lower 11
@@ -77,6 +78,7 @@ mod tests {
         }
 
         // Lower half instruction   0xFFF6
+        gba.cpu.set_register(THUMB_PC, 0x08000006);
         match gba.cpu.decode(0xFFF6) {
             Ok(mut instr) => {
                 (instr.borrow_mut() as &mut dyn Instruction).execute(&mut gba.cpu, &mut gba.memory_bus);
@@ -87,16 +89,18 @@ mod tests {
         }
 
         // PC should be offset by -20
-        assert_eq!(0x08000000 + 2 - 20, gba.cpu.get_register(THUMB_PC));
+        assert_eq!(0x08000004 - 20, gba.cpu.get_register(THUMB_PC));
 
-        // LR should be PC + 2
-        assert_eq!(0x08000000 + 1, gba.cpu.get_register(THUMB_LR));
+        // LR should be the instruction after the pair
+        assert_eq!(0x08000004 + 1, gba.cpu.get_register(THUMB_LR));
     }
 
     #[test]
     fn branch_long_positive_offset() {
         let mut gba: GBA = GBA::default(); 
         gba.cpu.set_instruction_set(InstructionSet::Thumb);
+        // the pair sits at 0x08000000, the pc reads two instructions ahead of each half
+        gba.cpu.set_register(THUMB_PC, 0x08000004);
 
         // Offset: 00000000000 00000001010 0 = 20
         //          upper 11    lower 11
@@ -111,6 +115,7 @@ mod tests {
         }
 
         // Lower half instruction   0xF80A
+        gba.cpu.set_register(THUMB_PC, 0x08000006);
         match gba.cpu.decode(0xF80A) {
             Ok(mut instr) => {
                 (instr.borrow_mut() as &mut dyn Instruction).execute(&mut gba.cpu, &mut gba.memory_bus);
@@ -120,10 +125,10 @@ mod tests {
             }
         }
 
-        // PC should be offset by -20
-        assert_eq!(0x08000000 + 2 + 20, gba.cpu.get_register(THUMB_PC));
+        // PC should be offset by 20
+        assert_eq!(0x08000004 + 20, gba.cpu.get_register(THUMB_PC));
 
-        // LR should be PC + 4
-        assert_eq!(0x08000000 + 1, gba.cpu.get_register(THUMB_LR));
+        // LR should be the instruction after the pair
+        assert_eq!(0x08000004 + 1, gba.cpu.get_register(THUMB_LR));
     }
 }
\ No newline at end of file
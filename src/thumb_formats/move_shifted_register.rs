@@ -49,7 +49,7 @@ impl Instruction for MoveShifted {
     fn asm(&self) -> String {
         return format!("MOVS r{}, r{}, {:?}", self.rd, self.rs, self.shift);
     }
-    fn cycles(&self) -> u32 {return 1;} // 1s
+    fn cycles(&self, _cpu: &CPU) -> u32 { 0 } // 1S
 
 }
 
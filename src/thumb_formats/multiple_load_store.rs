@@ -46,7 +46,7 @@ impl Instruction for MultipleLoadStore {
             }
         } else {
             if self.register_list.len() == 0 {
-                mem_bus.write_u32(base, cpu.get_register(THUMB_PC) + 4);
+                mem_bus.write_u32(base, cpu.get_register(THUMB_PC) + 2);
                 cpu.set_register(self.rb, base + 0x40);
             } else {
                 for reg_num in self.register_list.iter() {
@@ -73,8 +73,7 @@ impl Instruction for MultipleLoadStore {
     fn asm(&self) -> String{
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 3;} // Normal LDM instructions take nS + 1N + 1I and LDM PC takes (n+1)S + 2N + 1I
-    //STM instructions take (n-1)S + 2N incremental cycles to execute, where n is the number of words transferred.
+    fn cycles(&self, _cpu: &CPU) -> u32 { if self.load {1} else {0} } // LDMIA nS + 1N + 1I, STMIA (n-1)S + 2N
 
 }
 
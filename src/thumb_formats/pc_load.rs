@@ -26,8 +26,7 @@ impl fmt::Debug for LDR {
 
 impl Instruction for LDR {
     fn execute(&self, cpu: &mut CPU, mem_bus: &mut MemoryBus) -> u32 {
-        let mut current_pc = cpu.get_register(THUMB_PC) + 2; // another +2 in
-        current_pc &= !0x02;
+        let current_pc = cpu.get_register(THUMB_PC) & !0x02;
         let value = mem_bus.read_u32(current_pc + (self.offset as u32));
         cpu.set_register(self.destination, value);
         mem_bus.cycle_clock.get_cycles()
@@ -36,7 +35,7 @@ impl Instruction for LDR {
     fn asm(&self) -> String {
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 3;} // 1s + 1n + 1l
+    fn cycles(&self, _cpu: &CPU) -> u32 { 1 } // 1S + 1N + 1I
 
 }
 
@@ -66,7 +65,7 @@ mod tests {
         gba.cpu.set_instruction_set(InstructionSet::Thumb);
 
         gba.cpu.set_register(THUMB_PC, 0x08000000);
-        gba.memory_bus.mem_map.write_u32(0x08000000 + 40, 2000);
+        gba.memory_bus.mem_map.write_block(0x08000000 + 40, &2000u32.to_le_bytes().to_vec());
 
         // RD = r1, offset = 20
         let decode_result = gba.cpu.decode(0x490A);
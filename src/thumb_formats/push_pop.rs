@@ -77,7 +77,7 @@ impl Instruction for PushPop {
     fn asm(&self) -> String{
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 3;} //nS + 1N + 1I
+    fn cycles(&self, _cpu: &CPU) -> u32 { if self.load {1} else {0} } // POP nS + 1N + 1I, PUSH (n-1)S + 2N
 }
 
 impl fmt::Debug for PushPop {
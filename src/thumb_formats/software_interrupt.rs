@@ -1,7 +1,8 @@
 use crate::operations::instruction::Instruction;
-use crate::cpu::{cpu::CPU,  cpu::InstructionSet, cpu::OperatingMode, cpu::THUMB_PC, cpu::ARM_PC, cpu::ARM_LR};
+use crate::cpu::{cpu::CPU, cpu::Exception};
 use std::fmt;
 use crate::memory::memory_bus::MemoryBus;
+use crate::bios::hle;
 
 pub struct ThumbSoftwareInterrupt {
     pub comment_immediate: u8
@@ -17,21 +18,18 @@ impl From<u16> for ThumbSoftwareInterrupt {
 
 impl Instruction for ThumbSoftwareInterrupt {
     fn execute(&self, cpu: &mut CPU, _mem_bus: &mut MemoryBus) -> u32{
-        let old_cpsr = cpu.cpsr;
-        let current_pc = cpu.get_register(THUMB_PC);
-        cpu.set_instruction_set(InstructionSet::Arm);
-        cpu.set_operating_mode(OperatingMode::Supervisor);
-        cpu.cpsr.control_bits.irq_disable = true;
-        cpu.set_spsr(old_cpsr);
-        cpu.set_register(ARM_LR, current_pc);      
-        cpu.set_register(ARM_PC, 0x08);
+        if cpu.hle_bios && hle::handle_swi(self.comment_immediate, cpu, _mem_bus) {
+            return _mem_bus.cycle_clock.get_cycles();
+        }
+
+        cpu.take_exception(Exception::SoftwareInterrupt);
         _mem_bus.cycle_clock.get_cycles()
     }
 
     fn asm(&self) -> String{
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 2;} // Coprocessor data operations take 1S + bI incremental cycles to execute, where b is the number of cycles spent in the coprocessor busy-wait loop.
+    fn cycles(&self, _cpu: &CPU) -> u32 { 0 } // 2S + 1N
 
 }
 
@@ -45,7 +43,7 @@ impl fmt::Debug for ThumbSoftwareInterrupt {
 mod tests {
     use super::*;
     use crate::gba::GBA;
-    use crate::cpu::{cpu::InstructionSet, cpu::THUMB_PC, cpu::ARM_PC, cpu::ARM_LR};
+    use crate::cpu::{cpu::InstructionSet, cpu::OperatingMode, cpu::THUMB_PC, cpu::ARM_PC, cpu::ARM_LR};
     use std::borrow::{BorrowMut};
 
     #[test]
@@ -54,6 +52,7 @@ mod tests {
         gba.cpu.set_instruction_set(InstructionSet::Thumb);
         gba.cpu.set_operating_mode(OperatingMode::Supervisor);
 
+        // executing at 20, the pc reads two instructions ahead
         gba.cpu.set_register(THUMB_PC, 24);
 
         let decode_result = gba.cpu.decode(0xDF00);
@@ -69,6 +68,6 @@ mod tests {
         assert_eq!(InstructionSet::Arm, gba.cpu.get_instruction_set());
         assert_eq!(OperatingMode::Supervisor, gba.cpu.get_operating_mode());
         assert_eq!(0x8, gba.cpu.get_register(ARM_PC));
-        assert_eq!(24, gba.cpu.get_register(ARM_LR));
+        assert_eq!(22, gba.cpu.get_register(ARM_LR));
     }
 }
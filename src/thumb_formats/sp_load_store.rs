@@ -42,7 +42,7 @@ impl Instruction for SpLoadStore {
     fn asm(&self) -> String{
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 3;} // 1s + 1n + 1l
+    fn cycles(&self, _cpu: &CPU) -> u32 { if self.load {1} else {0} } // LDR 1S + 1N + 1I, STR 2N
 
 }
 
@@ -19,8 +19,7 @@ impl From<u16> for UnconditionalBranch {
 impl Instruction for UnconditionalBranch {
     fn execute(&self, cpu: &mut CPU, _mem_bus: &mut MemoryBus) -> u32 {
         // execute
-        let (signed_offset, _) = arm_arithmetic::add(self.offset, 2);
-        let (new_pc, _) = arm_arithmetic::add(cpu.get_register(THUMB_PC), signed_offset);
+        let (new_pc, _) = arm_arithmetic::add(cpu.get_register(THUMB_PC), self.offset);
         cpu.set_register(THUMB_PC, new_pc);
         _mem_bus.cycle_clock.get_cycles()
     }
@@ -28,7 +27,7 @@ impl Instruction for UnconditionalBranch {
     fn asm(&self) -> String{
         return format!("{:?}", self);
     }
-    fn cycles(&self) -> u32 {return 3;} // 2s + 1n
+    fn cycles(&self, _cpu: &CPU) -> u32 { 0 } // 2S + 1N
 
 }
 
@@ -49,6 +48,8 @@ mod tests {
     fn branch_unconditional_negative_offset() {
         let mut gba: GBA = GBA::default(); 
         gba.cpu.set_instruction_set(InstructionSet::Thumb);
+        // executing at 0x08000000, the pc reads two instructions ahead
+        gba.cpu.set_register(THUMB_PC, 0x08000004);
 
         let decode_result = gba.cpu.decode(0xE7F6);
         match decode_result {
@@ -60,13 +61,15 @@ mod tests {
             }
         }
 
-        assert_eq!(0x08000000 + 2 - 20, gba.cpu.get_register(THUMB_PC));
+        assert_eq!(0x08000004 - 20, gba.cpu.get_register(THUMB_PC));
     }
 
     #[test]
     fn branch_unconditional_positive_offset() {
         let mut gba: GBA = GBA::default(); 
         gba.cpu.set_instruction_set(InstructionSet::Thumb);
+        // executing at 0x08000000, the pc reads two instructions ahead
+        gba.cpu.set_register(THUMB_PC, 0x08000004);
 
         let decode_result = gba.cpu.decode(0xE00A);
         match decode_result {
@@ -78,6 +81,6 @@ mod tests {
             }
         }
 
-        assert_eq!(0x08000000 + 2 + 20, gba.cpu.get_register(THUMB_PC));
+        assert_eq!(0x08000004 + 20, gba.cpu.get_register(THUMB_PC));
     }
 }
\ No newline at end of file
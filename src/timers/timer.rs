@@ -1,4 +1,4 @@
-use crate::memory::timer_registers::*;
+use crate::memory::{timer_registers::*, GbaMem};
 use crate::interrupts::interrupts::Interrupts;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -14,7 +14,7 @@ pub struct Timer {
 }
 
 impl Timer {
-    pub fn register(&mut self, mem: &Rc<RefCell<Vec<u8>>>) {
+    pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>) {
         self.timer.register(mem);
         self.controller.register(mem);
     }
@@ -59,6 +59,16 @@ impl Timer {
         overflows
     }
 
+    // cascade timers only count when the timer before them overflows, so they never come first
+    pub fn cycles_to_overflow(&self) -> Option<usize> {
+        if self.controller.get_enable() == 0 || self.controller.get_cascade() == 1 {
+            return None;
+        }
+
+        let data = if self.previously_disabled { self.timer.get_reload() } else { self.timer.get_data() };
+        Some(((0x10000 - data as usize) * self.frequency()).saturating_sub(self.cycles))
+    }
+
     pub fn update_overflow(&mut self, overflows: usize, irq_ctrl: &mut Interrupts) -> usize {
         let mut timer_data = self.timer.get_data();
         let mut new_overflows = 0;
@@ -128,14 +138,16 @@ impl TimerHandler {
     }
 
 
-    pub fn register(&mut self, mem: &Rc<RefCell<Vec<u8>>>){
+    pub fn register(&mut self, mem: &Rc<RefCell<GbaMem>>){
         for i in 0..4 {
             self.timers[i].register(mem);
         }
     }
 
-    pub fn update(&mut self, cycles: usize, irq_ctrl: &mut Interrupts){
+    // returns how many times each timer overflowed, the direct sound fifos are clocked by these
+    pub fn update(&mut self, cycles: usize, irq_ctrl: &mut Interrupts) -> [usize; 4] {
         let mut overflows = 0usize;
+        let mut timer_overflows = [0usize; 4];
         for id in 0..4 {
             let mut timer = &mut self.timers[id];
             if timer.controller.get_enable() == 1 {
@@ -153,9 +165,16 @@ impl TimerHandler {
                         overflows = timer.update_overflow(overflows, irq_ctrl);
                     }
                 }
+                timer_overflows[id] = overflows;
             } else if !self.timers[id].previously_disabled {
                 self.timers[id].previously_disabled = true;
             }
         }
+
+        timer_overflows
+    }
+
+    pub fn cycles_to_next_overflow(&self) -> Option<usize> {
+        self.timers.iter().filter_map(|timer| timer.cycles_to_overflow()).min()
     }
 }
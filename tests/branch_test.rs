@@ -15,7 +15,7 @@ mod tests {
         let mut cpu = CPU::new();
         let mut bus = MemoryBus::new_stub();
         let current_pc = if cpu.get_instruction_set() == InstructionSet::Arm { ARM_PC } else { THUMB_PC };
-        cpu.set_register(current_pc, 0x105C8);
+        cpu.set_register(current_pc, 0x105CC);
         a.execute(&mut cpu,&mut bus);
         let reg = cpu.get_register(current_pc);
         assert_eq!(reg, 0x10584);
@@ -27,7 +27,7 @@ mod tests {
         let mut cpu = CPU::new();
         let mut bus = MemoryBus::new_stub();
         let current_pc = if cpu.get_instruction_set() == InstructionSet::Arm { ARM_PC } else { THUMB_PC };
-        cpu.set_register(current_pc, 0x10590);
+        cpu.set_register(current_pc, 0x10594);
         a.execute(&mut cpu,&mut bus);
         let reg = cpu.get_register(current_pc);
         assert_eq!(reg, 0x105A0);
@@ -39,7 +39,7 @@ mod tests {
         let mut cpu = CPU::new();
         let mut bus = MemoryBus::new_stub();
         let current_pc = if cpu.get_instruction_set() == InstructionSet::Arm { ARM_PC } else { THUMB_PC };
-        cpu.set_register(current_pc, 0x105C8);
+        cpu.set_register(current_pc, 0x105CC);
         a.execute(&mut cpu,&mut bus);
         let reg = cpu.get_register(current_pc);
         assert_eq!(reg, 0x10584);
@@ -52,7 +52,7 @@ mod tests {
         let mut cpu = CPU::new();
         let mut bus = MemoryBus::new_stub();
         let current_pc = if cpu.get_instruction_set() == InstructionSet::Arm { ARM_PC } else { THUMB_PC };
-        cpu.set_register(current_pc, 0x10590);
+        cpu.set_register(current_pc, 0x10594);
         a.execute(&mut cpu,&mut bus);
         let reg = cpu.get_register(current_pc);
         assert_eq!(reg, 0x105a0);